@@ -13,6 +13,7 @@ import "nouns/NounsSeeder.sol";
 import "../src/ZKRegistry.sol";
 import "../src/INoirVerifier.sol";
 import "../src/HashVerifier.sol";
+import "../src/HeaderChainVerifier.sol";
 import "../src/VoteVerifier.sol";
 import "../src/TallyVerifier.sol";
 import "../src/Poseidon.sol";
@@ -56,6 +57,7 @@ contract NounsVotingDeployScript is Script {
 
         ZKRegistry zkRegistry = new ZKRegistry();
 	INoirVerifier noirHashVerifier = new HashVerifier();
+        INoirVerifier noirHeaderChainVerifier = new HeaderChainVerifier();
         INoirVerifier noirVoteVerifier = new VoteVerifier();
         INoirVerifier noirTallyVerifier = new TallyVerifier();
         PoseidonFactory poseidonFactory = new PoseidonFactory();
@@ -64,6 +66,7 @@ contract NounsVotingDeployScript is Script {
             token,
             zkRegistry,
 	    noirHashVerifier,
+            noirHeaderChainVerifier,
             noirVoteVerifier,
             noirTallyVerifier,
             poseidonFactory.poseidon2()
@@ -12,6 +12,7 @@ import "nouns/NounsSeeder.sol";
 
 import "../src/ZKRegistry.sol";
 import "../src/INoirVerifier.sol";
+import "../src/HeaderChainVerifier.sol";
 import "../src/Poseidon.sol";
 import "../src/NounsVoting.sol";
 import "nouns-monorepo/packages/nouns-contracts/contracts/NounsDescriptorV2.sol";
@@ -48,6 +49,8 @@ contract NounsMainnetVotingDeployScript is Script {
         ZKRegistry zkRegistry = new ZKRegistry();
 	// TODO
 	INoirVerifier noirHashVerifier = new YesManNoirVerifier();
+        // Recorded block hashes anchor censuses, so they are never accepted without a sound proof
+        INoirVerifier noirHeaderChainVerifier = new HeaderChainVerifier();
         // TODO - Deploy Correct Noir Vote Verifier
         INoirVerifier noirVoteVerifier = new YesManNoirVerifier();
         // TODO - Deploy Correct Noir Tally Verifier
@@ -58,6 +61,7 @@ contract NounsMainnetVotingDeployScript is Script {
             token,
             zkRegistry,
 	    noirHashVerifier,
+            noirHeaderChainVerifier,
             noirVoteVerifier,
             noirTallyVerifier,
             poseidonFactory.poseidon2()
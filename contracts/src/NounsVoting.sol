@@ -61,6 +61,12 @@ contract NounsVoting {
     /// This is emitted when a voter submits a vote
     event BallotCast(uint256 processId, uint256 indexed a_x, uint256 indexed a_y, uint256 indexed b);
 
+    /// This is emitted when a voting process is created
+    event ProcessCreated(uint256 indexed processId, uint64 censusBlock, uint64 startBlock, uint64 endBlock);
+
+    /// This is emitted when the tally of a voting process is submitted
+    event TallySubmitted(uint256 indexed processId, uint256 votesFor, uint256 votesAgainst, uint256 votesAbstain);
+
     bytes32[] public_args;
 
     /// The address of the NounsToken contract
@@ -83,10 +89,17 @@ contract NounsVoting {
     // The id of the next voting process
     uint256 public nextProcessId = 0;
 
+    /// The Noir header chain verifier contract
+    INoirVerifier private headerChainVerifier;
+
+    /// Block hashes older than 256 blocks proven through header chain proofs
+    mapping(uint64 => bytes32) public historicalBlockHashes;
+
     constructor(
         NounsToken _nounsToken,
         ZKRegistry _zkRegistry,
 	INoirVerifier _hashVerifier,
+        INoirVerifier _headerChainVerifier,
         INoirVerifier _voteVerifier,
         INoirVerifier _tallyVerifier,
         Poseidon2 _poseidon
@@ -96,17 +109,50 @@ contract NounsVoting {
         zkRegistry = _zkRegistry;
 
 	hashVerifier = _hashVerifier;
+        headerChainVerifier = _headerChainVerifier;
         voteVerifier = _voteVerifier;
         tallyVerifier = _tallyVerifier;
 
         poseidon2 = _poseidon;
     }
 
+    /// @notice Records the hash of a block older than 256 blocks so that it may be used as a census block
+    /// @param blockNumber The number of a block whose hash is available, either through `blockhash` or a previous record
+    /// @param ancestorHash The hash of the block `numHeaders` blocks before `blockNumber`
+    /// @param numHeaders The number of headers linking the two blocks
+    /// @param proof The header chain proof
+    function recordBlockHash(
+        uint64 blockNumber,
+        bytes32 ancestorHash,
+        uint64 numHeaders,
+        bytes calldata proof
+    ) public {
+        _recordBlockHash(blockNumber, ancestorHash, numHeaders, proof);
+    }
+
+    /// @notice Records the hashes of a chain of ancestors in one transaction, each step anchored at the block recorded by the previous one
+    /// @param blockNumber The number of the block the first step is anchored at
+    /// @param ancestorHashes The hashes of the ancestors recorded by each step
+    /// @param numHeaders The number of headers covered by each step
+    /// @param proofs The header chain proofs of each step
+    function recordBlockHashes(
+        uint64 blockNumber,
+        bytes32[] calldata ancestorHashes,
+        uint64[] calldata numHeaders,
+        bytes[] calldata proofs
+    ) public {
+        require(ancestorHashes.length == numHeaders.length && numHeaders.length == proofs.length, "Error: Mismatched steps");
+
+        for (uint256 i = 0; i < proofs.length; i++) {
+            blockNumber = _recordBlockHash(blockNumber, ancestorHashes[i], numHeaders[i], proofs[i]);
+        }
+    }
+
     /// @notice This function is called to generate a new voting process
     /// @param blockDuration The number of blocks that the voting process will last
     /// @param tlcsPublicKey The public key of the TLCS service that encrypts the votes to the point in the future. We use the BabyJubJub curve for public/private key encryption, represented in Affine coordinates {x, y}. We trust that the voter will cross-check the public key with the one published by the TLCS service.
     /// @dev The storage roots should be for the same block
-    /// @notice To make the voting process secure, instead of using the storage roots directly, we should use the block hash obtained inside the contract. This will be done in a future version.
+    /// @dev Census blocks older than 256 blocks must first be recorded with `recordBlockHash`
     /// @return The id of the voting process
     function createProcess(
 			   bytes32 ipfsHash,
@@ -114,13 +160,13 @@ contract NounsVoting {
 			   uint64 blockDuration,
 			   uint64 tlcsRoundNumber,
 			   uint256[2] calldata tlcsPublicKey,
-			   uint64 census_block_number, // TODO: Should be < 256 blocks in the past
+			   uint64 census_block_number,
 			   bytes32 registry_storage_root,
 			   bytes32 nft_storage_root,
 			   bytes calldata hash_proof
     ) public returns (uint256) {
 
-	bytes32 census_block_hash = blockhash(census_block_number); // This will be zero if we're out of range, but then the proof will fail to pass.
+	bytes32 census_block_hash = _getBlockHash(census_block_number); // This will be zero if unknown, but then the proof will fail to pass.
 
 	// Form verifier argument
 	_push_u256(census_block_hash);
@@ -205,12 +251,20 @@ contract NounsVoting {
             action: action
         });
 
+        _emitProcessCreated(nextProcessId);
+
         // Increase the voteId for the next voting process
 	nextProcessId++;
 	
         return nextProcessId;
     }
 
+    /// @dev Reads the event fields from storage to keep the stack of the caller shallow
+    function _emitProcessCreated(uint256 processId) internal {
+        VotingProcess storage process = votingProcesses[processId];
+        emit ProcessCreated(processId, process.censusBlock, process.startBlock, process.endBlock);
+    }
+
     /// @notice This function is called by voter to submit their vote
     /// @param processId The id of the voting process
     /// @param a The first part of the encrypted vote
@@ -311,6 +365,8 @@ contract NounsVoting {
         process.votesAbstain = votesAbstain;
         process.tallied = true;
 
+        emit TallySubmitted(processId, votesFor, votesAgainst, votesAbstain);
+
         // If the voting process was successful, execute the action
         if (votesFor > votesAgainst && process.action.target != address(0)) {
 
@@ -383,6 +439,48 @@ contract NounsVoting {
             ];
     }
 
+    /// @notice Checks a header chain proof from a known block hash and records the hash of the ancestor
+    /// @return The number of the recorded ancestor
+    function _recordBlockHash(
+        uint64 blockNumber,
+        bytes32 ancestorHash,
+        uint64 numHeaders,
+        bytes calldata proof
+    ) internal returns (uint64) {
+        require(numHeaders > 0 && numHeaders <= blockNumber, "Error: Invalid number of headers");
+
+        bytes32 blockHash = _getBlockHash(blockNumber);
+        require(blockHash != bytes32(0), "Error: Unknown block hash");
+
+	_push_u256(blockHash);
+	_push_u256(ancestorHash);
+	_push_uint256(uint256(numHeaders));
+
+	require(headerChainVerifier.verify(proof, public_args), "Error: Invalid proof");
+
+	// free
+	delete public_args;
+
+        // Recorded block hashes are census anchors and must never change
+        uint64 ancestorNumber = blockNumber - numHeaders;
+        bytes32 recordedHash = historicalBlockHashes[ancestorNumber];
+        require(recordedHash == bytes32(0) || recordedHash == ancestorHash, "Error: Block hash already recorded");
+        historicalBlockHashes[ancestorNumber] = ancestorHash;
+
+        return ancestorNumber;
+    }
+
+    /// @notice Returns the hash of a block within the last 256 blocks or recorded through `recordBlockHash`, zero otherwise
+    function _getBlockHash(uint64 blockNumber) internal view returns (bytes32) {
+        bytes32 blockHash = blockhash(blockNumber);
+
+        if (blockHash == bytes32(0)) {
+            blockHash = historicalBlockHashes[blockNumber];
+        }
+
+        return blockHash;
+    }
+
     /// @notice This function is used to abstract a call to the Noir Vote Verifier contract
     function _verifyVote(
         uint256 processId,
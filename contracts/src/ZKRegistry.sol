@@ -16,6 +16,7 @@ contract ZKRegistry {
     uint8 public BLS12PK_X_INTERFACE_ID = 0xc2; // first byte of keccak("BLS12PK")
     uint8 public BLS12PK_Y_INTERFACE_ID = 0xc3; // first byte of keccak("BLS12PK") + 1
 
+    /// Poseidon commitment Poseidon(BBJJPK.x, BBJJPK.y), which is what voters register
     uint8 public POSEIDON_INTERFACE_ID = 0xbf; // first byte of keccak("POSEIDON")
 
     /// Register a value for a given interface id
@@ -23,6 +24,17 @@ contract ZKRegistry {
         registry[interface_id][msg.sender] = value;
     }
 
+    /// Emitted with a value registered along with a proof for it
+    event Registered(uint8 indexed interface_id, address indexed addr, uint256 value, bytes proof);
+
+    /// Register a value for a given interface id along with a proof for it,
+    /// e.g. a proof of possession of the key a commitment opens to.
+    /// The proof is not checked here, but published for anyone to check off-chain
+    function registerWithProof(uint8 interface_id, uint256 value, bytes calldata proof) public {
+        registry[interface_id][msg.sender] = value;
+        emit Registered(interface_id, msg.sender, value, proof);
+    }
+
     /// De-register a value for a given interface id
     function deregister(uint8 interface_id) public {
         delete registry[interface_id][msg.sender];
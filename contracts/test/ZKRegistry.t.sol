@@ -11,6 +11,7 @@ contract ZKRegistryTest is Test {
     ZKRegistry public zkRegistry;
     NounsVoting public nounsVoting;
     INoirVerifier public hashVerifier;
+    INoirVerifier public headerChainVerifier;
     INoirVerifier public voteVerifier;
     INoirVerifier public tallyVerifier;
     Poseidon2 public poseidon2;
@@ -19,6 +20,7 @@ contract ZKRegistryTest is Test {
     function setUp() public {
         zkRegistry = new ZKRegistry();
 	hashVerifier = new YesManNoirVerifier();
+	headerChainVerifier = new YesManNoirVerifier();
 	voteVerifier = new YesManNoirVerifier();
 	tallyVerifier = new YesManNoirVerifier();
 	PoseidonFactory poseidonFactory = new PoseidonFactory();
@@ -27,7 +29,7 @@ contract ZKRegistryTest is Test {
 	NounsSeeder nounsSeeder = new NounsSeeder();
 	DummyProxyRegistry dummyProxy = new DummyProxyRegistry();
 	nounsToken = new NounsToken(address(0x0), address(0x0), nounsDescriptor, nounsSeeder, dummyProxy);
-	nounsVoting = new NounsVoting(nounsToken, zkRegistry, hashVerifier, voteVerifier, tallyVerifier, poseidon2);
+	nounsVoting = new NounsVoting(nounsToken, zkRegistry, hashVerifier, headerChainVerifier, voteVerifier, tallyVerifier, poseidon2);
     }
 
     function testRegister() public {
@@ -36,6 +38,16 @@ contract ZKRegistryTest is Test {
         assertEq(zkRegistry.get(interface_id, address(this)), 0x1234);
     }
 
+    event Registered(uint8 indexed interface_id, address indexed addr, uint256 value, bytes proof);
+
+    function testRegisterWithProof() public {
+        uint8 interface_id = zkRegistry.POSEIDON_INTERFACE_ID();
+        vm.expectEmit(true, true, false, true);
+        emit Registered(interface_id, address(this), 0x1234, hex"5678");
+        zkRegistry.registerWithProof(interface_id, 0x1234, hex"5678");
+        assertEq(zkRegistry.get(interface_id, address(this)), 0x1234);
+    }
+
     function testDeregister() public {
         uint8 interface_id = zkRegistry.POSEIDON_INTERFACE_ID();
         zkRegistry.register(interface_id, 0x1234);
@@ -1,7 +1,9 @@
-use nouns_protocol::{wrap, wrap_into, BN254_Fr, PrivateKey, Wrapper};
+use ethers::core::rand::thread_rng;
+use nouns_protocol::keys::random_private_key;
+use nouns_protocol::{wrap, wrap_into, BN254_Fr, Wrapper};
 
 fn main() {
-    let tlcs_prk = PrivateKey::import(vec![1u8; 32]).unwrap();
+    let tlcs_prk = random_private_key(&mut thread_rng());
     let tlcs_pubk = tlcs_prk.public();
 
     let tlcs_pubk_s: [ethers::core::k256::U256; 2] = wrap_into!(tlcs_pubk);
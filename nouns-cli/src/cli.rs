@@ -1,13 +1,20 @@
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::time::Duration;
 
-use clap::{command, Arg, Command};
+use clap::{command, Arg, ArgAction, ArgMatches, Command};
 use ethers::abi::Address;
 use ethers::core::k256::U256;
-use ethers::types::H256;
+use ethers::prelude::Signer;
+use ethers::types::{H256, U64};
 
-use nouns_protocol::{BBJJ_Ec, BBJJ_Fr, PrivateKey, VoteChoice};
+use nouns_protocol::{BBJJ_Ec, BBJJ_Fr, SecretKey, VoteChoice};
 
+use crate::ethereum::contract_interactions::ProcessPhase;
+use crate::ethereum::layout::TokenStorageLayout;
+use crate::ethereum::safe::{SafeOutput, SafeParams};
+use crate::ethereum::signer::{TxSigner, DEFAULT_DERIVATION_PATH};
+use crate::keystore;
 use crate::parsers::{
     parse_bbjj_prk, parse_duration, parse_ipfs_hash, parse_private_key, parse_tlcs_pbk, parse_u256,
 };
@@ -15,61 +22,279 @@ use crate::parsers::{
 /// The global parameters of the CLI
 pub struct GlobalCliParams {
     pub contract_address: Address,
-    pub rpc_url: String,
-    pub tx_private_key: [u8; 32],
+    /// RPC URLs in order of preference, tried in turn when one fails
+    pub rpc_urls: Vec<String>,
+    /// The RPC URLs of the same chain that historical state and storage proofs are read from, which default to `rpc_urls`
+    pub state_rpc_urls: Vec<String>,
+    /// The signer of the transactions, only loaded for the commands that need it
+    pub tx_signer: Option<TxSigner>,
+    pub token_layout: TokenStorageLayout,
+    /// The SQLite index of the NounsVoting events, read instead of the chain when given
+    pub index_path: Option<PathBuf>,
+    /// The Safe that the transactions are prepared for, instead of being sent from the transaction private key
+    pub safe: Option<SafeParams>,
 }
 
 /// The CLI commands that the user can choose from
 /// Parameters are passed as arguments and the command is executed
 pub enum CliCommand {
-    RegKey(PrivateKey),
-    CreateProcess(H256, Duration, Duration),
-    Vote(Option<Address>, U256, U256, PrivateKey, VoteChoice),
+    RegKey(SecretKey),
+    CreateProcess(H256, Duration, Duration, Option<U64>),
+    Vote(
+        Option<Address>,
+        U256,
+        U256,
+        SecretKey,
+        VoteChoice,
+        PathBuf,
+        bool,
+    ),
     Tally(U256),
+    FindSlot(Option<Address>, Option<U256>, Option<Address>, u64),
+    Index(U64),
+    Status(U256),
+    ListProcesses(Vec<ProcessPhase>, bool),
+    Audit(U256),
+    CheckVote(PathBuf, SecretKey),
+    RegistryStatus(Address, Option<BBJJ_Ec>, Option<H256>),
+    RegistryRotate(SecretKey),
+    RegistryDeregister,
+    Delegate(Address),
+    VerifySpoiledBallot(PathBuf, Option<U256>),
     None, // No command was chosen
 }
 
-pub fn get_user_input() -> Result<(GlobalCliParams, CliCommand), String> {
+/// The `key` commands, which manage the keystore of registry keys
+pub enum KeyCommand {
+    New(String, Option<u32>),
+    Recover(String, u32),
+    Import(String, Option<SecretKey>),
+    ExportPublic(String),
+    List,
+}
+
+/// The input of the user: either a command on the NounsVoting contract along with the global parameters,
+/// or a `key` command, which needs neither of them
+pub enum UserInput {
+    Contract(Box<GlobalCliParams>, CliCommand),
+    Key(PathBuf, KeyCommand),
+}
+
+pub fn get_user_input() -> Result<UserInput, String> {
     let command = command_constructor();
     let matches = command.get_matches();
+    let global_matches = &matches;
+
+    let keystore_dir: &String = matches
+        .get_one("keystore-dir")
+        .ok_or("Missing keystore directory")?;
+    let keystore_dir = PathBuf::from(keystore_dir);
+
+    // Parse the command `key`
+    if let Some(key_matches) = matches.subcommand_matches("key") {
+        let key_command = match key_matches.subcommand() {
+            Some(("new", matches)) => {
+                let index = if matches.get_flag("mnemonic") {
+                    Some(get_key_index(matches)?)
+                } else {
+                    None
+                };
+                KeyCommand::New(get_key_name(matches)?, index)
+            }
+            Some(("recover", matches)) => {
+                KeyCommand::Recover(get_key_name(matches)?, get_key_index(matches)?)
+            }
+            Some(("import", matches)) => {
+                let private_key: Option<&String> = matches.get_one("reg-private-key");
+                let private_key = if matches.get_flag("derive") {
+                    Some(derive_registry_key(&get_tx_signer(global_matches)?)?)
+                } else {
+                    private_key
+                        .map(|private_key| {
+                            SecretKey::import(parse_private_key(private_key)?.as_slice())
+                                .map_err(|e| format!("Invalid private key: {}", e))
+                        })
+                        .transpose()?
+                };
+
+                KeyCommand::Import(get_key_name(matches)?, private_key)
+            }
+            Some(("export-public", matches)) => KeyCommand::ExportPublic(get_key_name(matches)?),
+            Some(("list", _)) => KeyCommand::List,
+            _ => return Err("Missing key command".to_string()),
+        };
 
+        return Ok(UserInput::Key(keystore_dir, key_command));
+    }
+
+    let (global_cli_param, cli_command) = get_contract_command(&matches, &keystore_dir)?;
+    Ok(UserInput::Contract(Box::new(global_cli_param), cli_command))
+}
+
+/// Parses the global parameters and the command on the NounsVoting contract
+fn get_contract_command(
+    matches: &ArgMatches,
+    keystore_dir: &Path,
+) -> Result<(GlobalCliParams, CliCommand), String> {
     // Parse the global parameters
     let global_cli_param = {
         let contract_address: &String = matches
             .get_one("contract-address")
             .ok_or("Missing contract address")?;
-        let rpc_url: &String = matches.get_one("rpc-url").ok_or("Missing RPC URL")?;
-        let tx_private_key: &String = matches
-            .get_one("tx-private-key")
-            .ok_or("Missing transaction private key")?;
+        let rpc_urls: Vec<String> = matches
+            .get_many("rpc-url")
+            .ok_or("Missing RPC URL")?
+            .cloned()
+            .collect();
+        let state_rpc_urls: Vec<String> = match matches.get_many("state-rpc-url") {
+            Some(state_rpc_urls) => state_rpc_urls.cloned().collect(),
+            None => rpc_urls.clone(),
+        };
 
         let contract_address = Address::from_str(contract_address)
             .map_err(|e| format!("Invalid contract address: {}", e))?;
 
-        let tx_private_key = parse_private_key(tx_private_key)?;
+        let token_layout = match matches.get_one::<String>("token-layout-file") {
+            Some(path) => TokenStorageLayout::from_file(Path::new(path))?,
+            None => TokenStorageLayout::preset(
+                matches
+                    .get_one::<String>("token-layout")
+                    .ok_or("Missing token storage layout")?,
+            )?,
+        };
+
+        let index_path = matches.get_one::<String>("index-db").map(PathBuf::from);
+
+        let safe = matches
+            .get_one::<String>("safe")
+            .map(|address| -> Result<SafeParams, String> {
+                let address = Address::from_str(address)
+                    .map_err(|e| format!("Invalid Safe address: {}", e))?;
+                let output = if matches.get_flag("safe-tx-hash") {
+                    SafeOutput::SafeTxHash
+                } else {
+                    SafeOutput::TransactionBuilder(PathBuf::from(
+                        matches
+                            .get_one::<String>("safe-output")
+                            .map_or("safe-batch.json", |path| path.as_str()),
+                    ))
+                };
+                Ok(SafeParams { address, output })
+            })
+            .transpose()?;
+
+        // Loading the signer may ask for a password, so it is only loaded for the commands
+        // that send transactions or derive the registry key from it.
+        // Transactions prepared for a Safe are signed by its owners instead, except for the census block
+        // that `create-process` records beforehand.
+        let needs_tx_signer = match matches.subcommand() {
+            Some(("create-process" | "vote" | "tally", _)) => true,
+            Some(("reg-key", matches)) => safe.is_none() || matches.get_flag("derive"),
+            Some(("delegate", _)) => safe.is_none(),
+            Some(("registry", registry_matches)) => match registry_matches.subcommand() {
+                Some(("status", matches)) => {
+                    matches.get_one::<String>("address").is_none() && safe.is_none()
+                }
+                Some(("rotate", matches)) => safe.is_none() || matches.get_flag("derive"),
+                _ => safe.is_none(),
+            },
+            Some(("check-vote", matches)) => matches.get_flag("derive"),
+            _ => false,
+        };
+        let tx_signer = if needs_tx_signer {
+            Some(get_tx_signer(matches)?)
+        } else {
+            None
+        };
 
         GlobalCliParams {
             contract_address,
-            rpc_url: String::from(rpc_url),
-            tx_private_key,
+            rpc_urls,
+            state_rpc_urls,
+            tx_signer,
+            token_layout,
+            index_path,
+            safe,
         }
     };
 
+    // Votes and tallies are sent by anyone, so they are never prepared for a Safe
+    if global_cli_param.safe.is_some()
+        && (matches.subcommand_matches("vote").is_some()
+            || matches.subcommand_matches("tally").is_some())
+    {
+        return Err("`--safe` only applies to commands whose transactions the Safe sends: `reg-key`, `registry rotate`, `registry deregister`, `create-process` and `delegate`".to_string());
+    }
+
     if let Some(matches) = matches.subcommand_matches("reg-key") {
-        let key_to_reg: &String = matches
-            .get_one("reg-private-key")
-            .ok_or("Missing key to register in ZKRegistry")?;
-
-        let key_to_reg = PrivateKey::import(
-            parse_private_key(key_to_reg)
-                .map_err(|e| format!("Invalid key to register in ZKRegistry: {}", e))?
-                .to_vec(),
-        )
-        .map_err(|e| format!("Invalid key to register in ZKRegistry: {}", e))?;
+        let key_to_reg = get_registry_key(
+            matches,
+            keystore_dir,
+            global_cli_param.tx_signer.as_ref(),
+            "key to register in ZKRegistry",
+        )?;
 
         return Ok((global_cli_param, CliCommand::RegKey(key_to_reg)));
     }
 
+    // Parse the command `registry`
+    if let Some(registry_matches) = matches.subcommand_matches("registry") {
+        let cli_command = match registry_matches.subcommand() {
+            Some(("status", matches)) => {
+                let address = match (matches.get_one::<String>("address"), &global_cli_param.safe) {
+                    (Some(address), _) => {
+                        Address::from_str(address).map_err(|e| format!("Invalid address: {}", e))?
+                    }
+                    (None, Some(safe)) => safe.address,
+                    (None, None) => global_cli_param
+                        .tx_signer
+                        .as_ref()
+                        .ok_or("Missing transaction signer")?
+                        .address(),
+                };
+                let public_key = match (
+                    matches.get_one::<String>("public-key"),
+                    matches.get_one::<String>("key-name"),
+                ) {
+                    (Some(public_key), _) => Some(
+                        parse_tlcs_pbk(public_key.as_str())
+                            .map_err(|e| format!("Invalid public key: {}", e))?,
+                    ),
+                    (None, Some(key_name)) => Some(keystore::public_key(keystore_dir, key_name)?),
+                    (None, None) => None,
+                };
+                let registration_tx = matches
+                    .get_one::<String>("registration-tx")
+                    .map(|tx_hash| {
+                        H256::from_str(tx_hash)
+                            .map_err(|e| format!("Invalid registration transaction hash: {}", e))
+                    })
+                    .transpose()?;
+
+                CliCommand::RegistryStatus(address, public_key, registration_tx)
+            }
+            Some(("rotate", matches)) => CliCommand::RegistryRotate(get_registry_key(
+                matches,
+                keystore_dir,
+                global_cli_param.tx_signer.as_ref(),
+                "key to register in ZKRegistry",
+            )?),
+            Some(("deregister", _)) => CliCommand::RegistryDeregister,
+            _ => return Err("Missing registry command".to_string()),
+        };
+
+        return Ok((global_cli_param, cli_command));
+    }
+
+    // Parse the command `delegate`
+    if let Some(matches) = matches.subcommand_matches("delegate") {
+        let delegatee: &String = matches.get_one("delegatee").ok_or("Missing delegatee")?;
+        let delegatee = Address::from_str(delegatee)
+            .map_err(|e| format!("Invalid delegatee address: {}", e))?;
+
+        return Ok((global_cli_param, CliCommand::Delegate(delegatee)));
+    }
+
     // Parse the command `create-process`
     if let Some(matches) = matches.subcommand_matches("create-process") {
         let start_delay: &String = matches
@@ -79,14 +304,19 @@ pub fn get_user_input() -> Result<(GlobalCliParams, CliCommand), String> {
             .get_one("process-duration")
             .ok_or("Missing process duration")?;
         let ipfs_hash: &String = matches.get_one("ipfs-hash").ok_or("Missing IPFS hash")?;
+        let census_block: Option<&String> = matches.get_one("census-block");
 
         let start_delay = parse_duration(start_delay);
         let process_duration = parse_duration(process_duration);
         let ipfs_hash = parse_ipfs_hash(ipfs_hash)?;
+        let census_block = census_block
+            .map(|s| u64::from_str(s).map(U64::from))
+            .transpose()
+            .map_err(|e| format!("Invalid census block: {}", e))?;
 
         return Ok((
             global_cli_param,
-            CliCommand::CreateProcess(ipfs_hash, start_delay, process_duration),
+            CliCommand::CreateProcess(ipfs_hash, start_delay, process_duration, census_block),
         ));
     }
 
@@ -97,9 +327,6 @@ pub fn get_user_input() -> Result<(GlobalCliParams, CliCommand), String> {
             .get_one("voting-process-id")
             .ok_or("Missing process id")?;
         let nft_id: &String = matches.get_one("nft-id").ok_or("Missing NFT id")?;
-        let nft_owner_prk: &String = matches
-            .get_one("reg-private-key")
-            .ok_or("Missing nft owner private registry key")?;
         let vote_choice: &String = matches
             .get_one("vote-choice")
             .ok_or("Missing vote choice")?;
@@ -111,14 +338,17 @@ pub fn get_user_input() -> Result<(GlobalCliParams, CliCommand), String> {
         );
         // We allow the user to pass the nft id as a decimal or as a hex string (with or without the 0x prefix)
         let nft_id = parse_u256(nft_id)?;
-        let nft_owner_prk = PrivateKey::import(
-            parse_private_key(nft_owner_prk)
-                .map_err(|e| format!("Invalid nft owner private key: {}", e))?
-                .to_vec(),
-        )
-        .map_err(|e| format!("Invalid nft owner private key: {}", e))?;
+        let nft_owner_prk = get_registry_key(
+            matches,
+            keystore_dir,
+            global_cli_param.tx_signer.as_ref(),
+            "nft owner private registry key",
+        )?;
 
         let vote_choice = VoteChoice::from(vote_choice.as_str());
+        let receipt_dir: &String = matches
+            .get_one("receipt-dir")
+            .ok_or("Missing receipt directory")?;
 
         return Ok((
             global_cli_param,
@@ -128,6 +358,8 @@ pub fn get_user_input() -> Result<(GlobalCliParams, CliCommand), String> {
                 nft_id,
                 nft_owner_prk,
                 vote_choice,
+                PathBuf::from(receipt_dir),
+                matches.get_flag("challenge"),
             ),
         ));
     }
@@ -143,10 +375,221 @@ pub fn get_user_input() -> Result<(GlobalCliParams, CliCommand), String> {
         return Ok((global_cli_param, CliCommand::Tally(process_id)));
     }
 
+    // Parse the command `find-slot`
+    if let Some(matches) = matches.subcommand_matches("find-slot") {
+        let token_address: Option<&String> = matches.get_one("token-address");
+        let nft_id: Option<&String> = matches.get_one("nft-id");
+        let delegator: Option<&String> = matches.get_one("delegator");
+        let max_slot: &String = matches
+            .get_one("max-slot")
+            .ok_or("Missing maximum storage slot")?;
+
+        let token_address = token_address
+            .map(|s| Address::from_str(s))
+            .transpose()
+            .map_err(|e| format!("Invalid token address: {}", e))?;
+        let nft_id = nft_id.map(parse_u256).transpose()?;
+        let delegator = delegator
+            .map(|s| Address::from_str(s))
+            .transpose()
+            .map_err(|e| format!("Invalid delegator address: {}", e))?;
+        let max_slot =
+            u64::from_str(max_slot).map_err(|e| format!("Invalid maximum storage slot: {}", e))?;
+
+        return Ok((
+            global_cli_param,
+            CliCommand::FindSlot(token_address, nft_id, delegator, max_slot),
+        ));
+    }
+
+    // Parse the command `status`
+    if let Some(matches) = matches.subcommand_matches("status") {
+        let process_id: &String = matches
+            .get_one("voting-process-id")
+            .ok_or("Missing process id")?;
+
+        let process_id = parse_u256(process_id)?;
+
+        return Ok((global_cli_param, CliCommand::Status(process_id)));
+    }
+
+    // Parse the command `list-processes`
+    if let Some(matches) = matches.subcommand_matches("list-processes") {
+        let phases = [
+            ("active", ProcessPhase::Active),
+            ("ended-untallied", ProcessPhase::EndedUntallied),
+            ("tallied", ProcessPhase::Tallied),
+        ]
+        .into_iter()
+        .filter(|(flag, _)| matches.get_flag(flag))
+        .map(|(_, phase)| phase)
+        .collect();
+        let json = matches.get_flag("json");
+
+        return Ok((global_cli_param, CliCommand::ListProcesses(phases, json)));
+    }
+
+    // Parse the command `audit`
+    if let Some(matches) = matches.subcommand_matches("audit") {
+        let process_id: &String = matches
+            .get_one("voting-process-id")
+            .ok_or("Missing process id")?;
+
+        let process_id = parse_u256(process_id)?;
+
+        return Ok((global_cli_param, CliCommand::Audit(process_id)));
+    }
+
+    // Parse the command `verify-spoiled-ballot`
+    if let Some(matches) = matches.subcommand_matches("verify-spoiled-ballot") {
+        let ballot_path: &String = matches.get_one("ballot").ok_or("Missing spoiled ballot")?;
+        let commitment: Option<&String> = matches.get_one("commitment");
+
+        let commitment = commitment.map(parse_u256).transpose()?;
+
+        return Ok((
+            global_cli_param,
+            CliCommand::VerifySpoiledBallot(PathBuf::from(ballot_path), commitment),
+        ));
+    }
+
+    // Parse the command `check-vote`
+    if let Some(matches) = matches.subcommand_matches("check-vote") {
+        let receipt_path: &String = matches.get_one("receipt").ok_or("Missing vote receipt")?;
+        let reg_private_key = get_registry_key(
+            matches,
+            keystore_dir,
+            global_cli_param.tx_signer.as_ref(),
+            "private registry key",
+        )?;
+
+        return Ok((
+            global_cli_param,
+            CliCommand::CheckVote(PathBuf::from(receipt_path), reg_private_key),
+        ));
+    }
+
+    // Parse the command `index`
+    if let Some(matches) = matches.subcommand_matches("index") {
+        let from_block: &String = matches.get_one("from-block").ok_or("Missing first block")?;
+
+        if global_cli_param.index_path.is_none() {
+            return Err("Missing index database (`--index-db`)".to_string());
+        }
+        let from_block = u64::from_str(from_block)
+            .map(U64::from)
+            .map_err(|e| format!("Invalid first block: {}", e))?;
+
+        return Ok((global_cli_param, CliCommand::Index(from_block)));
+    }
+
     // No command was chosen
     Ok((global_cli_param, CliCommand::None))
 }
 
+/// Reads the registry private key: derived from the signature of the transaction wallet with `--derive`,
+/// from the keystore if `--key-name` is given, asking for its password, and from `--private-key` otherwise
+fn get_registry_key(
+    matches: &ArgMatches,
+    keystore_dir: &Path,
+    tx_signer: Option<&TxSigner>,
+    description: &str,
+) -> Result<SecretKey, String> {
+    if matches.get_flag("derive") {
+        return derive_registry_key(tx_signer.ok_or("Missing transaction signer")?);
+    }
+
+    if let Some(key_name) = matches.get_one::<String>("key-name") {
+        return keystore::unlock(keystore_dir, key_name);
+    }
+
+    let private_key: &String = matches
+        .get_one("reg-private-key")
+        .ok_or(format!("Missing {}", description))?;
+
+    SecretKey::import(
+        parse_private_key(private_key)
+            .map_err(|e| format!("Invalid {}: {}", description, e))?
+            .as_slice(),
+    )
+    .map_err(|e| format!("Invalid {}: {}", description, e))
+}
+
+/// Derives the registry key from the signature of the transaction wallet
+fn derive_registry_key(tx_signer: &TxSigner) -> Result<SecretKey, String> {
+    let wallet = tx_signer.local_wallet().ok_or(
+        "Registry keys are only derived from local transaction keys, not from remote signers",
+    )?;
+    keystore::derive_key(wallet)
+}
+
+/// Loads the transaction signer chosen by the global parameters:
+/// a remote signer, an encrypted keystore or a mnemonic, both asked for on the terminal, or a private key
+fn get_tx_signer(matches: &ArgMatches) -> Result<TxSigner, String> {
+    if let Some(url) = matches.get_one::<String>("remote-signer") {
+        let address: &String = matches
+            .get_one("remote-signer-address")
+            .ok_or("Missing remote signer address")?;
+        let address = Address::from_str(address)
+            .map_err(|e| format!("Invalid remote signer address: {}", e))?;
+        return TxSigner::remote(url, address);
+    }
+
+    if let Some(path) = matches.get_one::<String>("tx-keystore") {
+        let password = keystore::read_secret(&format!("Password of keystore {}: ", path))?;
+        return TxSigner::from_keystore(Path::new(path), &password);
+    }
+
+    if matches.get_flag("tx-mnemonic") {
+        let derivation_path: &String = matches
+            .get_one("tx-derivation-path")
+            .ok_or("Missing derivation path")?;
+        let mnemonic = keystore::read_secret("Mnemonic of the transaction key: ")?;
+        return TxSigner::from_mnemonic(mnemonic.trim(), derivation_path);
+    }
+
+    let tx_private_key: &String = matches.get_one("tx-private-key").ok_or(
+        "Missing transaction signer: `--private-key`, `--tx-keystore`, `--tx-mnemonic` or `--remote-signer`",
+    )?;
+    TxSigner::from_private_key(&*parse_private_key(tx_private_key)?)
+}
+
+fn get_key_name(matches: &ArgMatches) -> Result<String, String> {
+    matches
+        .get_one::<String>("key-name")
+        .cloned()
+        .ok_or("Missing key name".to_string())
+}
+
+fn get_key_index(matches: &ArgMatches) -> Result<u32, String> {
+    let index: &String = matches.get_one("index").ok_or("Missing key index")?;
+    u32::from_str(index).map_err(|e| format!("Invalid key index: {}", e))
+}
+
+/// The `--index` argument of the commands that derive a registry key from a mnemonic
+fn index_arg() -> Arg {
+    Arg::new("index")
+        .long("index")
+        .help("The index of the key derived from the mnemonic. One mnemonic backs up several keys.")
+        .default_value("0")
+}
+
+/// The `--derive` argument of the commands that take a registry private key
+fn derive_arg() -> Arg {
+    Arg::new("derive")
+        .long("derive")
+        .help("Derive the registry key from the signature of the local transaction wallet (`--private-key`, `--tx-keystore` or `--tx-mnemonic`) over a fixed message, instead of passing it. The same wallet always derives the same key.")
+        .action(ArgAction::SetTrue)
+}
+
+/// The `--key-name` argument of the commands that take a registry private key
+fn key_name_arg() -> Arg {
+    Arg::new("key-name")
+        .long("key-name")
+        .help("The name of the registry key in the keystore, used instead of `--private-key`. Its password is asked for.")
+        .help("Example: `voter`")
+}
+
 /// Constructs the CLI
 fn command_constructor() -> Command {
     command!()
@@ -163,20 +606,190 @@ fn command_constructor() -> Command {
             Arg::new("rpc-url")
                 .short('r')
                 .long("rpc-url")
-                .help("The RPC URL of the target EVM blockchain")
-                .help("Example: `https://rpc2.sepolia.org`")
+                .help("The RPC URL of the target EVM blockchain. Several comma-separated URLs are tried in turn when one fails.")
+                .help("Example: `https://rpc2.sepolia.org,https://rpc.sepolia.org`")
                 .required(true)
+                .value_delimiter(',')
                 .env("RPC_URL"),
         )
+        .arg(
+            Arg::new("state-rpc-url")
+                .long("state-rpc-url")
+                .help("The RPC URL of the same chain to read historical state and take storage proofs from, e.g. an archive node. Defaults to `--rpc-url`. Several comma-separated URLs are tried in turn when one fails.")
+                .help("Example: `https://eth.llamarpc.com`")
+                .value_delimiter(',')
+                .env("STATE_RPC_URL"),
+        )
         .arg(
             Arg::new("tx-private-key")
                 .short('k')
                 .long("private-key")
                 .help("The Private Key of the account that will be used to send the transactions")
                 .help("Example: `1234567890123456789012345678901234567890123456789012345678901234`")
-                .required(true)
                 .env("TX_PRIVATE_KEY"),
         )
+        .arg(
+            Arg::new("tx-keystore")
+                .long("tx-keystore")
+                .help("An encrypted Ethereum JSON keystore with the key that sends the transactions, used instead of `--private-key`. Its password is asked for.")
+                .help("Example: `~/.ethereum/keystore/UTC--2024-01-01T00-00-00.000Z--f39fd6e51aad88f6f4ce6ab8827279cfffb92266`")
+                .conflicts_with_all(["tx-private-key", "tx-mnemonic", "remote-signer"])
+                .env("TX_KEYSTORE"),
+        )
+        .arg(
+            Arg::new("tx-mnemonic")
+                .long("tx-mnemonic")
+                .help("Derive the key that sends the transactions from a BIP-39 mnemonic, used instead of `--private-key`. The mnemonic is asked for.")
+                .conflicts_with_all(["tx-private-key", "remote-signer"])
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("tx-derivation-path")
+                .long("tx-derivation-path")
+                .help("The derivation path of the account of `--tx-mnemonic`")
+                .default_value(DEFAULT_DERIVATION_PATH)
+                .env("TX_DERIVATION_PATH"),
+        )
+        .arg(
+            Arg::new("remote-signer")
+                .long("remote-signer")
+                .help("The JSON-RPC URL of a signer daemon, such as web3signer, that signs the transactions with `eth_signTransaction` instead of `--private-key`")
+                .help("Example: `http://localhost:9000`")
+                .conflicts_with("tx-private-key")
+                .requires("remote-signer-address")
+                .env("REMOTE_SIGNER_URL"),
+        )
+        .arg(
+            Arg::new("remote-signer-address")
+                .long("remote-signer-address")
+                .help("The account of the signer daemon that signs the transactions")
+                .help("Example: `0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266`")
+                .requires("remote-signer")
+                .env("REMOTE_SIGNER_ADDRESS"),
+        )
+        .arg(
+            Arg::new("token-layout")
+                .long("token-layout")
+                .help("The storage layout of the NFT contract: `nouns` or `lil-nouns`. Votes only support the NounsToken layout")
+                .default_value("nouns")
+                .env("TOKEN_LAYOUT"),
+        )
+        .arg(
+            Arg::new("token-layout-file")
+                .long("token-layout-file")
+                .help("A TOML file describing a custom storage layout of the NFT contract, as printed by `find-slot`. Votes only support the NounsToken layout")
+                .help("Example: `owner_slot = 3`, `ownership_encoding = \"address\"`, `delegate_slot = 11`, `registry_slot = 0`")
+                .conflicts_with("token-layout")
+                .env("TOKEN_LAYOUT_FILE"),
+        )
+        .arg(
+            Arg::new("index-db")
+                .long("index-db")
+                .help("The SQLite database filled by the `index` command. When given, ballots are read from it instead of the chain.")
+                .help("Example: `nouns.db`")
+                .env("INDEX_DB"),
+        )
+        .arg(
+            Arg::new("safe")
+                .long("safe")
+                .help("The address of a Safe that holds the NFTs or creates the voting processes. The transactions of `reg-key`, `registry`, `create-process` and `delegate` are prepared for its owners instead of being sent.")
+                .help("Example: `0x5FbDB2315678afecb367f032d93F642f64180aa3`")
+                .env("SAFE_ADDRESS"),
+        )
+        .arg(
+            Arg::new("safe-output")
+                .long("safe-output")
+                .help("The file the Safe Transaction Builder JSON batch is written to. Defaults to `safe-batch.json`; an existing file is never overwritten.")
+                .help("Example: `register.json`")
+                .requires("safe")
+                .conflicts_with("safe-tx-hash"),
+        )
+        .arg(
+            Arg::new("safe-tx-hash")
+                .long("safe-tx-hash")
+                .help("Print the EIP-712 SafeTx hash of each transaction for the owners to sign, instead of writing a Transaction Builder batch")
+                .requires("safe")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("keystore-dir")
+                .long("keystore-dir")
+                .help("The directory of the keystore managed by the `key` command")
+                .default_value("keystore")
+                .env("KEYSTORE_DIR"),
+        )
+        // The `key` command does not need the connection parameters
+        .subcommand_negates_reqs(true)
+        .subcommand(
+            Command::new("key")
+                .about("Manages the keystore of password-encrypted registry keys")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("new")
+                        .about("Generates a new registry key and stores it in the keystore")
+                        .arg(
+                            Arg::new("key-name")
+                                .short('n')
+                                .long("name")
+                                .help("The name of the key in the keystore")
+                                .help("Example: `voter`")
+                                .required(true)
+                        )
+                        .arg(
+                            Arg::new("mnemonic")
+                                .long("mnemonic")
+                                .help("Derive the key from a new BIP-39 mnemonic, which is printed once so that the key can be recovered")
+                                .action(ArgAction::SetTrue)
+                        )
+                        .arg(index_arg().requires("mnemonic"))
+                )
+                .subcommand(
+                    Command::new("recover")
+                        .about("Recovers a registry key from its BIP-39 mnemonic and stores it in the keystore. The mnemonic is asked for.")
+                        .arg(
+                            Arg::new("key-name")
+                                .short('n')
+                                .long("name")
+                                .help("The name of the key in the keystore")
+                                .help("Example: `voter`")
+                                .required(true)
+                        )
+                        .arg(index_arg())
+                )
+                .subcommand(
+                    Command::new("import")
+                        .about("Stores an existing registry key in the keystore. The key is asked for if it is not given.")
+                        .arg(
+                            Arg::new("key-name")
+                                .short('n')
+                                .long("name")
+                                .help("The name of the key in the keystore")
+                                .help("Example: `voter`")
+                                .required(true)
+                        )
+                        .arg(
+                            Arg::new("reg-private-key")
+                                .short('k')
+                                .long("private-key")
+                                .help("The registry Private Key to import")
+                                .help("Example: `0x043c3780cb30f913d1c34d80437f7c61c973461595986e899ee6a8171143db1d`")
+                        )
+                        .arg(derive_arg())
+                )
+                .subcommand(
+                    Command::new("export-public")
+                        .about("Prints the public key of a registry key in the keystore")
+                        .arg(
+                            Arg::new("key-name")
+                                .short('n')
+                                .long("name")
+                                .help("The name of the key in the keystore")
+                                .help("Example: `voter`")
+                                .required(true)
+                        )
+                )
+                .subcommand(Command::new("list").about("Lists the registry keys in the keystore"))
+        )
         .subcommand(
             Command::new("reg-key")
                 .about("Registers a new BBJJ Public Key in the ZKRegistry contract")
@@ -186,9 +799,73 @@ fn command_constructor() -> Command {
                         .long("private-key")
                         .help("The Private Key to register in the zkRegistry under the account, that owns the NFT")
                         .help("Example: `043c3780cb30f913d1c34d80437f7c61c973461595986e899ee6a8171143db1d`")
-                        .required(true)
+                        .required_unless_present_any(["key-name", "derive"])
                         .env("REG_PRIVATE_KEY")
                 )
+                .arg(key_name_arg())
+                .arg(derive_arg())
+        )
+        .subcommand(
+            Command::new("registry")
+                .about("Manages the BBJJ Public Key registered in the ZKRegistry contract. Every change is confirmed by reading the key back.")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("status")
+                        .about("Shows the BBJJ Public Key registered by an address and whether it matches a given key")
+                        .arg(
+                            Arg::new("address")
+                                .help("The address whose registered key is shown. Defaults to the Safe given by `--safe`, or else to the address of the transaction private key.")
+                                .help("Example: `0x5FbDB2315678afecb367f032d93F642f64180aa3`")
+                        )
+                        .arg(
+                            Arg::new("public-key")
+                                .short('p')
+                                .long("public-key")
+                                .help("The BBJJ Public Key to compare with the registered one, as printed by `key export-public`")
+                                .conflicts_with("key-name")
+                        )
+                        .arg(
+                            Arg::new("key-name")
+                                .long("key-name")
+                                .help("The name of a registry key in the keystore to compare with the registered one. Its password is not needed.")
+                                .help("Example: `voter`")
+                        )
+                        .arg(
+                            Arg::new("registration-tx")
+                                .long("registration-tx")
+                                .help("The transaction that registered the key, whose published proof of possession is checked against the registered key")
+                                .help("Example: `0x3c0c3ba3e6c4d1d3e6c7bd6a1c1b6a44e1e1ab84c5e0a77d4c5a4cfa9b7e2d10`")
+                        )
+                )
+                .subcommand(
+                    Command::new("rotate")
+                        .about("Registers a new BBJJ Public Key in place of the registered one, listing the open voting processes whose census already took the old key")
+                        .arg(
+                            Arg::new("reg-private-key")
+                                .short('k')
+                                .long("private-key")
+                                .help("The new Private Key to register in the zkRegistry under the account, that owns the NFT")
+                                .help("Example: `043c3780cb30f913d1c34d80437f7c61c973461595986e899ee6a8171143db1d`")
+                                .required_unless_present_any(["key-name", "derive"])
+                                .env("REG_PRIVATE_KEY")
+                        )
+                        .arg(key_name_arg())
+                        .arg(derive_arg())
+                )
+                .subcommand(
+                    Command::new("deregister")
+                        .about("Removes the BBJJ Public Key registered by the account of the transaction private key")
+                )
+        )
+        .subcommand(
+            Command::new("delegate")
+                .about("Delegates the votes of the NFTs held by the account of the transaction private key, or by the Safe given by `--safe`")
+                .arg(
+                    Arg::new("delegatee")
+                        .help("The address the votes are delegated to")
+                        .help("Example: `0x5FbDB2315678afecb367f032d93F642f64180aa3`")
+                        .required(true)
+                )
         )
         .subcommand(
             Command::new("create-process")
@@ -221,6 +898,13 @@ fn command_constructor() -> Command {
                         .help("Example: bafkreidfgllkxpigujgbavuq5kxdd5yo2jid3abzuxhwj7l6socllnd3m4")
                         .required(true)
                 )
+                .arg(
+                    Arg::new("census-block")
+                        .short('b')
+                        .long("census-block")
+                        .help("The block number at which token ownership is taken. Defaults to the latest block. Blocks older than 256 blocks are first recorded in the NounsVoting contract through header chain proofs.")
+                        .help("Example: `17900000`")
+                )
         )
         .subcommand(
             Command::new("vote")
@@ -254,9 +938,11 @@ fn command_constructor() -> Command {
                         .long("private-key")
                         .help("The Private Key registered in the zkRegistry under the account, that owns the NFT")
                         .help("Example: `0x043c3780cb30f913d1c34d80437f7c61c973461595986e899ee6a8171143db1d`")
-                        .required(true)
+                        .required_unless_present_any(["key-name", "derive"])
                         .env("REG_PRIVATE_KEY")
                 )
+                .arg(key_name_arg())
+                .arg(derive_arg())
                 .arg(
                     Arg::new("vote-choice")
                         .short('v')
@@ -264,6 +950,19 @@ fn command_constructor() -> Command {
                         .help("The Vote Choice as: (Y)es/(N)o/(A)bstain)")
                         .required(true)
                 )
+                .arg(
+                    Arg::new("receipt-dir")
+                        .long("receipt-dir")
+                        .help("The directory the encrypted vote receipt and any spoiled ballots are saved to")
+                        .default_value("receipts")
+                        .env("RECEIPT_DIR")
+                )
+                .arg(
+                    Arg::new("challenge")
+                        .long("challenge")
+                        .help("Show a commitment to each encrypted ballot and ask whether to cast it or spoil it to check its encryption with `verify-spoiled-ballot`")
+                        .action(ArgAction::SetTrue)
+                )
         )
         .subcommand(
             Command::new("tally")
@@ -277,4 +976,144 @@ fn command_constructor() -> Command {
                         .required(true)
                 )
         )
+        .subcommand(
+            Command::new("status")
+                .visible_alias("process-info")
+                .about("Shows the details of an existing voting process")
+                .arg(
+                    Arg::new("voting-process-id")
+                        .short('p')
+                        .long("voting-process-id")
+                        .help("The Voting Process ID")
+                        .help("Example: `1`")
+                        .required(true)
+                )
+        )
+        .subcommand(
+            Command::new("list-processes")
+                .about("Lists the voting processes of the NounsVoting contract. Phase flags can be combined; without any, all processes are listed.")
+                .arg(
+                    Arg::new("active")
+                        .long("active")
+                        .help("List the processes accepting votes")
+                        .action(ArgAction::SetTrue)
+                )
+                .arg(
+                    Arg::new("ended-untallied")
+                        .long("ended-untallied")
+                        .help("List the processes that ended and are waiting for their tally")
+                        .action(ArgAction::SetTrue)
+                )
+                .arg(
+                    Arg::new("tallied")
+                        .long("tallied")
+                        .help("List the tallied processes")
+                        .action(ArgAction::SetTrue)
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("Print the processes as JSON")
+                        .action(ArgAction::SetTrue)
+                )
+        )
+        .subcommand(
+            Command::new("audit")
+                .about("Checks the tally result submitted for a voting process by decrypting the ballots again")
+                .arg(
+                    Arg::new("voting-process-id")
+                        .short('p')
+                        .long("voting-process-id")
+                        .help("The Voting Process ID whose tally result is checked")
+                        .help("Example: `1`")
+                        .required(true)
+                )
+        )
+        .subcommand(
+            Command::new("verify-spoiled-ballot")
+                .about("Checks that a ballot spoiled with `vote --challenge` encrypts the chosen vote")
+                .arg(
+                    Arg::new("ballot")
+                        .short('b')
+                        .long("ballot")
+                        .help("The spoiled ballot saved by `vote --challenge`")
+                        .help("Example: `receipts/spoiled-1-2c5d0a8f1b9e3d47.json`")
+                        .required(true)
+                )
+                .arg(
+                    Arg::new("commitment")
+                        .short('c')
+                        .long("commitment")
+                        .help("The ballot commitment shown before spoiling the ballot. If it is not supplied, the commitment of the ballot is printed to be compared by hand.")
+                        .help("Example: `0x1f0e4a5c7b3d2e9f8a6b5c4d3e2f1a0b9c8d7e6f5a4b3c2d1e0f9a8b7c6d5e4f`")
+                )
+        )
+        .subcommand(
+            Command::new("check-vote")
+                .about("Checks that a vote was cast as intended, included in the voting process and counted, using its receipt")
+                .arg(
+                    Arg::new("receipt")
+                        .short('r')
+                        .long("receipt")
+                        .help("The vote receipt saved by `vote`")
+                        .help("Example: `receipts/receipt-1-5ee3b1b1b0b6a3b8.json`")
+                        .required(true)
+                )
+                .arg(
+                    Arg::new("reg-private-key")
+                        .short('k')
+                        .long("private-key")
+                        .help("The Private Key registered in the zkRegistry that the vote was cast with")
+                        .help("Example: `0x043c3780cb30f913d1c34d80437f7c61c973461595986e899ee6a8171143db1d`")
+                        .required_unless_present_any(["key-name", "derive"])
+                        .env("REG_PRIVATE_KEY")
+                )
+                .arg(key_name_arg())
+                .arg(derive_arg())
+        )
+        .subcommand(
+            Command::new("find-slot")
+                .about("Discovers the storage layout of an NFT contract and prints it as a layout config")
+                .arg(
+                    Arg::new("token-address")
+                        .short('t')
+                        .long("token-address")
+                        .help("The address of the NFT contract. If it is not supplied, the one of the NounsVoting contract is used.")
+                        .help("Example: `0x9C8fF314C9Bc7F6e59A9d9225Fb22946427eDC03`")
+                )
+                .arg(
+                    Arg::new("nft-id")
+                        .short('n')
+                        .long("nft-id")
+                        .help("The ID of an existing NFT, used to find the ownership mapping")
+                        .help("Example: `1` or `0x0000000000000000000000000000000000000000000000000000000000000001`")
+                        .required_unless_present("delegator")
+                )
+                .arg(
+                    Arg::new("delegator")
+                        .short('d')
+                        .long("delegator")
+                        .help("The address of an account that has delegated its votes, used to find the delegation mapping")
+                        .help("Example: `0xa8b2e7f501928374169283f7b2a5d3f9e0a7b3d6`")
+                )
+                .arg(
+                    Arg::new("max-slot")
+                        .short('m')
+                        .long("max-slot")
+                        .help("The number of storage slots to search")
+                        .default_value("64")
+                )
+        )
+        .subcommand(
+            Command::new("index")
+                .about("Follows the NounsVoting events and stores the voting processes, ballots and tally results in the index database")
+                .arg(
+                    Arg::new("from-block")
+                        .short('f')
+                        .long("from-block")
+                        .help("The block to start indexing from when the index is empty, e.g. the block the NounsVoting contract was deployed in")
+                        .help("Example: `17900000`")
+                        .default_value("0")
+                )
+        )
 }
@@ -0,0 +1,313 @@
+use std::collections::HashSet;
+
+use ethers::contract::parse_log;
+use ethers::core::k256::U256;
+use ethers::prelude::{BlockNumber, Filter, Log, Middleware, MiddlewareError, H256, U64};
+
+use nouns_protocol::{wrap, wrap_into, TruncatedBallot, Wrapper};
+
+use crate::ethereum::contract_interactions::{get_voting_process, BallotCastFilter, NounsVoting};
+use crate::EthersU256;
+
+/// Number of blocks queried for logs at once. Halved whenever the RPC node rejects a range,
+/// and doubled back after each page it accepts.
+const LOG_CHUNK_SIZE: u64 = 2000;
+
+/// A ballot cast in a voting process, along with the transaction that cast it
+#[derive(Clone, Debug)]
+pub(crate) struct CastBallot {
+    pub(crate) event: BallotCastFilter,
+    pub(crate) block_number: U64,
+    pub(crate) transaction_hash: H256,
+    pub(crate) log_index: EthersU256,
+}
+
+/// The ballots cast in a voting process up to `to_block`, in the order they were cast
+pub(crate) struct ProcessBallots {
+    pub(crate) ballots: Vec<CastBallot>,
+    pub(crate) to_block: U64,
+    /// Whether `to_block` covers the whole voting period
+    pub(crate) complete: bool,
+}
+
+impl CastBallot {
+    pub(crate) fn truncated_ballot(&self) -> TruncatedBallot {
+        let a_x: U256 = wrap_into!(self.event.a_x);
+        let a_y: U256 = wrap_into!(self.event.a_y);
+        let b: U256 = wrap_into!(self.event.b);
+
+        TruncatedBallot {
+            a: wrap_into!([a_x, a_y]),
+            b: wrap_into!(b),
+        }
+    }
+}
+
+impl ProcessBallots {
+    pub(crate) fn truncated_ballots(&self) -> Vec<TruncatedBallot> {
+        self.ballots
+            .iter()
+            .map(CastBallot::truncated_ballot)
+            .collect()
+    }
+}
+
+/// Pages through the logs matching a filter over a block range,
+/// shrinking the pages whenever the RPC node rejects them and growing them back afterwards
+pub(crate) struct LogPages {
+    filter: Filter,
+    next_block: U64,
+    to_block: U64,
+    chunk_size: u64,
+}
+
+impl LogPages {
+    pub(crate) fn new(filter: Filter, from_block: U64, to_block: U64) -> Self {
+        LogPages {
+            filter,
+            next_block: from_block,
+            to_block,
+            chunk_size: LOG_CHUNK_SIZE,
+        }
+    }
+
+    /// The first block of the next page
+    pub(crate) fn next_block(&self) -> U64 {
+        self.next_block
+    }
+
+    /// Fetches the next page of logs along with its last block, or `None` past the end of the range
+    pub(crate) async fn next<M: Middleware>(
+        &mut self,
+        client: &M,
+    ) -> Result<Option<(U64, Vec<Log>)>, M::Error> {
+        while self.next_block <= self.to_block {
+            let chunk_end = (self.next_block + self.chunk_size - 1).min(self.to_block);
+            let filter = self
+                .filter
+                .clone()
+                .from_block(self.next_block)
+                .to_block(chunk_end);
+
+            match client.get_logs(&filter).await {
+                Ok(logs) => {
+                    self.next_block = chunk_end + 1;
+                    // A dense range may be followed by sparse ones
+                    self.chunk_size = (self.chunk_size * 2).min(LOG_CHUNK_SIZE);
+                    return Ok(Some((chunk_end, logs)));
+                }
+                // Nodes limit the block range or the number of results, so retry with a smaller range
+                Err(e) if e.as_error_response().is_some() && self.chunk_size > 1 => {
+                    self.chunk_size /= 2;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// The block number, transaction hash and index in the block of a mined log
+pub(crate) fn log_position(log: &Log) -> Result<(U64, H256, EthersU256), String> {
+    match (log.block_number, log.transaction_hash, log.log_index) {
+        (Some(block_number), Some(transaction_hash), Some(log_index)) => {
+            Ok((block_number, transaction_hash, log_index))
+        }
+        _ => Err(format!("Error: Pending log {:?}", log)),
+    }
+}
+
+/// Collects the `BallotCast` events of a voting process from its start block up to its end block
+/// or the finalized block, whichever comes first, paging through block ranges.
+/// Logs that were removed or seen more than once because of reorgs are dropped.
+pub(crate) async fn fetch_ballots<M: Middleware>(
+    nouns_voting: &NounsVoting<M>,
+    process_id: EthersU256,
+) -> Result<ProcessBallots, String> {
+    let voting_process = get_voting_process(nouns_voting, process_id).await?;
+    let client = nouns_voting.client();
+
+    // Blocks past the finalized one may still be reorganised
+    let finalized_block = match client.get_block(BlockNumber::Finalized).await {
+        Ok(Some(block)) => block.number,
+        // Nodes without the `finalized` tag only offer the latest block
+        _ => None,
+    };
+    let finalized_block = match finalized_block {
+        Some(block_number) => block_number,
+        None => client
+            .get_block_number()
+            .await
+            .map_err(|e| format!("Error getting current block number: {}", e))?,
+    };
+
+    // Votes are accepted up to the block before the end block
+    let last_voting_block = U64::from(voting_process.end_block.saturating_sub(1));
+    let from_block = U64::from(voting_process.start_block);
+    let to_block = last_voting_block.min(finalized_block);
+
+    let mut ballots = vec![];
+    let mut pages = LogPages::new(
+        nouns_voting.ballot_cast_filter().filter,
+        from_block,
+        to_block,
+    );
+    loop {
+        let chunk_start = pages.next_block();
+        let logs = match pages.next(client.as_ref()).await {
+            Ok(Some((_, logs))) => logs,
+            Ok(None) => break,
+            Err(e) => {
+                return Err(format!(
+                    "Error getting the ballots of voting process {} in block {}: {}",
+                    process_id, chunk_start, e
+                ))
+            }
+        };
+
+        for log in logs {
+            if log.removed == Some(true) {
+                continue;
+            }
+
+            let (block_number, transaction_hash, log_index) = log_position(&log)?;
+            let event: BallotCastFilter = parse_log(log).map_err(|e| {
+                format!(
+                    "Error decoding ballot in transaction {:?}: {}",
+                    transaction_hash, e
+                )
+            })?;
+
+            if event.process_id == process_id {
+                ballots.push(CastBallot {
+                    event,
+                    block_number,
+                    transaction_hash,
+                    log_index,
+                });
+            }
+        }
+    }
+
+    Ok(ProcessBallots {
+        ballots: order_ballots(ballots),
+        to_block,
+        complete: to_block == last_voting_block,
+    })
+}
+
+/// Sorts ballots in the order they were cast, which the ballots hash depends on,
+/// keeping only the first position of a ballot seen more than once
+fn order_ballots(mut ballots: Vec<CastBallot>) -> Vec<CastBallot> {
+    ballots.sort_by_key(|ballot| (ballot.block_number, ballot.log_index));
+    let mut seen = HashSet::new();
+    ballots.retain(|ballot| {
+        seen.insert((
+            ballot.transaction_hash,
+            ballot.event.a_x,
+            ballot.event.a_y,
+            ballot.event.b,
+        ))
+    });
+
+    ballots
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::prelude::{Filter, Log, Provider, H256, U64};
+    use ethers::providers::{JsonRpcError, MockResponse};
+
+    use crate::ethereum::ballots::{
+        log_position, order_ballots, CastBallot, LogPages, LOG_CHUNK_SIZE,
+    };
+    use crate::ethereum::contract_interactions::BallotCastFilter;
+    use crate::EthersU256;
+
+    #[tokio::test]
+    async fn test_log_pages() {
+        let (provider, mock) = Provider::mocked();
+        let filter = Filter::new();
+        let log = Log {
+            block_number: Some(U64::from(1500)),
+            ..Default::default()
+        };
+
+        // The node rejects the first range, accepts half of it, and then a full range again.
+        // Mocked responses are served last in, first out.
+        mock.push::<Vec<Log>, _>(vec![log.clone()]).unwrap();
+        mock.push::<Vec<Log>, _>(vec![]).unwrap();
+        mock.push_response(MockResponse::Error(JsonRpcError {
+            code: -32005,
+            message: "query returned more than 10000 results".to_string(),
+            data: None,
+        }));
+
+        let mut pages = LogPages::new(filter.clone(), U64::zero(), U64::from(2999));
+        assert_eq!(
+            pages.next(&provider).await.unwrap(),
+            Some((U64::from(999), vec![]))
+        );
+        assert_eq!(pages.next_block(), U64::from(1000));
+        assert_eq!(
+            pages.next(&provider).await.unwrap(),
+            Some((U64::from(2999), vec![log]))
+        );
+        assert_eq!(pages.next(&provider).await.unwrap(), None);
+
+        for (from_block, to_block) in [(0, LOG_CHUNK_SIZE - 1), (0, 999), (1000, 2999)] {
+            mock.assert_request(
+                "eth_getLogs",
+                [filter.clone().from_block(from_block).to_block(to_block)],
+            )
+            .unwrap();
+        }
+
+        // Other errors are not retried
+        mock.push_response(MockResponse::Value(serde_json::Value::Bool(true)));
+        let mut pages = LogPages::new(filter, U64::zero(), U64::zero());
+        assert!(pages.next(&provider).await.is_err());
+    }
+
+    #[test]
+    fn test_order_ballots() {
+        let ballot = |block_number: u64, log_index: u64, transaction: u8, b: u64| {
+            let log = Log {
+                block_number: Some(U64::from(block_number)),
+                transaction_hash: Some(H256::repeat_byte(transaction)),
+                log_index: Some(EthersU256::from(log_index)),
+                ..Default::default()
+            };
+            let (block_number, transaction_hash, log_index) = log_position(&log).unwrap();
+
+            CastBallot {
+                event: BallotCastFilter {
+                    process_id: EthersU256::zero(),
+                    a_x: EthersU256::one(),
+                    a_y: EthersU256::one(),
+                    b: EthersU256::from(b),
+                },
+                block_number,
+                transaction_hash,
+                log_index,
+            }
+        };
+
+        // The last ballot is the first one seen again at a later position, as after a reorg
+        let ballots = order_ballots(vec![
+            ballot(11, 0, 3, 3),
+            ballot(10, 5, 2, 2),
+            ballot(10, 1, 1, 1),
+            ballot(12, 0, 1, 1),
+        ]);
+        let positions: Vec<_> = ballots
+            .iter()
+            .map(|ballot| (ballot.block_number.as_u64(), ballot.log_index.as_u64()))
+            .collect();
+        assert_eq!(positions, vec![(10, 1), (10, 5), (11, 0)]);
+
+        // Pending logs have no position
+        assert!(log_position(&Log::default()).is_err());
+    }
+}
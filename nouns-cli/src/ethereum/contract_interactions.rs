@@ -1,38 +1,77 @@
 use ark_ff::{biginteger::BigInteger256 as B256, BigInt, BigInteger, Field, PrimeField};
+use chrono::{DateTime, Utc};
 use console::Emoji;
+use std::fmt;
+use std::io::Write;
 use std::ops::Add;
+use std::path::PathBuf;
 use std::sync::{mpsc, Arc};
 use std::thread;
 
 use std::time::Duration;
 
-use ethers::core::k256::ecdsa::SigningKey;
+use ethers::contract::parse_log;
 use ethers::core::k256::U256;
 use ethers::core::rand;
 use ethers::prelude::{
-    abigen, Address, BigEndianHash, Http, LocalWallet, Middleware, Provider, SignerMiddleware,
-    TransactionRequest, Wallet,
+    abigen, Address, BigEndianHash, Block, BlockNumber, ContractCall, Middleware, Provider,
+    TransactionRequest,
 };
 use ethers::types::{H256, U64};
 
 use indicatif::{ProgressBar, ProgressStyle};
 
 use nouns_protocol::{
-    wrap, wrap_into, PrivateKey, Tallier, TruncatedBallot, VoteChoice, Voter, Wrapper,
+    wrap, wrap_into, SecretKey, Tallier, VoteChoice, Voter, Wrapper, BBJJ_Ec, BBJJ_G1,
 };
 
-use nouns_protocol::noir::BlockHashVerifierInput;
+use nouns_protocol::noir::{BlockHashVerifierInput, HeaderChainVerifierInput, MAX_CHAIN_LENGTH};
+use nouns_protocol::registry::{key_commitment, verify_registration, PossessionProof};
 use tokio::runtime::Runtime;
-
-use crate::ethereum::proofs;
+use zeroize::Zeroizing;
+
+use crate::ethereum::indexer::{self, Index};
+use crate::ethereum::layout::{OwnershipEncoding, TokenStorageLayout};
+use crate::ethereum::proofs::map_storage_slot;
+use crate::ethereum::provider::FailoverHttp;
+use crate::ethereum::receipts::{self, StoredReceipt};
+use crate::ethereum::safe::{self, SafeParams, SafeTransaction};
+use crate::ethereum::{ballots, mpt, proofs};
+use crate::keystore::format_public_key;
 use crate::EthersU256;
 
 static SPARKLE: Emoji<'_, '_> = Emoji("✨ ", ":-)");
 
+/// Storage slot of the `votingProcesses` mapping in the NounsVoting contract
+const VOTING_PROCESSES_SLOT: u64 = 7;
+
+/// Offsets of `tlcsRoundNumber` and the two words of `tlcsPublicKey` within the storage of a voting process
+const TLCS_STORAGE_OFFSETS: [u64; 3] = [6, 7, 8];
+
+/// Census blocks further back than this are recorded through header chain proofs.
+/// `blockhash` only covers the last 256 blocks, so this leaves time to generate the block hash proof.
+const MAX_BLOCKHASH_AGE: u64 = 128;
+
+/// Number of header chain proofs recorded per `recordBlockHashes` transaction
+const HEADER_CHAIN_PROOFS_PER_TX: usize = 8;
+
+/// Average time between Ethereum blocks, in seconds
+const ETH_BLOCK_TIME: u64 = 12;
+
+/// The interface ID of Poseidon commitments to BBJJ Public Keys in ZKRegistry
+const POSEIDON_INTERFACE_ID: u8 = 0xbf;
+
+/// The interface IDs of the x and y coordinates of BBJJ Public Keys registered before their Poseidon commitment
+const LEGACY_KEY_INTERFACE_IDS: [u8; 2] = [0x00, 0x01];
+
 abigen!(
     ZKRegistry,
     r#"[
             function register(uint8 interface_id, uint256 value)
+            function registerWithProof(uint8 interface_id, uint256 value, bytes proof)
+            event Registered(uint8 indexed interface_id, address indexed addr, uint256 value, bytes proof)
+            function deregister(uint8 interface_id)
+            function get(uint8 interface_id, address addr) view returns (uint256)
         ]"#,
 );
 
@@ -52,11 +91,52 @@ abigen!(
             function getTlcsRoundNumber(uint256 processId) public view returns (uint64)
             function getBallotsHash(uint256 processId) public view returns (uint256) 
             function getTallyResult(uint256 processId) public view returns (uint256[3] memory)
+            function historicalBlockHashes(uint64 blockNumber) public view returns (bytes32)
+            function recordBlockHash(uint64 blockNumber, bytes32 ancestorHash, uint64 numHeaders, bytes calldata proof) public
+            function recordBlockHashes(uint64 blockNumber, bytes32[] calldata ancestorHashes, uint64[] calldata numHeaders, bytes[] calldata proofs) public
             
-            event BallotCast(uint256 indexed processId, uint256 indexed a_x, uint256 indexed a_y, uint256 indexed b)
+            event BallotCast(uint256 processId, uint256 indexed a_x, uint256 indexed a_y, uint256 indexed b)
+            event ProcessCreated(uint256 indexed processId, uint64 censusBlock, uint64 startBlock, uint64 endBlock)
+            event TallySubmitted(uint256 indexed processId, uint256 votesFor, uint256 votesAgainst, uint256 votesAbstain)
         ]"#,
 );
 
+// The tuple output of the getter needs the JSON ABI for abigen to decode it
+abigen!(
+    NounsVotingProcesses,
+    r#"[{
+        "type": "function",
+        "name": "votingProcesses",
+        "stateMutability": "view",
+        "inputs": [{ "name": "processId", "type": "uint256", "internalType": "uint256" }],
+        "outputs": [
+            { "name": "ipfsHash", "type": "bytes32", "internalType": "bytes32" },
+            { "name": "censusBlock", "type": "uint64", "internalType": "uint64" },
+            { "name": "startBlock", "type": "uint64", "internalType": "uint64" },
+            { "name": "blockHash", "type": "bytes32", "internalType": "bytes32" },
+            { "name": "endBlock", "type": "uint64", "internalType": "uint64" },
+            { "name": "zkRegistryStorageRoot", "type": "bytes32", "internalType": "bytes32" },
+            { "name": "nounsTokenStorageRoot", "type": "bytes32", "internalType": "bytes32" },
+            { "name": "tlcsRoundNumber", "type": "uint64", "internalType": "uint64" },
+            { "name": "ballotsHash", "type": "uint256", "internalType": "uint256" },
+            { "name": "votesFor", "type": "uint256", "internalType": "uint256" },
+            { "name": "votesAgainst", "type": "uint256", "internalType": "uint256" },
+            { "name": "votesAbstain", "type": "uint256", "internalType": "uint256" },
+            { "name": "tallied", "type": "bool", "internalType": "bool" },
+            {
+                "name": "action",
+                "type": "tuple",
+                "internalType": "struct NounsVoting.ExecutableAction",
+                "components": [
+                    { "name": "target", "type": "address", "internalType": "address" },
+                    { "name": "funcSignature", "type": "bytes4", "internalType": "bytes4" },
+                    { "name": "args", "type": "bytes", "internalType": "bytes" }
+                ]
+            }
+        ]
+    }]"#,
+);
+
 abigen!(
     NounsToken,
     r#"[
@@ -71,70 +151,407 @@ abigen!(
 );
 
 /// Function that registers a new BBJJ Public Key in the ZKRegistry contract.
-pub async fn reg_key(
-    client: SignerMiddleware<Provider<Http>, LocalWallet>,
+/// With a Safe, the registration is prepared for the Safe instead, so that the key is registered under its address.
+pub async fn reg_key<M: Middleware + Clone + 'static>(
+    client: M,
     nouns_voting_address: Address,
-    bbjj_private_key: PrivateKey,
+    bbjj_private_key: SecretKey,
+    safe: Option<&SafeParams>,
 ) -> Result<(), String> {
-    /// The BBJJ Public Key interface ID in ZKRegistry
-    const INTERFACE_ID: u8 = 0x00;
+    let nouns_voting = NounsVoting::new(nouns_voting_address, Arc::new(client.clone()));
+    let zk_registry = get_zk_registry(&nouns_voting, client).await?;
+
+    if let Some(safe) = safe {
+        prepare_safe_registration(
+            &zk_registry,
+            &bbjj_private_key,
+            safe,
+            "Register Baby Jubjub public key",
+        )
+        .await?;
+        println!(
+            "{} Baby Jubjub public key registration prepared for the Safe {:?}",
+            SPARKLE, safe.address
+        );
+        return Ok(());
+    }
 
-    let client = Arc::new(client);
+    let tx_hash = register_public_key(&zk_registry, &bbjj_private_key).await?;
 
-    let nouns_voting = NounsVoting::new(nouns_voting_address, client.clone());
-    let zk_registry_address = nouns_voting.zk_registry().call().await.map_err(|e| {
-        format!("Error getting the ZKRegistry address from the NounsVoting contract: {e:?}")
-    })?;
+    println!(
+        "{} Baby Jubjub public key registered successfully (transaction hash {:?})",
+        SPARKLE, tx_hash
+    );
+    Ok(())
+}
+
+/// Function that shows the commitment to the BBJJ Public Key registered by `address` in the ZKRegistry contract
+/// and, if a public key is given, whether it is the registered one.
+/// Given the transaction that registered the key, the proof of possession published with it is checked too.
+/// Keys registered as their coordinates, as before their commitment, are pointed out, as votes no longer read them.
+pub async fn registry_status(
+    eth_connection: Provider<FailoverHttp>,
+    state_connection: Provider<FailoverHttp>,
+    nouns_voting_address: Address,
+    address: Address,
+    public_key: Option<BBJJ_Ec>,
+    registration_tx: Option<H256>,
+) -> Result<(), String> {
+    let (zk_registry_address, census_block, registered_commitment, legacy_key, proof_check) =
+        exec_with_progress("Fetching registered key", move || {
+            let rt = Runtime::new().unwrap();
+            rt.block_on(async {
+                let nouns_voting =
+                    NounsVoting::new(nouns_voting_address, Arc::new(eth_connection.clone()));
+                let zk_registry = get_zk_registry(&nouns_voting, state_connection.clone()).await?;
+
+                let census_block = state_connection
+                    .get_block_number()
+                    .await
+                    .map_err(|e| format!("Error getting current block number: {}", e))?;
+                let registered_commitment =
+                    get_registered_commitment(&zk_registry, address, Some(census_block.as_u64()))
+                        .await?;
+                let legacy_key = get_registry_values(
+                    &zk_registry,
+                    LEGACY_KEY_INTERFACE_IDS,
+                    address,
+                    Some(census_block.as_u64()),
+                )
+                .await
+                .map_err(|e| {
+                    format!(
+                        "Error getting the legacy key registered by {:?}: {}",
+                        address, e
+                    )
+                })?;
+                let proof_check = match registration_tx {
+                    Some(tx_hash) => Some(
+                        check_registration_proof(
+                            &zk_registry,
+                            address,
+                            tx_hash,
+                            registered_commitment,
+                        )
+                        .await,
+                    ),
+                    None => None,
+                };
+
+                Ok((
+                    zk_registry.address(),
+                    census_block,
+                    registered_commitment,
+                    legacy_key,
+                    proof_check,
+                ))
+            })
+        })?;
+
+    println!(
+        "ZKRegistry {:?} at block {}",
+        zk_registry_address, census_block
+    );
+    println!("  Address:           {:?}", address);
+    println!(
+        "  Key commitment:    {}",
+        format_registered_commitment(&registered_commitment)
+    );
+    if legacy_key != [EthersU256::zero(); 2] {
+        println!(
+            "  Legacy key:        {:#x},{:#x} (registered as its coordinates, which votes no longer read)",
+            legacy_key[0], legacy_key[1]
+        );
+        if registered_commitment.is_zero() {
+            println!("Register the key again with `reg-key` to vote with it");
+        }
+    }
+
+    if let Some(proof_check) = &proof_check {
+        println!(
+            "  Possession proof:  {}",
+            match proof_check {
+                Ok(()) => "valid".to_string(),
+                Err(e) => format!("invalid ({})", e),
+            }
+        );
+    }
+
+    if let Some(public_key) = public_key {
+        let matches = registered_commitment == public_key_commitment(&public_key)?;
+        println!("  Given key:         {}", format_public_key(&public_key));
+        println!(
+            "  Matches:           {}",
+            if matches { "yes" } else { "no" }
+        );
+        if !matches {
+            return Err("The registered key does not match the given key".to_string());
+        }
+    }
+
+    match proof_check {
+        Some(Err(_)) => Err(
+            "The registered key has no valid proof of possession in the given transaction"
+                .to_string(),
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Function that registers a new BBJJ Public Key in the ZKRegistry contract
+/// in place of the one registered by the sender.
+/// Voting processes whose census already took the old key still need it to vote, so they are listed first.
+/// With a Safe, the rotation of the key of the Safe is prepared instead.
+pub async fn rotate_key<M: Middleware + Clone + 'static>(
+    client: M,
+    nouns_voting_address: Address,
+    bbjj_private_key: SecretKey,
+    safe: Option<&SafeParams>,
+) -> Result<(), String> {
+    let new_commitment = public_key_commitment(&bbjj_private_key.public())?;
+
+    let sender = match safe {
+        Some(safe) => safe.address,
+        None => sender(&client)?,
+    };
+    let (zk_registry, old_commitment, captured_processes) =
+        check_registered_key(client, nouns_voting_address, sender)?;
+    if old_commitment == new_commitment {
+        return Err(format!("This key is already registered by {:?}", sender));
+    }
+    if old_commitment.is_zero() {
+        println!("No key was registered by {:?}", sender);
+    } else {
+        println!(
+            "Replacing the registered key with commitment {}",
+            format_registered_commitment(&old_commitment)
+        );
+    }
+    warn_captured_processes(&captured_processes, "old key");
+
+    if let Some(safe) = safe {
+        prepare_safe_registration(
+            &zk_registry,
+            &bbjj_private_key,
+            safe,
+            "Rotate Baby Jubjub public key",
+        )
+        .await?;
+        println!(
+            "{} Baby Jubjub public key rotation prepared for the Safe {:?}",
+            SPARKLE, safe.address
+        );
+        return Ok(());
+    }
+
+    let tx_hash = register_public_key(&zk_registry, &bbjj_private_key).await?;
 
-    let contract = ZKRegistry::new(zk_registry_address, client);
+    println!(
+        "{} Baby Jubjub public key rotated successfully (transaction hash {:?})",
+        SPARKLE, tx_hash
+    );
+    Ok(())
+}
 
-    let bbjj_pbk = bbjj_private_key.public();
+/// Function that removes the BBJJ Public Key registered by the sender from the ZKRegistry contract.
+/// With a Safe, the removal of the key of the Safe is prepared instead.
+pub async fn deregister_key<M: Middleware + Clone + 'static>(
+    client: M,
+    nouns_voting_address: Address,
+    safe: Option<&SafeParams>,
+) -> Result<(), String> {
+    let sender = match safe {
+        Some(safe) => safe.address,
+        None => sender(&client)?,
+    };
+    let (zk_registry, old_commitment, captured_processes) =
+        check_registered_key(client, nouns_voting_address, sender)?;
+    if old_commitment.is_zero() {
+        return Err(format!("No key is registered by {:?}", sender));
+    }
+    println!(
+        "Deregistering the key with commitment {}",
+        format_registered_commitment(&old_commitment)
+    );
+    warn_captured_processes(&captured_processes, "deregistered key");
 
-    let bbjj_pbk: [U256; 2] = wrap_into!(bbjj_pbk);
+    if let Some(safe) = safe {
+        let transaction = SafeTransaction::new(
+            &zk_registry.deregister(POSEIDON_INTERFACE_ID),
+            "key commitment deregistration",
+        )?;
+        safe::output_transactions(
+            safe,
+            zk_registry.client(),
+            &[transaction],
+            "Deregister Baby Jubjub public key",
+        )
+        .await?;
+        println!(
+            "{} Baby Jubjub public key deregistration prepared for the Safe {:?}",
+            SPARKLE, safe.address
+        );
+        return Ok(());
+    }
 
-    let (x_tx_hash, y_tx_hash) =
-        exec_with_progress("Submitting Baby Jubjub public key to registry", move || {
+    let tx_hash = exec_with_progress("Deregistering Baby Jubjub public key", {
+        let zk_registry = zk_registry.clone();
+        move || {
             let rt = Runtime::new().unwrap();
             rt.block_on(async {
-                let x_register_request = contract.register(INTERFACE_ID, wrap_into!(bbjj_pbk[0]));
-
-                let x_tx = x_register_request
-                    .send()
-                    .await
-                    .map_err(|e| format!("Error sending X coordinate registration tx {e:?}"))?;
+                let tx_hash = send_confirmed_tx(
+                    zk_registry.deregister(POSEIDON_INTERFACE_ID),
+                    "key commitment deregistration",
+                )
+                .await?;
 
-                let y_register_request =
-                    contract.register(INTERFACE_ID + 1, wrap_into!(bbjj_pbk[1]));
+                let registered_commitment =
+                    get_registered_commitment(&zk_registry, sender, None).await?;
+                if !registered_commitment.is_zero() {
+                    return Err(format!(
+                        "The key with commitment {} is still registered after the deregistration",
+                        format_registered_commitment(&registered_commitment)
+                    ));
+                }
 
-                let y_tx = y_register_request
-                    .send()
-                    .await
-                    .map_err(|_e| format!("Error sending Y coordinate registration tx"))?;
-                Ok((x_tx.tx_hash(), y_tx.tx_hash()))
+                Ok(tx_hash)
             })
+        }
+    })?;
+
+    println!(
+        "{} Baby Jubjub public key deregistered successfully (transaction hash {:?})",
+        SPARKLE, tx_hash
+    );
+    Ok(())
+}
+
+/// Function that delegates the votes of the NFTs of the sender to `delegatee` in the NFT contract of the census.
+/// With a Safe, the delegation of the NFTs of the Safe is prepared instead.
+pub async fn delegate<M: Middleware + Clone + 'static>(
+    client: M,
+    nouns_voting_address: Address,
+    delegatee: Address,
+    safe: Option<&SafeParams>,
+) -> Result<(), String> {
+    let sender = match safe {
+        Some(safe) => safe.address,
+        None => sender(&client)?,
+    };
+    let nouns_token_address = NounsVoting::new(nouns_voting_address, Arc::new(client.clone()))
+        .nouns_token()
+        .call()
+        .await
+        .map_err(|e| {
+            format!("Error getting the NounsToken address from the NounsVoting contract: {e:?}")
         })?;
+    let nouns_token = NounsToken::new(nouns_token_address, Arc::new(client));
+
+    let balance = nouns_token
+        .balance_of(sender)
+        .call()
+        .await
+        .map_err(|e| format!("Error getting the NFT balance of {:?}: {e:?}", sender))?;
+    if balance.is_zero() {
+        println!(
+            "{:?} holds no NFTs yet, the delegation applies to the NFTs it receives",
+            sender
+        );
+    }
+
+    if let Some(safe) = safe {
+        let transaction = SafeTransaction::new(&nouns_token.delegate(delegatee), "delegate")?;
+        safe::output_transactions(
+            safe,
+            nouns_token.client(),
+            &[transaction],
+            "Delegate NFT votes",
+        )
+        .await?;
+        println!(
+            "{} Delegation to {:?} prepared for the Safe {:?}",
+            SPARKLE, delegatee, safe.address
+        );
+        return Ok(());
+    }
+
+    let tx_hash = exec_with_progress("Delegating NFT votes", {
+        move || {
+            let rt = Runtime::new().unwrap();
+            rt.block_on(async {
+                let tx_hash =
+                    send_confirmed_tx(nouns_token.delegate(delegatee), "delegation").await?;
+
+                let delegates =
+                    nouns_token.delegates(sender).call().await.map_err(|e| {
+                        format!("Error getting the delegate of {:?}: {e:?}", sender)
+                    })?;
+                if delegates != delegatee {
+                    return Err(format!(
+                        "The NFTs of {:?} are delegated to {:?} after the delegation",
+                        sender, delegates
+                    ));
+                }
+
+                Ok(tx_hash)
+            })
+        }
+    })?;
 
     println!(
-        "{} Baby Jubjub public key registered successfully (transaction hash {})",
-        SPARKLE, x_tx_hash
+        "{} NFT votes delegated to {:?} successfully (transaction hash {:?})",
+        SPARKLE, delegatee, tx_hash
     );
     Ok(())
 }
 
 /// Function that creates a new voting process in the NounsVoting contract.
-pub async fn create_process(
-    client: SignerMiddleware<Provider<Http>, LocalWallet>,
-    eth_connection: Provider<Http>,
+/// With a Safe, the `createProcess` transaction is prepared for the Safe instead of being sent.
+/// Census blocks that have to be recorded beforehand are still recorded from the wallet, as anyone may record them.
+pub async fn create_process<M: Middleware + 'static>(
+    client: M,
+    state_connection: Provider<FailoverHttp>,
     contract_address: Address,
     ipfs_hash: H256,
     start_delay: Duration,
     process_duration: Duration,
+    census_block: Option<U64>,
+    safe: Option<&SafeParams>,
 ) -> Result<(), String> {
-    const ETH_BLOCK_TIME: u64 = 12;
-
     let client = Arc::new(client);
     let nouns_voting = NounsVoting::new(contract_address, client);
 
+    // A Safe creates the process whenever its owners execute the transaction, possibly after `blockhash`
+    // no longer covers the census block, so the census block is recorded by the wallet beforehand.
+    // Only blocks before the latest one can be linked to a later block, so it defaults to the parent of the latest one.
+    let census_block = match census_block {
+        None if safe.is_some() => Some(exec_with_progress("Fetching latest block", {
+            let state_connection = state_connection.clone();
+            move || {
+                let rt = Runtime::new().unwrap();
+                rt.block_on(async {
+                    state_connection
+                        .get_block_number()
+                        .await
+                        .map(|block_number| block_number - 1)
+                        .map_err(|e| format!("Error getting current block number: {}", e))
+                })
+            }
+        })?),
+        census_block => census_block,
+    };
+
+    // Census blocks out of reach of `blockhash` have to be recorded in the contract beforehand
+    let recorded_block_hash = match census_block {
+        Some(census_block_number) => record_census_block(
+            &nouns_voting,
+            &state_connection,
+            census_block_number,
+            safe.is_some(),
+        )?,
+        None => None,
+    };
+
     // Get number of blocks for start delay (rounded up)
     let start_delay = U64::from(start_delay.as_secs() / ETH_BLOCK_TIME + 1);
 
@@ -159,18 +576,31 @@ pub async fn create_process(
         move || {
             let rt = Runtime::new().unwrap();
             rt.block_on(async {
-                // First fetch current block number, block hash, block header and state root
-                let census_block_number = eth_connection
-                    .get_block_number()
-                    .await
-                    .map_err(|_| format!("Error getting current block number"))?;
-                let block = eth_connection
+                // First fetch census block number, block hash, block header and state root
+                let census_block_number = match census_block {
+                    Some(census_block_number) => census_block_number,
+                    None => state_connection
+                        .get_block_number()
+                        .await
+                        .map_err(|e| format!("Error getting current block number: {}", e))?,
+                };
+                let block = state_connection
                     .get_block(census_block_number)
                     .await
-                    .map_err(|_| format!("Error obtaining block data"))?
-                    .unwrap();
-                let block_hash = block.hash.unwrap();
+                    .map_err(|e| format!("Error obtaining block data: {}", e))?
+                    .ok_or(format!("Error: Block {} not found", census_block_number))?;
+                // The header is checked against the block hash when encoding it
                 let block_header = proofs::header_from_block(&block)?;
+                let block_hash = block.hash.unwrap();
+
+                if recorded_block_hash.is_some_and(|recorded_hash| recorded_hash != block_hash) {
+                    return Err(format!(
+                        "The hash of census block {} ({:?}) differs from the one recorded in the NounsVoting contract ({:?}). The chain may have been reorganised.",
+                        census_block_number,
+                        block_hash,
+                        recorded_block_hash.unwrap()
+                    ));
+                }
 
                 // Then fetch state proofs and storage hashes of the relevant contracts
                 // First fetch addresses from voting contract
@@ -188,37 +618,24 @@ pub async fn create_process(
 
                 // Then fetch state proofs
                 let zk_registry_state_proof = proofs::get_state_proof(
-                    &eth_connection,
+                    &state_connection,
                     census_block_number,
                     zk_registry_address,
                 )
                 .await?;
                 let nouns_token_contract_state_proof = proofs::get_state_proof(
-                    &eth_connection,
+                    &state_connection,
                     census_block_number,
                     nouns_token_address,
                 )
                 .await?;
 
-                // ...and storage roots
-                let zk_registry_storage_root = eth_connection
-                    .get_proof(
-                        zk_registry_address,
-                        vec![],
-                        Some(census_block_number.into()),
-                    )
-                    .await
-                    .map_err(|_| "Error fetching storage root")?
-                    .storage_hash;
-                let nouns_token_contract_storage_root = eth_connection
-                    .get_proof(
-                        nouns_token_address,
-                        vec![],
-                        Some(census_block_number.into()),
-                    )
-                    .await
-                    .map_err(|_| "Error fetching storage root")?
-                    .storage_hash;
+                // ...and storage roots, taken from the verified account states
+                let zk_registry_storage_root =
+                    mpt::AccountState::decode(&zk_registry_state_proof.value)?.storage_root;
+                let nouns_token_contract_storage_root =
+                    mpt::AccountState::decode(&nouns_token_contract_state_proof.value)?
+                        .storage_root;
                 Ok((
                     census_block_number,
                     block_hash,
@@ -287,22 +704,38 @@ pub async fn create_process(
     // Pass proof together with state root, storage roots and block number along to process creation request,
     // since the remaining public inputs lie (or may be obtained) within the contract itself
 
+    let create_process_request = nouns_voting.create_process(
+        ipfs_hash.into(),
+        start_delay.as_u64(),
+        process_duration.as_u64(),
+        tlcs_round_number,
+        wrap_into!(wrap_into!(tlcs_pbk)),
+        census_block_number.as_u64(),
+        zk_registry_storage_root.into(),
+        nouns_token_contract_storage_root.into(),
+        proof.into(),
+    );
+
+    if let Some(safe) = safe {
+        let transaction = SafeTransaction::new(&create_process_request, "createProcess")?;
+        safe::output_transactions(
+            safe,
+            nouns_voting.client(),
+            &[transaction],
+            "Create voting process",
+        )
+        .await?;
+        println!(
+            "{} Process creation with TLCS round number {} prepared for the Safe {:?}. Its ID is assigned when the Safe executes it.",
+            SPARKLE, tlcs_round_number, safe.address
+        );
+        return Ok(());
+    }
+
     let (tx_hash, process_id) = exec_with_progress("Submitting data to smart contract", {
         move || {
             let rt = Runtime::new().unwrap();
             rt.block_on(async {
-                let create_process_request = nouns_voting.create_process(
-                    ipfs_hash.into(),
-                    start_delay.as_u64(),
-                    process_duration.as_u64(),
-                    tlcs_round_number,
-                    wrap_into!(wrap_into!(tlcs_pbk)),
-                    census_block_number.as_u64(),
-                    zk_registry_storage_root.into(),
-                    nouns_token_contract_storage_root.into(),
-                    proof.into(),
-                );
-
                 let tx = create_process_request
                     .send()
                     .await
@@ -328,37 +761,41 @@ pub async fn create_process(
 }
 
 /// Function that votes in an existing voting process in the NounsVoting contract.
-pub async fn vote(
-    client: SignerMiddleware<Provider<Http>, LocalWallet>,
-    eth_connection: Provider<Http>,
+/// A receipt of the vote, encrypted with the registry private key, is saved in `receipt_dir`.
+/// With `challenge`, the voter may spoil ballots to check their encryption before casting one;
+/// spoiled ballots are saved in `receipt_dir` as well.
+pub async fn vote<M: Middleware + 'static>(
+    client: M,
+    state_connection: Provider<FailoverHttp>,
     voter_address: Option<Address>, // The address that is enrolled to vote
     nouns_voting_address: Address,
     process_id: U256,
     nft_id: U256,
     chain_id: U256,
-    bbjj_private_key: PrivateKey,
+    bbjj_private_key: SecretKey,
     vote_choice: VoteChoice,
+    token_layout: TokenStorageLayout,
+    receipt_dir: PathBuf,
+    challenge: bool,
 ) -> Result<(), String> {
+    // The vote circuit derives the storage slots from the NounsToken and ZKRegistry layouts
+    if token_layout != TokenStorageLayout::NOUNS {
+        return Err(
+            "The vote circuit only supports the NounsToken storage layout (`--token-layout nouns`)"
+                .to_string(),
+        );
+    }
+
     let client = Arc::new(client);
     let nouns_voting = NounsVoting::new(nouns_voting_address, client.clone());
 
-    // TODO: Factor out
     let ipfs_digest = nouns_voting
         .clone()
         .get_ipfs_hash(wrap_into!(process_id))
         .call()
         .await
         .map_err(|e| format!("Error fetching proposal's IPFS CID: {:?}", e))?;
-    let ipfs_cid_string = {
-        let mut multihash_bytes: Vec<u8> = vec![0x12, 0x20];
-        let mut ipfs_digest = ipfs_digest.to_vec();
-        multihash_bytes.append(&mut ipfs_digest);
-        let cid_multihash = multihash::Multihash::from_bytes(&multihash_bytes)
-            .map_err(|e| format!("Error parsing CID multihash bytes: {}", e))?;
-        let cid = cid::Cid::new_v1(0x55, cid_multihash);
-        cid.to_string_of_base(multibase::Base::Base32Lower)
-            .map_err(|e| format!("Could not form CID string: {}", e))?
-    };
+    let ipfs_cid_string = crate::parsers::format_ipfs_cid(H256::from(ipfs_digest))?;
 
     let tlcs_pbk = {
         let tlcs_round_number = nouns_voting
@@ -398,14 +835,13 @@ pub async fn vote(
     let (
         voter_address,
         registry_account_state_hash,
-        registry_account_state_proof_x,
+        registry_account_state_proof,
         nft_account_state_hash,
         nft_account_state_proof,
         delegation_proof,
     ) = exec_with_progress("Fetching data from blockchain", {
-        let bbjj_private_key = PrivateKey {
-            key: bbjj_private_key.key.clone(),
-        };
+        // Only the public key is needed to check the registry, so the private key stays here
+        let bbjj_public_key = bbjj_private_key.public();
         let nouns_voting = nouns_voting.clone();
         move || {
             let rt = Runtime::new().unwrap();
@@ -414,7 +850,7 @@ pub async fn vote(
                                              format!("Error getting the NounsToken address from the NounsVoting contract: {e:?}")
                                          })?;
 
-                                         let nouns_token = NounsToken::new(nouns_token_address, client.clone());
+                                         let nouns_token = NounsToken::new(nouns_token_address, Arc::new(state_connection.clone()));
                                          let nft_owner = nouns_token
                                              .owner_of(wrap_into!(nft_id))
                                              .call()
@@ -426,28 +862,28 @@ pub async fn vote(
                                          // If no voter address was specified, assume it is the NFT owner.
                                          let voter_address = voter_address.unwrap_or(nft_owner);
 
-                                         let census_block_number = nouns_voting
-                                             .get_census_block(wrap_into!(process_id))
-                                             .call()
-                                             .await
-                                             .map_err(|_| format!("Error getting census block number"))?;
+                                         let voting_process = get_voting_process(&nouns_voting, wrap_into!(process_id)).await?;
+                                         let census_block_number = voting_process.census_block;
 
                                          let zk_registry_address = nouns_voting.zk_registry().call().await.map_err(|e| {
                                              format!("Error getting the ZKRegistry address from the NounsVoting contract: {e:?}")
                                          })?;
 
-                                         let (registry_account_state_hash, registry_account_state_proof_x) = proofs::get_zk_registry_proof(
-                                             &eth_connection,
+                                         // Make sure the node can serve the census the process was created with
+                                         check_census_block(&state_connection, &voting_process, zk_registry_address).await?;
+
+                                         let (registry_account_state_hash, registry_account_state_proof) = proofs::get_zk_registry_proof(
+                                             &state_connection,
                                              voter_address,
                                              U64::from(census_block_number),
                                              zk_registry_address,
+                                             &token_layout,
                                          )
                                              .await?;
 
                                          // Check that the storage proof is correct
-                                         // TODO: Also check roots against contract
-                                         let expected_value: [U256; 2] = wrap_into!(bbjj_private_key.public());
-                                         if registry_account_state_proof_x.value != wrap_into!(expected_value[0]) {
+                                         let expected_value: U256 = wrap_into!(key_commitment(&bbjj_public_key)?);
+                                         if registry_account_state_proof.value != wrap_into!(expected_value) {
                                              return Err(format!(
                                                  "The public key you specified is invalid or does not exist. Are you sure you enrolled to vote?"
                                              ));
@@ -455,59 +891,97 @@ pub async fn vote(
 
                                          // Fetch NFT ownership proof
                                          let (nft_account_state_hash, nft_account_state_proof) = proofs::get_nft_ownership_proof(
-                                             eth_connection.clone(),
+                                             state_connection.clone(),
                                              wrap_into!(nft_id),
                                              U64::from(census_block_number),
                                              nouns_token_address,
+                                             &token_layout,
                                          )
                                              .await?;
 
                                          // ...as well as delegation proof
                                          let (_, delegation_proof) = proofs::get_delegation_proof(
-                                             eth_connection,
+                                             state_connection,
                                              nft_owner,
                                              U64::from(census_block_number),
                                              nouns_token_address,
+                                             &token_layout,
                                          )
                                              .await?;
 
+                                         // The contract verifies the vote proof against the roots recorded at process creation
+                                         check_census_roots(&voting_process, registry_account_state_hash, nft_account_state_hash)?;
+
                                          // Check that the NFT ownership proof implies that voter_address is the owner
                                          // *or* nft_owner has delegated to voter_address
-                                         if (nft_account_state_proof.value != EthersU256::from_big_endian(&nft_owner.as_bytes())) & (delegation_proof.value != EthersU256::from_big_endian(&voter_address.as_bytes())) {
+                                         let proven_owner = token_layout.decode_owner(nft_account_state_proof.value)?;
+                                         let proven_delegate = token_layout.decode_delegate(delegation_proof.value)?;
+                                         if (proven_owner != Some(nft_owner)) & (proven_delegate != Some(voter_address)) {
                                              return Err(format!(
                                                  "Error: The voter is neither the owner of the NFT nor its delegate."
                                              ));
                                          }
                                          Ok((voter_address,
-                                             registry_account_state_hash, registry_account_state_proof_x,
+                                             registry_account_state_hash, registry_account_state_proof,
                                              nft_account_state_hash, nft_account_state_proof, delegation_proof))
                                      })
         }
     })?;
 
-    let (ballot, proof) = exec_with_progress(
-        "Generating vote proof (this might take a while)",
-        move || {
-            let rng = &mut rand::thread_rng();
+    // The receipt is encrypted with the key the vote was cast with
+    let receipt_password = Zeroizing::new(bbjj_private_key.key);
+
+    let voter = Voter::new(voter_address, bbjj_private_key);
+    let prepare_ballot = || {
+        voter
+            .prepare_ballot(
+                nft_id,
+                vote_choice,
+                process_id,
+                nouns_voting_address,
+                chain_id,
+                tlcs_pbk.clone(),
+                &mut rand::thread_rng(),
+            )
+            .map_err(|e| format!("Error encrypting vote: {}", e))
+    };
+    let mut prepared_ballot = prepare_ballot()?;
 
-            let voter = Voter::new(voter_address, bbjj_private_key);
+    // Every spoiled ballot reveals its blinding factor, so a fresh one is encrypted until one is cast
+    let mut cast = !challenge;
+    while !cast {
+        println!(
+            "Ballot commitment: {:#x}",
+            receipts::fr_to_u256(prepared_ballot.commitment()?)
+        );
+        match prompt("Cast this ballot, or spoil it to check its encryption? [cast/spoil]")?.as_str()
+        {
+            "cast" | "c" => cast = true,
+            "spoil" | "s" => {
+                let path = receipts::save_spoiled_ballot(&prepared_ballot.spoil(), &receipt_dir)?;
+                println!(
+                    "Ballot spoiled and saved to {}. Check it with `verify-spoiled-ballot`.",
+                    path.display()
+                );
+                prepared_ballot = prepare_ballot()?;
+            }
+            answer => println!("Unknown answer \"{}\"", answer),
+        }
+    }
 
+    let (ballot, receipt, proof) = exec_with_progress(
+        "Generating vote proof (this might take a while)",
+        move || {
             voter
-                .gen_vote(
-                    nft_id,
-                    vote_choice,
-                    process_id,
-                    nouns_voting_address,
-                    chain_id,
-                    tlcs_pbk,
+                .prove_ballot(
+                    prepared_ballot,
                     wrap_into!(nft_account_state_hash),
                     wrap_into!(registry_account_state_hash),
                     (
                         nft_account_state_proof.clone(),
-                        registry_account_state_proof_x.clone(),
+                        registry_account_state_proof.clone(),
                         delegation_proof.clone(),
                     ),
-                    rng,
                 )
                 .map_err(|e| format!("Error generating vote proof: {}", e))
         },
@@ -542,15 +1016,35 @@ pub async fn vote(
         SPARKLE, tx_hash
     );
 
+    let chain_id: EthersU256 = wrap_into!(chain_id);
+    let receipt_path = StoredReceipt {
+        receipt,
+        chain_id: chain_id.as_u64(),
+        contract_address: nouns_voting_address,
+        transaction_hash: tx_hash,
+    }
+    .save(&receipt_dir, receipt_password.as_slice())
+    .map_err(|e| {
+        format!(
+            "The vote was submitted (transaction hash {}) but its receipt could not be saved. {}",
+            tx_hash, e
+        )
+    })?;
+    println!(
+        "Vote receipt saved to {}. Check the vote with `check-vote`.",
+        receipt_path.display()
+    );
+
     Ok(())
 }
 
 /// Function to tally the votes in an existing voting process in the NounsVoting contract.
-pub async fn tally(
-    client: SignerMiddleware<Provider<Http>, LocalWallet>,
+pub async fn tally<M: Middleware + 'static>(
+    client: M,
     nouns_voting_address: Address,
     chain_id: U256,
     process_id: U256,
+    index_path: Option<PathBuf>,
 ) -> Result<(), String> {
     let client = Arc::new(client);
     let nouns_voting = NounsVoting::new(nouns_voting_address, client.clone());
@@ -598,62 +1092,55 @@ pub async fn tally(
         move || {
             let rt = Runtime::new().unwrap();
             rt.block_on(async {
-                let process_start_block = nouns_voting
-                    .get_start_block(wrap_into!(process_id))
-                    .call()
-                    .await
-                    .map_err(|_| format!("Error getting start block number"))
-                    .unwrap();
-
-                let filter = nouns_voting
-                    .ballot_cast_filter()
-                    .filter
-                    .from_block(U64::from(process_start_block));
+                let process_ballots = match index_path {
+                    // Read the ballots from the index if there is one
+                    Some(index_path) => {
+                        let index = Index::open(&index_path)?;
+                        let chain_id: EthersU256 = wrap_into!(chain_id);
+                        index.check_source(chain_id.as_u64(), nouns_voting_address)?;
+                        indexer::check_head(&index, client.as_ref()).await?;
+
+                        if let Some(tally) = index.tally(wrap_into!(process_id))? {
+                            return Err(format!(
+                                "Voting process {} was already tallied in block {}",
+                                tally.process_id, tally.block_number
+                            ));
+                        }
 
-                let logs = client.get_logs(&filter).await.map_err(|_| {
-                    format!(
-                        "Error getting the logs for the voting process with id {}",
-                        process_id
-                    )
-                })?;
+                        let process_ballots = index.ballots(wrap_into!(process_id))?;
+                        if !process_ballots.complete {
+                            return Err(format!(
+                                "The voting period is not indexed yet (ballots indexed up to block {}). Run the `index` command again later.",
+                                process_ballots.to_block
+                            ));
+                        }
 
-                let mut ballots: Vec<TruncatedBallot> = Vec::new();
-                for log in logs {
-                    // Check that the log is of correct form:
-                    if log.topics.len() != 4 {
-                        return Err(format!(
-                            "Error: The log with transaction hash {:?} is not of the correct form.",
-                            log.transaction_hash
-                        ));
+                        process_ballots
                     }
+                    None => {
+                        let process_ballots =
+                            ballots::fetch_ballots(&nouns_voting, wrap_into!(process_id)).await?;
+                        if !process_ballots.complete {
+                            return Err(format!(
+                                "The voting period is not finalized yet (ballots fetched up to block {}). Try again later.",
+                                process_ballots.to_block
+                            ));
+                        }
 
-                    let log_process_id: U256 = wrap_into!(H256::from(<[u8;32]>::try_from(log.data.as_ref()).map_err(|e| format!("Could not convert log process ID to byte array: {}", e))?).into_uint());
-                    if log_process_id == process_id
-                    {
-                        let a_x: U256 = wrap_into!(log.topics[1].into_uint());
-                        let a_y: U256 = wrap_into!(log.topics[2].into_uint());
-                        let b: U256 = wrap_into!(log.topics[3].into_uint());
-
-                        let truncated_ballot = TruncatedBallot {
-                            a: wrap_into!([a_x, a_y]),
-                            b: wrap_into!(b),
-                        };
-
-                        ballots.push(truncated_ballot);
+                        process_ballots
                     }
-                }
-                
+                };
+
                 // Get the ballot hash
                 let ballot_hash = nouns_voting
                     .get_ballots_hash(wrap_into!(process_id))
                     .call()
                     .await
-                    .map_err(|_| format!("Error getting ballot hash"))
-                    .unwrap();
+                    .map_err(|e| format!("Error getting ballot hash: {e:?}"))?;
 
                 let ballot_hash: U256 = wrap_into!(ballot_hash);
 
-                Ok((ballots, ballot_hash))
+                Ok((process_ballots.truncated_ballots(), ballot_hash))
             })
         }
     })?;
@@ -699,58 +1186,1016 @@ pub async fn tally(
     Ok(())
 }
 
-/// This function will try to help mine the blocks until the specified block number
-/// It will do transactions to increase the block number, only valid for local testing
-pub async fn mine_blocks_until(
-    eth_connection: Provider<Http>,
-    wallet_address: Address,
-    client: &SignerMiddleware<Provider<Http>, Wallet<SigningKey>>,
-    target_block: u64,
+/// Function that checks the tally result submitted for a voting process without proving it again.
+/// It checks the ballots against the ballots hash, the TLCS private key against the public key
+/// the ballots were encrypted with, decrypts the ballots and compares the result with the submitted one.
+/// The ballots are read from the index at `index_path` if one is given.
+/// It ends with a JSON report of the checks and fails if any of them does.
+pub async fn audit(
+    eth_connection: Provider<FailoverHttp>,
+    nouns_voting_address: Address,
+    chain_id: U256,
+    process_id: U256,
+    index_path: Option<PathBuf>,
 ) -> Result<(), String> {
-    // Get the current block number
-    let mut current_block = eth_connection
-        .get_block_number()
-        .await
-        .expect("Error getting current block number");
+    let process_number: EthersU256 = wrap_into!(process_id);
 
-    if current_block.as_u64() < target_block {
-        println!(
-            "Target block not yet reached! Going to mine the next {} blocks.",
-            target_block - current_block.as_u64()
-        );
-    }
+    let (voting_process, process_ballots, tlcs_keypair) = exec_with_progress(
+        "Fetching voting process and ballots",
+        move || {
+            let rt = Runtime::new().unwrap();
+            rt.block_on(async {
+                let nouns_voting =
+                    NounsVoting::new(nouns_voting_address, Arc::new(eth_connection.clone()));
+                let voting_process =
+                    get_voting_process(&nouns_voting, wrap_into!(process_id)).await?;
+                if !voting_process.tallied {
+                    return Err(format!(
+                        "Voting process {} has no tally result to audit",
+                        process_number
+                    ));
+                }
 
-    // Check that the process has ended
-    while current_block.as_u64() < target_block {
-        // Do transactions to increase the block number
+                let process_ballots = match index_path {
+                    Some(index_path) => {
+                        let index = Index::open(&index_path)?;
+                        let chain_id: EthersU256 = wrap_into!(chain_id);
+                        index.check_source(chain_id.as_u64(), nouns_voting_address)?;
+                        indexer::check_head(&index, &eth_connection).await?;
+                        index.ballots(wrap_into!(process_id))?
+                    }
+                    None => ballots::fetch_ballots(&nouns_voting, wrap_into!(process_id)).await?,
+                };
+                if !process_ballots.complete {
+                    return Err(format!(
+                        "The ballots of the voting period are not available yet (up to block {}). Try again later.",
+                        process_ballots.to_block
+                    ));
+                }
 
-        let tx = TransactionRequest::pay(wallet_address, 10);
-        let sent_tx = client
-            .send_transaction(tx, None)
-            .await
-            .map_err(|_e| format!("Error sending transaction to increase block number"))?;
-        let _receipt = sent_tx.await.map_err(|_e| {
-            format!(
-                "Error waiting for transaction to increase block number: {:?}",
-                _e
-            )
-        })?;
+                let tlcs_keypair =
+                    tlcs::get_bjj_keypair_strings(voting_process.tlcs_round_number).await;
 
-        current_block = eth_connection
-            .get_block_number()
-            .await
-            .expect("Error getting current block number");
-    }
-    println!("Target block reached! Mining finished.");
-    Ok(())
+                Ok((voting_process, process_ballots, tlcs_keypair))
+            })
+        },
+    )?;
+
+    let mut report = CheckReport::default();
+
+    // The ballots hash chains the ballots in the order they were cast
+    let ballots = process_ballots.truncated_ballots();
+    let ballots_hash: U256 = wrap_into!(Tallier::ballots_hash(&ballots)?);
+    let ballots_hash: EthersU256 = wrap_into!(ballots_hash);
+    report.check(
+        "ballots_hash",
+        ballots_hash == voting_process.ballots_hash,
+        format!(
+            "{} ballots hash to {:#x}, the contract holds {:#x}",
+            ballots.len(),
+            ballots_hash,
+            voting_process.ballots_hash
+        ),
+    );
+
+    // The TLCS private key must match the public key the ballots were encrypted with
+    let tlcs_prk = match tlcs_keypair {
+        Ok((_, tlcs_prk_string)) if !tlcs_prk_string.is_empty() => {
+            let tlcs_prk = crate::parsers::parse_bbjj_prk(&tlcs_prk_string)?;
+            let tlcs_pbk: [U256; 2] = wrap_into!(BBJJ_G1.mul_scalar(&tlcs_prk));
+            let expected_tlcs_pbk: [U256; 2] =
+                voting_process.tlcs_public_key.map(|x| wrap_into!(x));
+            let pass = report.check(
+                "tlcs_key",
+                tlcs_pbk == expected_tlcs_pbk,
+                format!(
+                    "The private key of TLCS round {} {} the public key of the voting process",
+                    voting_process.tlcs_round_number,
+                    if tlcs_pbk == expected_tlcs_pbk {
+                        "matches"
+                    } else {
+                        "does not match"
+                    }
+                ),
+            );
+            pass.then_some(tlcs_prk)
+        }
+        Ok(_) => {
+            report.check(
+                "tlcs_key",
+                false,
+                format!(
+                    "The private key of TLCS round {} is not released yet",
+                    voting_process.tlcs_round_number
+                ),
+            );
+            None
+        }
+        Err(e) => {
+            report.check(
+                "tlcs_key",
+                false,
+                format!("Error fetching the TLCS private key: {}", e),
+            );
+            None
+        }
+    };
+
+    // Decrypt the ballots and compare the result with the submitted one
+    if let Some(tlcs_prk) = tlcs_prk {
+        match Tallier::decrypt(
+            &ballots,
+            tlcs_prk,
+            chain_id,
+            process_id,
+            nouns_voting_address,
+        ) {
+            Ok(tally) => {
+                report.check(
+                    "decryption",
+                    true,
+                    format!("{} ballots decrypted", ballots.len()),
+                );
+
+                let vote_count = tally.vote_count.map(EthersU256::from);
+                let submitted_vote_count = [
+                    voting_process.votes_against,
+                    voting_process.votes_for,
+                    voting_process.votes_abstain,
+                ];
+                report.check(
+                    "tally",
+                    vote_count == submitted_vote_count,
+                    format!(
+                        "Decrypted For: {}, Against: {}, Abstain: {}; submitted For: {}, Against: {}, Abstain: {}",
+                        vote_count[1],
+                        vote_count[0],
+                        vote_count[2],
+                        submitted_vote_count[1],
+                        submitted_vote_count[0],
+                        submitted_vote_count[2]
+                    ),
+                );
+            }
+            Err(e) => {
+                report.check("decryption", false, e);
+            }
+        }
+    }
+
+    // Checks are skipped when an earlier one fails, so all four must have run
+    report.finish(
+        4,
+        serde_json::json!({ "process_id": process_number.to_string() }),
+        "The audit of the tally failed",
+    )
+}
+
+/// Function that checks a vote against its receipt: that the ballot encrypts the recorded choice,
+/// that it was cast in the voting process and is part of its ballots hash and,
+/// once the process is tallied, that it decrypts to the recorded choice.
+/// The ballots are read from the index at `index_path` if one is given.
+/// It ends with a JSON report of the checks and fails if any of them does.
+pub async fn check_vote(
+    eth_connection: Provider<FailoverHttp>,
+    nouns_voting_address: Address,
+    chain_id: U256,
+    receipt_path: PathBuf,
+    bbjj_private_key: SecretKey,
+    index_path: Option<PathBuf>,
+) -> Result<(), String> {
+    let stored = StoredReceipt::load(&receipt_path, &bbjj_private_key.key)?;
+    let chain_number: EthersU256 = wrap_into!(chain_id);
+    if stored.chain_id != chain_number.as_u64() || stored.contract_address != nouns_voting_address {
+        return Err(format!(
+            "The receipt is for a vote in contract {:?} on chain {}",
+            stored.contract_address, stored.chain_id
+        ));
+    }
+    let process_id = stored.receipt.process_id;
+    let process_number: EthersU256 = wrap_into!(process_id);
+
+    let (voting_process, process_ballots, contract_ballots_hash, tlcs_keypair) =
+        exec_with_progress("Fetching voting process and ballots", move || {
+            let rt = Runtime::new().unwrap();
+            rt.block_on(async {
+                let nouns_voting =
+                    NounsVoting::new(nouns_voting_address, Arc::new(eth_connection.clone()));
+                let voting_process = get_voting_process(&nouns_voting, process_number).await?;
+
+                let process_ballots = match index_path {
+                    Some(index_path) => {
+                        let index = Index::open(&index_path)?;
+                        index.check_source(chain_number.as_u64(), nouns_voting_address)?;
+                        indexer::check_head(&index, &eth_connection).await?;
+                        index.ballots(process_number)?
+                    }
+                    None => ballots::fetch_ballots(&nouns_voting, process_number).await?,
+                };
+
+                // The ballots hash as of the last block the ballots were collected from
+                let contract_ballots_hash = nouns_voting
+                    .get_ballots_hash(process_number)
+                    .block(process_ballots.to_block)
+                    .call()
+                    .await
+                    .map_err(|e| {
+                        format!(
+                            "Error fetching the ballots hash at block {}: {}",
+                            process_ballots.to_block, e
+                        )
+                    })?;
+
+                let tlcs_keypair = if voting_process.tallied {
+                    Some(tlcs::get_bjj_keypair_strings(voting_process.tlcs_round_number).await)
+                } else {
+                    None
+                };
+
+                Ok((
+                    voting_process,
+                    process_ballots,
+                    contract_ballots_hash,
+                    tlcs_keypair,
+                ))
+            })
+        })?;
+
+    let mut report = CheckReport::default();
+
+    // The ballot must encrypt the recorded choice under the TLCS public key of the process
+    let tlcs_pbk = receipts::tlcs_public_key(voting_process.tlcs_public_key);
+    let cast_as_intended = stored
+        .receipt
+        .verify(&tlcs_pbk, nouns_voting_address, chain_id)?;
+    report.check(
+        "cast_as_intended",
+        cast_as_intended,
+        format!(
+            "The ballot {} a vote \"{}\"",
+            if cast_as_intended {
+                "encrypts"
+            } else {
+                "does not encrypt"
+            },
+            stored.receipt.vote_choice
+        ),
+    );
+
+    let (a, b) = (stored.a(), stored.b());
+    let position = process_ballots
+        .ballots
+        .iter()
+        .position(|ballot| [ballot.event.a_x, ballot.event.a_y] == a && ballot.event.b == b);
+    match position {
+        Some(i) => {
+            let ballot = &process_ballots.ballots[i];
+            report.check(
+                "included",
+                true,
+                format!(
+                    "Ballot {} of {} was cast in block {} by transaction {:?}",
+                    i + 1,
+                    process_ballots.ballots.len(),
+                    ballot.block_number,
+                    ballot.transaction_hash
+                ),
+            );
+        }
+        None if process_ballots.complete => {
+            report.check(
+                "included",
+                false,
+                format!(
+                    "The ballot is not among the {} ballots of the voting process",
+                    process_ballots.ballots.len()
+                ),
+            );
+        }
+        None => report.pending(
+            "included",
+            format!(
+                "The ballot is not among the ballots cast up to the finalized block {}",
+                process_ballots.to_block
+            ),
+        ),
+    }
+
+    let ballots = process_ballots.truncated_ballots();
+    let ballots_hash: U256 = wrap_into!(Tallier::ballots_hash(&ballots)?);
+    let ballots_hash: EthersU256 = wrap_into!(ballots_hash);
+    report.check(
+        "ballots_hash",
+        ballots_hash == contract_ballots_hash,
+        format!(
+            "{} ballots hash to {:#x}, the contract held {:#x} at block {}",
+            ballots.len(),
+            ballots_hash,
+            contract_ballots_hash,
+            process_ballots.to_block
+        ),
+    );
+
+    // Decrypting the ballot on its own shows how it was counted
+    match tlcs_keypair {
+        None => report.pending(
+            "counted",
+            "The voting process is not tallied yet".to_string(),
+        ),
+        Some(Ok((_, tlcs_prk_string))) if !tlcs_prk_string.is_empty() => {
+            let tlcs_prk = crate::parsers::parse_bbjj_prk(&tlcs_prk_string)?;
+            let ballot = nouns_protocol::TruncatedBallot {
+                a: stored.receipt.a.clone(),
+                b: stored.receipt.b,
+            };
+            match Tallier::decrypt(
+                &[ballot],
+                tlcs_prk,
+                chain_id,
+                process_id,
+                nouns_voting_address,
+            ) {
+                Ok(tally) => {
+                    let choice = stored.receipt.vote_choice as usize;
+                    report.check(
+                        "counted",
+                        tally.vote_count[choice] == 1,
+                        format!(
+                            "The ballot decrypts to For: {}, Against: {}, Abstain: {}",
+                            tally.vote_count[1], tally.vote_count[0], tally.vote_count[2]
+                        ),
+                    );
+                }
+                Err(e) => {
+                    report.check("counted", false, e);
+                }
+            }
+        }
+        Some(Ok(_)) => report.pending(
+            "counted",
+            format!(
+                "The private key of TLCS round {} is not released yet",
+                voting_process.tlcs_round_number
+            ),
+        ),
+        Some(Err(e)) => {
+            report.check(
+                "counted",
+                false,
+                format!("Error fetching the TLCS private key: {}", e),
+            );
+        }
+    }
+
+    report.finish(
+        4,
+        serde_json::json!({
+            "process_id": process_number.to_string(),
+            "vote_choice": stored.receipt.vote_choice.to_string(),
+            "transaction_hash": format!("{:?}", stored.transaction_hash),
+        }),
+        "The vote check failed",
+    )
+}
+
+/// Function that checks a ballot spoiled with `vote --challenge`, independently of the vote that
+/// followed: that it was encrypted to the TLCS public key of its voting process, that it encrypts
+/// its claimed vote choice and, if given, that it matches the commitment shown before spoiling it.
+/// It ends with a JSON report of the checks and fails if any of them does.
+pub async fn verify_spoiled_ballot(
+    eth_connection: Provider<FailoverHttp>,
+    nouns_voting_address: Address,
+    chain_id: U256,
+    ballot_path: PathBuf,
+    commitment: Option<U256>,
+) -> Result<(), String> {
+    let spoiled_ballot = receipts::load_spoiled_ballot(&ballot_path)?;
+    if spoiled_ballot.chain_id != chain_id || spoiled_ballot.contract_addr != nouns_voting_address {
+        let ballot_chain_id: EthersU256 = wrap_into!(spoiled_ballot.chain_id);
+        return Err(format!(
+            "The ballot is for a vote in contract {:?} on chain {}",
+            spoiled_ballot.contract_addr, ballot_chain_id
+        ));
+    }
+    let process_number: EthersU256 = wrap_into!(spoiled_ballot.process_id);
+
+    let voting_process = exec_with_progress("Fetching voting process", move || {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let nouns_voting = NounsVoting::new(nouns_voting_address, Arc::new(eth_connection));
+            get_voting_process(&nouns_voting, process_number).await
+        })
+    })?;
+
+    let mut report = CheckReport::default();
+
+    let tlcs_pbk = receipts::tlcs_public_key(voting_process.tlcs_public_key);
+    report.check(
+        "tlcs_key",
+        spoiled_ballot.tlcs_pk == tlcs_pbk,
+        format!(
+            "The ballot was encrypted to {} TLCS public key of voting process {}",
+            if spoiled_ballot.tlcs_pk == tlcs_pbk {
+                "the"
+            } else {
+                "another key than the"
+            },
+            process_number
+        ),
+    );
+
+    // Recompute `K = tlcs_pk^r` from the revealed blinding factor and the ballot from `K`
+    let encrypts_choice = spoiled_ballot.verify()?;
+    report.check(
+        "encryption",
+        encrypts_choice,
+        format!(
+            "The ballot {} a vote \"{}\"",
+            if encrypts_choice {
+                "encrypts"
+            } else {
+                "does not encrypt"
+            },
+            spoiled_ballot.vote_choice
+        ),
+    );
+
+    let ballot_commitment = receipts::fr_to_u256(spoiled_ballot.commitment()?);
+    match commitment {
+        Some(commitment) => {
+            let commitment: EthersU256 = wrap_into!(commitment);
+            report.check(
+                "commitment",
+                commitment == ballot_commitment,
+                format!(
+                    "The ballot commits to {:#x}, the commitment shown was {:#x}",
+                    ballot_commitment, commitment
+                ),
+            );
+        }
+        None => report.pending(
+            "commitment",
+            format!(
+                "The ballot commits to {:#x}. Compare it with the commitment shown before spoiling it.",
+                ballot_commitment
+            ),
+        ),
+    }
+
+    report.finish(
+        3,
+        serde_json::json!({
+            "process_id": process_number.to_string(),
+            "vote_choice": spoiled_ballot.vote_choice.to_string(),
+        }),
+        "The spoiled ballot check failed",
+    )
+}
+
+/// Function that prints the details of a voting process: its proposal, census and voting period,
+/// phase, TLCS key availability, ballots and tally result.
+/// The ballots are counted from the index at `index_path` if one is given.
+pub async fn status(
+    eth_connection: Provider<FailoverHttp>,
+    nouns_voting_address: Address,
+    process_id: U256,
+    index_path: Option<PathBuf>,
+) -> Result<(), String> {
+    let process_id: EthersU256 = wrap_into!(process_id);
+
+    let (voting_process, latest_block, block_times, process_ballots, tlcs_keypair) =
+        exec_with_progress("Fetching voting process", move || {
+            let rt = Runtime::new().unwrap();
+            rt.block_on(async {
+                let nouns_voting =
+                    NounsVoting::new(nouns_voting_address, Arc::new(eth_connection.clone()));
+                let voting_process = get_voting_process(&nouns_voting, process_id).await?;
+
+                let latest_block = eth_connection
+                    .get_block(BlockNumber::Latest)
+                    .await
+                    .map_err(|e| format!("Error getting latest block: {}", e))?
+                    .ok_or("Latest block not found")?;
+                let latest_block_number = latest_block.number.ok_or("Latest block is pending")?;
+
+                let mut block_times = vec![];
+                for block_number in [
+                    voting_process.census_block,
+                    voting_process.start_block,
+                    voting_process.end_block,
+                ] {
+                    block_times.push(
+                        estimate_block_time(&eth_connection, &latest_block, block_number).await?,
+                    );
+                }
+
+                let process_ballots = match index_path {
+                    Some(index_path) => {
+                        let index = Index::open(&index_path)?;
+                        let chain_id = eth_connection
+                            .get_chainid()
+                            .await
+                            .map_err(|e| format!("Error getting chain id: {}", e))?;
+                        index.check_source(chain_id.as_u64(), nouns_voting_address)?;
+                        indexer::check_head(&index, &eth_connection).await?;
+                        index.ballots(process_id)?
+                    }
+                    None => ballots::fetch_ballots(&nouns_voting, process_id).await?,
+                };
+
+                // The TLCS service being unreachable does not prevent showing the rest
+                let tlcs_keypair =
+                    tlcs::get_bjj_keypair_strings(voting_process.tlcs_round_number).await;
+
+                Ok((
+                    voting_process,
+                    latest_block_number.as_u64(),
+                    block_times,
+                    process_ballots,
+                    tlcs_keypair,
+                ))
+            })
+        })?;
+
+    let phase = ProcessPhase::of(&voting_process, latest_block);
+
+    let tlcs_keys = match tlcs_keypair {
+        Ok((public_key, _)) if public_key.is_empty() => "public key not published".to_string(),
+        Ok((_, private_key)) if private_key.is_empty() => {
+            "public key published, private key not released yet".to_string()
+        }
+        Ok(_) => "public and private keys released".to_string(),
+        Err(e) => format!("unavailable ({})", e),
+    };
+
+    println!("Voting process {}", process_id);
+    println!(
+        "  Proposal:      ipfs://{}",
+        crate::parsers::format_ipfs_cid(H256::from(voting_process.ipfs_hash))?
+    );
+    println!("  Phase:         {} (latest block {})", phase, latest_block);
+    println!(
+        "  Census block:  {} ({})",
+        voting_process.census_block, block_times[0]
+    );
+    println!(
+        "  Start block:   {} ({})",
+        voting_process.start_block, block_times[1]
+    );
+    println!(
+        "  End block:     {} ({})",
+        voting_process.end_block, block_times[2]
+    );
+    println!(
+        "  TLCS round:    {} ({}), {}",
+        voting_process.tlcs_round_number,
+        format_timestamp(tlcs::round_timestamp(voting_process.tlcs_round_number)),
+        tlcs_keys
+    );
+    println!(
+        "  Ballots:       {} (up to block {})",
+        process_ballots.ballots.len(),
+        process_ballots.to_block
+    );
+    println!("  Ballots hash:  {:#x}", voting_process.ballots_hash);
+    if voting_process.tallied {
+        println!(
+            "  Tally:         For: {}, Against: {}, Abstain: {}",
+            voting_process.votes_for, voting_process.votes_against, voting_process.votes_abstain
+        );
+    }
+
+    Ok(())
+}
+
+/// A voting process as listed by `list-processes`
+struct ListedProcess {
+    process_id: EthersU256,
+    ipfs_hash: H256,
+    census_block: u64,
+    start_block: u64,
+    end_block: u64,
+    /// The votes for, against and abstaining, once tallied
+    result: Option<[EthersU256; 3]>,
+}
+
+/// Function that lists the voting processes of the NounsVoting contract with their blocks and tally status,
+/// as a table or as JSON. Only the processes in one of the given phases are listed, if any are given.
+/// The processes are read from the index at `index_path` if one is given, as of its last block.
+pub async fn list_processes(
+    eth_connection: Provider<FailoverHttp>,
+    nouns_voting_address: Address,
+    phases: Vec<ProcessPhase>,
+    json: bool,
+    index_path: Option<PathBuf>,
+) -> Result<(), String> {
+    let (voting_processes, latest_block) =
+        exec_with_progress("Fetching voting processes", move || {
+            let rt = Runtime::new().unwrap();
+            rt.block_on(async {
+                if let Some(index_path) = index_path {
+                    let index = Index::open(&index_path)?;
+                    let chain_id = eth_connection
+                        .get_chainid()
+                        .await
+                        .map_err(|e| format!("Error getting chain id: {}", e))?;
+                    index.check_source(chain_id.as_u64(), nouns_voting_address)?;
+                    indexer::check_head(&index, &eth_connection).await?;
+                    let head = index
+                        .head()?
+                        .map(|(block_number, _)| block_number.as_u64())
+                        .unwrap_or_default();
+
+                    let mut voting_processes = vec![];
+                    for process in index.processes()? {
+                        let result = index.tally(process.process_id)?.map(|tally| {
+                            [tally.votes_for, tally.votes_against, tally.votes_abstain]
+                        });
+                        voting_processes.push(ListedProcess {
+                            process_id: process.process_id,
+                            ipfs_hash: process.ipfs_hash,
+                            census_block: process.census_block,
+                            start_block: process.start_block,
+                            end_block: process.end_block,
+                            result,
+                        });
+                    }
+
+                    return Ok((voting_processes, head));
+                }
+
+                let nouns_voting =
+                    NounsVoting::new(nouns_voting_address, Arc::new(eth_connection.clone()));
+                let latest_block = eth_connection
+                    .get_block_number()
+                    .await
+                    .map_err(|e| format!("Error getting current block number: {}", e))?;
+                let next_process_id = nouns_voting
+                    .next_process_id()
+                    .block(latest_block)
+                    .call()
+                    .await
+                    .map_err(|e| format!("Error getting the number of voting processes: {e:?}"))?;
+
+                let mut voting_processes = vec![];
+                let mut process_id = EthersU256::zero();
+                while process_id < next_process_id {
+                    let voting_process = get_voting_process(&nouns_voting, process_id).await?;
+                    voting_processes.push(ListedProcess {
+                        process_id,
+                        ipfs_hash: H256::from(voting_process.ipfs_hash),
+                        census_block: voting_process.census_block,
+                        start_block: voting_process.start_block,
+                        end_block: voting_process.end_block,
+                        result: voting_process.tallied.then_some([
+                            voting_process.votes_for,
+                            voting_process.votes_against,
+                            voting_process.votes_abstain,
+                        ]),
+                    });
+                    process_id += EthersU256::one();
+                }
+
+                Ok((voting_processes, latest_block.as_u64()))
+            })
+        })?;
+
+    let voting_processes = voting_processes
+        .into_iter()
+        .map(|voting_process| {
+            let phase = ProcessPhase::at(
+                voting_process.start_block,
+                voting_process.end_block,
+                voting_process.result.is_some(),
+                latest_block,
+            );
+            (voting_process, phase)
+        })
+        .filter(|(_, phase)| phases.is_empty() || phases.contains(phase));
+
+    if json {
+        let voting_processes = voting_processes
+            .map(|(voting_process, phase)| {
+                let [votes_for, votes_against, votes_abstain] =
+                    voting_process.result.unwrap_or_default();
+                Ok(serde_json::json!({
+                    "process_id": voting_process.process_id.to_string(),
+                    "ipfs_cid": crate::parsers::format_ipfs_cid(voting_process.ipfs_hash)?,
+                    "phase": phase.to_string(),
+                    "census_block": voting_process.census_block,
+                    "start_block": voting_process.start_block,
+                    "end_block": voting_process.end_block,
+                    "tallied": voting_process.result.is_some(),
+                    "votes_for": votes_for.to_string(),
+                    "votes_against": votes_against.to_string(),
+                    "votes_abstain": votes_abstain.to_string(),
+                }))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&voting_processes)
+                .map_err(|e| format!("Error serialising voting processes: {}", e))?
+        );
+
+        return Ok(());
+    }
+
+    println!("Voting processes at block {}", latest_block);
+    println!(
+        "{:>6}  {:<8}  {:>12}  {:>12}  {:>12}  Result (for/against/abstain)",
+        "ID", "Phase", "Census block", "Start block", "End block"
+    );
+    for (voting_process, phase) in voting_processes {
+        let result = match voting_process.result {
+            Some([votes_for, votes_against, votes_abstain]) => {
+                format!("{}/{}/{}", votes_for, votes_against, votes_abstain)
+            }
+            None => "-".to_string(),
+        };
+        println!(
+            "{:>6}  {:<8}  {:>12}  {:>12}  {:>12}  {}",
+            voting_process.process_id.to_string(),
+            phase.to_string(),
+            voting_process.census_block,
+            voting_process.start_block,
+            voting_process.end_block,
+            result
+        );
+    }
+
+    Ok(())
+}
+
+/// Gives the time of a block, extrapolated from the average block time if it is not mined yet
+async fn estimate_block_time(
+    eth_connection: &Provider<FailoverHttp>,
+    latest_block: &Block<H256>,
+    block_number: u64,
+) -> Result<String, String> {
+    /// Number of blocks the average block time is taken over
+    const BLOCK_TIME_SAMPLE: u64 = 1000;
+
+    let latest_block_number = latest_block
+        .number
+        .ok_or("Latest block is pending")?
+        .as_u64();
+    let block_timestamp = |block_number: u64| {
+        let eth_connection = eth_connection.clone();
+        async move {
+            eth_connection
+                .get_block(block_number)
+                .await
+                .map_err(|e| format!("Error getting block {}: {}", block_number, e))?
+                .map(|block| block.timestamp.as_u64())
+                .ok_or(format!("Block {} not found", block_number))
+        }
+    };
+
+    if block_number <= latest_block_number {
+        return Ok(format_timestamp(block_timestamp(block_number).await?));
+    }
+
+    let sample_block_number = latest_block_number.saturating_sub(BLOCK_TIME_SAMPLE);
+    let block_time = if sample_block_number < latest_block_number {
+        (latest_block.timestamp.as_u64() - block_timestamp(sample_block_number).await?) as f64
+            / (latest_block_number - sample_block_number) as f64
+    } else {
+        ETH_BLOCK_TIME as f64
+    };
+    let timestamp = latest_block.timestamp.as_u64()
+        + ((block_number - latest_block_number) as f64 * block_time) as u64;
+
+    Ok(format!("~{}", format_timestamp(timestamp)))
+}
+
+fn format_timestamp(timestamp: u64) -> String {
+    match DateTime::<Utc>::from_timestamp(timestamp as i64, 0) {
+        Some(time) => time.format("%Y-%m-%d %H:%M UTC").to_string(),
+        None => format!("timestamp {}", timestamp),
+    }
+}
+
+/// Function that follows the events of the NounsVoting contract and stores the voting processes,
+/// ballots and tally results up to the latest finalized block in the index at `index_path`,
+/// rolling back blocks dropped by reorgs.
+/// An empty index starts at `from_block`.
+pub async fn index(
+    eth_connection: Provider<FailoverHttp>,
+    nouns_voting_address: Address,
+    index_path: PathBuf,
+    from_block: U64,
+) -> Result<(), String> {
+    let report = exec_with_progress("Indexing NounsVoting events", move || {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let mut index = Index::open(&index_path)?;
+            let nouns_voting = NounsVoting::new(nouns_voting_address, Arc::new(eth_connection));
+
+            indexer::sync(&mut index, &nouns_voting, from_block).await
+        })
+    })?;
+
+    if report.rolled_back {
+        println!(
+            "Rolled back the index to re-index from block {} after a chain reorganisation",
+            report.from_block
+        );
+    }
+    println!(
+        "Indexed blocks {} to {}: {} voting processes, {} ballots and {} tally results",
+        report.from_block, report.to_block, report.processes, report.ballots, report.tallies
+    );
+    if report.interrupted {
+        println!(
+            "The chain was reorganised after block {} while indexing. Run the `index` command again.",
+            report.to_block
+        );
+    }
+
+    Ok(())
+}
+
+/// Function that discovers the storage layout of an NFT contract by searching for the mapping slots
+/// that hold the owner of a sample token and the delegate of a sample delegator.
+/// Slots that are not searched for are taken from the current layout.
+pub async fn find_slot(
+    eth_connection: Provider<FailoverHttp>,
+    state_connection: Provider<FailoverHttp>,
+    nouns_voting_address: Address,
+    token_address: Option<Address>,
+    nft_id: Option<U256>,
+    delegator: Option<Address>,
+    max_slot: u64,
+    token_layout: TokenStorageLayout,
+) -> Result<(), String> {
+    // If no token contract was specified, take the one of the voting contract
+    let token_address = match token_address {
+        Some(token_address) => token_address,
+        None => NounsVoting::new(nouns_voting_address, Arc::new(eth_connection))
+            .nouns_token()
+            .call()
+            .await
+            .map_err(|e| {
+                format!("Error getting the NounsToken address from the NounsVoting contract: {e:?}")
+            })?,
+    };
+
+    let nouns_token = NounsToken::new(token_address, Arc::new(state_connection.clone()));
+    let block_number = state_connection
+        .get_block_number()
+        .await
+        .map_err(|e| format!("Error getting current block number: {}", e))?;
+
+    let expected_owner = match nft_id {
+        Some(nft_id) => Some((
+            nft_id,
+            nouns_token
+                .owner_of(wrap_into!(nft_id))
+                .block(block_number)
+                .call()
+                .await
+                .map_err(|e| format!("Error getting the owner of NFT {}: {e:?}", nft_id))?,
+        )),
+        None => None,
+    };
+    let expected_delegate = match delegator {
+        Some(delegator) => Some((
+            delegator,
+            nouns_token
+                .delegates(delegator)
+                .block(block_number)
+                .call()
+                .await
+                .map_err(|e| format!("Error getting the delegate of {:?}: {e:?}", delegator))?,
+        )),
+        None => None,
+    };
+
+    let token_layout = exec_with_progress("Searching token contract storage", move || {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let mut token_layout = token_layout;
+
+            if let Some((nft_id, owner)) = expected_owner {
+                let owner = EthersU256::from_big_endian(owner.as_bytes());
+                let address_mask = (EthersU256::one() << 160) - 1;
+
+                // Plain addresses as well as ERC721A packed ownerships hold the owner in the lower bits
+                let (owner_slot, value) = proofs::find_mapping_slot(
+                    &state_connection,
+                    token_address,
+                    H256::from_uint(&wrap_into!(nft_id)),
+                    block_number,
+                    max_slot,
+                    |value| value & address_mask == owner,
+                )
+                .await?
+                .ok_or(format!(
+                    "No ownership mapping found for NFT {} in the first {} storage slots",
+                    nft_id, max_slot
+                ))?;
+
+                token_layout.owner_slot = owner_slot;
+                token_layout.ownership_encoding = if value == owner {
+                    OwnershipEncoding::Address
+                } else {
+                    OwnershipEncoding::Erc721a
+                };
+            }
+
+            if let Some((delegator, delegate)) = expected_delegate {
+                let delegate = EthersU256::from_big_endian(delegate.as_bytes());
+
+                // Accounts that have not delegated have an empty slot, even if the contract reports them as their own delegate
+                let (delegate_slot, _) = proofs::find_mapping_slot(
+                    &state_connection,
+                    token_address,
+                    H256::from(delegator),
+                    block_number,
+                    max_slot,
+                    |value| value == delegate,
+                )
+                .await?
+                .ok_or(format!(
+                    "No delegation mapping found for {:?} in the first {} storage slots. Make sure the account has delegated explicitly.",
+                    delegator, max_slot
+                ))?;
+
+                token_layout.delegate_slot = Some(delegate_slot);
+            }
+
+            Ok(token_layout)
+        })
+    })?;
+
+    let votable = token_layout == TokenStorageLayout::NOUNS;
+    let token_layout = toml::to_string(&token_layout)
+        .map_err(|e| format!("Error serialising token storage layout: {}", e))?;
+
+    println!(
+        "{}Storage layout of token contract {:?} at block {}. Save it to a file and pass it with `--token-layout-file`:\n\n{}",
+        SPARKLE, token_address, block_number, token_layout
+    );
+    if !votable {
+        println!("This is not the NounsToken storage layout, which is the only one the vote circuit supports: this token cannot be voted with yet.");
+    }
+
+    Ok(())
+}
+
+/// This function will try to help mine the blocks until the specified block number
+/// It will do transactions to increase the block number, only valid for local testing
+pub async fn mine_blocks_until<M: Middleware>(
+    eth_connection: Provider<FailoverHttp>,
+    wallet_address: Address,
+    client: &M,
+    target_block: u64,
+) -> Result<(), String> {
+    // Get the current block number
+    let mut current_block = eth_connection
+        .get_block_number()
+        .await
+        .expect("Error getting current block number");
+
+    if current_block.as_u64() < target_block {
+        println!(
+            "Target block not yet reached! Going to mine the next {} blocks.",
+            target_block - current_block.as_u64()
+        );
+    }
+
+    // Check that the process has ended
+    while current_block.as_u64() < target_block {
+        // Do transactions to increase the block number
+
+        let tx = TransactionRequest::pay(wallet_address, 10);
+        let sent_tx = client
+            .send_transaction(tx, None)
+            .await
+            .map_err(|e| format!("Error sending transaction to increase block number: {}", e))?;
+        let _receipt = sent_tx.await.map_err(|_e| {
+            format!(
+                "Error waiting for transaction to increase block number: {:?}",
+                _e
+            )
+        })?;
+
+        current_block = eth_connection
+            .get_block_number()
+            .await
+            .expect("Error getting current block number");
+    }
+    println!("Target block reached! Mining finished.");
+    Ok(())
 }
 
 /// Function to obtain the token ids that the user can vote with
 /// If the user has no tokens, it will try to mint new ones
-pub async fn obtain_token_ids_to_vote(
+pub async fn obtain_token_ids_to_vote<M: Middleware>(
     wallet_address: Address,
-    nouns_voting: NounsVoting<SignerMiddleware<Provider<Http>, Wallet<SigningKey>>>,
-    client: SignerMiddleware<Provider<Http>, Wallet<SigningKey>>,
+    nouns_voting: NounsVoting<M>,
+    client: M,
 ) -> Result<Vec<EthersU256>, String> {
     let client = Arc::new(client);
     // Request from voting contract the nouns token address
@@ -794,11 +2239,11 @@ pub async fn obtain_token_ids_to_vote(
 }
 
 /// Function to delegate tokens to another address
-pub async fn delegate_tokens(
+pub async fn delegate_tokens<M: Middleware>(
     wallet_address: Address,
     delegate_address: Address,
-    nouns_voting: NounsVoting<SignerMiddleware<Provider<Http>, Wallet<SigningKey>>>,
-    client: SignerMiddleware<Provider<Http>, Wallet<SigningKey>>,
+    nouns_voting: NounsVoting<M>,
+    client: M,
 ) -> Result<Address, String> {
     let client = Arc::new(client);
     // Request from voting contract the nouns token address
@@ -962,11 +2407,11 @@ pub async fn delegate_tokens(
 //     async fn simulate_tally(
 //         voting_address: H160,
 //         tlcs_prk: PrivateKey,
-//         eth_connection: Provider<Http>,
+//         eth_connection: Provider<FailoverHttp>,
 //         wallet_address: Address,
 //         chain_id: U256,
-//         client: SignerMiddleware<Provider<Http>, Wallet<SigningKey>>,
-//         nouns_voting: NounsVoting<SignerMiddleware<Provider<Http>, Wallet<SigningKey>>>,
+//         client: SignerMiddleware<Provider<FailoverHttp>, Wallet<SigningKey>>,
+//         nouns_voting: NounsVoting<SignerMiddleware<Provider<FailoverHttp>, Wallet<SigningKey>>>,
 //         process_id: U256,
 //     ) -> Result<[U256; 3], String> {
 //         // Get the process end block number
@@ -999,6 +2444,804 @@ pub async fn delegate_tokens(
 //     }
 // }
 
+/// Makes the hash of the census block available to the NounsVoting contract.
+/// Blocks older than `MAX_BLOCKHASH_AGE`, or any block if `always` is set, are linked to a known block
+/// through header chain proofs of up to `MAX_CHAIN_LENGTH` headers each,
+/// which are recorded in the contract `HEADER_CHAIN_PROOFS_PER_TX` at a time.
+/// Steps end at multiples of `MAX_CHAIN_LENGTH`, so an interrupted run is resumed
+/// from the lowest block recorded so far rather than from the latest block.
+/// Returns the recorded block hash, or `None` if the census block is recent enough for `blockhash`.
+fn record_census_block<M: Middleware + 'static>(
+    nouns_voting: &NounsVoting<M>,
+    state_connection: &Provider<FailoverHttp>,
+    census_block_number: U64,
+    always: bool,
+) -> Result<Option<H256>, String> {
+    let latest_block_number = exec_with_progress("Checking census block", {
+        let state_connection = state_connection.clone();
+        move || {
+            let rt = Runtime::new().unwrap();
+            rt.block_on(async {
+                state_connection
+                    .get_block_number()
+                    .await
+                    .map_err(|e| format!("Error getting current block number: {}", e))
+            })
+        }
+    })?;
+
+    if census_block_number > latest_block_number {
+        return Err(format!(
+            "Census block {} is in the future (latest block is {})",
+            census_block_number, latest_block_number
+        ));
+    }
+
+    if !always && latest_block_number - census_block_number <= U64::from(MAX_BLOCKHASH_AGE) {
+        return Ok(None);
+    }
+    if census_block_number == latest_block_number {
+        return Err(format!(
+            "Census block {} is the latest block, so no later block links to its hash yet",
+            census_block_number
+        ));
+    }
+
+    // Look up the census block and the step ends above it, up to the blocks still covered by `blockhash`
+    let chain_length = MAX_CHAIN_LENGTH as u64;
+    let census = census_block_number.as_u64();
+    let anchor = exec_with_progress("Looking for block hashes recorded previously", {
+        let nouns_voting = nouns_voting.clone();
+        let latest = latest_block_number.as_u64();
+        move || {
+            let rt = Runtime::new().unwrap();
+            rt.block_on(async {
+                let step_ends = (census / chain_length + 1) * chain_length
+                    ..latest.saturating_sub(MAX_BLOCKHASH_AGE);
+                for block_number in
+                    std::iter::once(census).chain(step_ends.step_by(chain_length as usize))
+                {
+                    let recorded_block_hash = nouns_voting
+                        .historical_block_hashes(block_number)
+                        .call()
+                        .await
+                        .map_err(|e| format!("Error getting recorded block hash: {e:?}"))?;
+                    if recorded_block_hash != [0u8; 32] {
+                        return Ok((block_number, H256::from(recorded_block_hash)));
+                    }
+                }
+
+                Ok((latest, H256::zero()))
+            })
+        }
+    })?;
+
+    let (anchor_number, anchor_hash) = anchor;
+    if anchor_number == census {
+        return Ok(Some(anchor_hash));
+    }
+
+    // Each step goes down to the next multiple of `MAX_CHAIN_LENGTH`, or to the census block
+    let mut steps = Vec::new();
+    let mut block_number = anchor_number;
+    while block_number > census {
+        let ancestor_number = ((block_number - 1) / chain_length * chain_length).max(census);
+        steps.push((block_number, ancestor_number));
+        block_number = ancestor_number;
+    }
+
+    // The latest block is only known through `blockhash` for 256 blocks, so the first step anchored at it is sent alone
+    let mut batches = Vec::new();
+    let mut rest = steps.as_slice();
+    if anchor_hash.is_zero() {
+        let (first, others) = rest.split_at(1);
+        batches.push(first);
+        rest = others;
+    }
+    batches.extend(rest.chunks(HEADER_CHAIN_PROOFS_PER_TX));
+
+    println!(
+        "Recording census block {} from block {} takes {} header chain proofs in {} transactions",
+        census,
+        anchor_number,
+        steps.len(),
+        batches.len()
+    );
+
+    let mut ancestor_hash = H256::zero();
+    for batch in batches {
+        let mut ancestor_hashes = Vec::with_capacity(batch.len());
+        let mut num_headers = Vec::with_capacity(batch.len());
+        let mut proofs = Vec::with_capacity(batch.len());
+
+        for &(block_number, ancestor_number) in batch {
+            println!(
+                "Proving the hash of block {} ({} blocks left)",
+                ancestor_number,
+                ancestor_number - census
+            );
+
+            let (block_hash, block_headers, chain_ancestor_hash) =
+                exec_with_progress("Fetching block headers", {
+                    let state_connection = state_connection.clone();
+                    move || {
+                        let rt = Runtime::new().unwrap();
+                        rt.block_on(async {
+                            proofs::get_header_chain(
+                                &state_connection,
+                                block_number.into(),
+                                ancestor_number.into(),
+                            )
+                            .await
+                        })
+                    }
+                })?;
+
+            let proof = exec_with_progress(
+                "Generating header chain proof (this might take a while)",
+                move || {
+                    nouns_protocol::noir::prove_header_chain(HeaderChainVerifierInput {
+                        block_hash,
+                        ancestor_hash: chain_ancestor_hash,
+                        block_headers,
+                    })
+                },
+            )?;
+
+            ancestor_hash = chain_ancestor_hash;
+            ancestor_hashes.push(chain_ancestor_hash.into());
+            num_headers.push(block_number - ancestor_number);
+            proofs.push(proof.into());
+        }
+
+        let (block_number, _) = batch[0];
+        let (_, ancestor_number) = batch[batch.len() - 1];
+        exec_with_progress("Submitting header chain proofs to smart contract", {
+            let nouns_voting = nouns_voting.clone();
+            move || {
+                let rt = Runtime::new().unwrap();
+                rt.block_on(async {
+                    let record_request = nouns_voting.record_block_hashes(
+                        block_number,
+                        ancestor_hashes,
+                        num_headers,
+                        proofs,
+                    );
+
+                    // The next batch is anchored at the last block of this one, so wait for it to be recorded
+                    record_request
+                        .send()
+                        .await
+                        .map_err(|e| format!("Error sending recordBlockHashes tx: {}", e))?
+                        .await
+                        .map_err(|e| format!("Error waiting for recordBlockHashes tx: {}", e))?
+                        .filter(|receipt| receipt.status == Some(1.into()))
+                        .ok_or(format!(
+                            "The recordBlockHashes tx for block {} failed",
+                            ancestor_number
+                        ))?;
+
+                    Ok(())
+                })
+            }
+        })?;
+    }
+
+    println!(
+        "{} Census block {} recorded with hash {:?}",
+        SPARKLE, census_block_number, ancestor_hash
+    );
+
+    Ok(Some(ancestor_hash))
+}
+
+/// Gets the ZKRegistry contract of the NounsVoting contract, read and sent to through `client`
+async fn get_zk_registry<M: Middleware, N: Middleware>(
+    nouns_voting: &NounsVoting<M>,
+    client: N,
+) -> Result<ZKRegistry<N>, String> {
+    let zk_registry_address = nouns_voting.zk_registry().call().await.map_err(|e| {
+        format!("Error getting the ZKRegistry address from the NounsVoting contract: {e:?}")
+    })?;
+
+    Ok(ZKRegistry::new(zk_registry_address, Arc::new(client)))
+}
+
+/// Reads the commitment to the BBJJ Public Key registered by `address`,
+/// at `block` if given and at the latest block otherwise.
+/// It is zero if no key is registered.
+async fn get_registered_commitment<M: Middleware>(
+    zk_registry: &ZKRegistry<M>,
+    address: Address,
+    block: Option<u64>,
+) -> Result<EthersU256, String> {
+    let [commitment] = get_registry_values(zk_registry, [POSEIDON_INTERFACE_ID], address, block)
+        .await
+        .map_err(|e| format!("Error getting the key registered by {:?}: {}", address, e))?;
+
+    Ok(commitment)
+}
+
+/// Reads the values registered by `address` under `interface_ids`
+async fn get_registry_values<M: Middleware, const N: usize>(
+    zk_registry: &ZKRegistry<M>,
+    interface_ids: [u8; N],
+    address: Address,
+    block: Option<u64>,
+) -> Result<[EthersU256; N], String> {
+    let mut values = [EthersU256::zero(); N];
+    for (value, interface_id) in values.iter_mut().zip(interface_ids) {
+        let mut request = zk_registry.get(interface_id, address);
+        if let Some(block) = block {
+            request = request.block(BlockNumber::Number(block.into()));
+        }
+        *value = request.call().await.map_err(|e| format!("{e:?}"))?;
+    }
+
+    Ok(values)
+}
+
+/// The commitment to a BBJJ Public Key, as registered in the ZKRegistry
+fn public_key_commitment(public_key: &BBJJ_Ec) -> Result<EthersU256, String> {
+    let commitment: U256 = wrap_into!(key_commitment(public_key)?);
+    Ok(wrap_into!(commitment))
+}
+
+/// Registers the commitment to the public key of `private_key` under the sender in the ZKRegistry contract.
+/// Its proof of possession is checked and published with it, and the commitment is read back to confirm it.
+async fn register_public_key<M: Middleware + 'static>(
+    zk_registry: &ZKRegistry<M>,
+    private_key: &SecretKey,
+) -> Result<H256, String> {
+    let sender = sender(zk_registry.client().as_ref())?;
+    let chain_id = zk_registry
+        .client()
+        .get_chainid()
+        .await
+        .map_err(|e| format!("Could not get chain id: {}", e))?
+        .as_u64();
+
+    let (commitment, proof) =
+        registration_commitment(private_key, sender, chain_id, zk_registry.address())?;
+
+    exec_with_progress("Submitting Baby Jubjub public key to registry", {
+        let zk_registry = zk_registry.clone();
+        move || {
+            let rt = Runtime::new().unwrap();
+            rt.block_on(async {
+                let tx_hash = send_confirmed_tx(
+                    zk_registry.register_with_proof(POSEIDON_INTERFACE_ID, commitment, proof.into()),
+                    "key commitment registration",
+                )
+                .await?;
+
+                let registered_commitment =
+                    get_registered_commitment(&zk_registry, sender, None).await?;
+                if registered_commitment != commitment {
+                    return Err(format!(
+                        "The key commitment registered by {:?} is {} after the registration, instead of {}",
+                        sender,
+                        format_registered_commitment(&registered_commitment),
+                        format_registered_commitment(&commitment)
+                    ));
+                }
+
+                Ok(tx_hash)
+            })
+        }
+    })
+}
+
+/// Prepares the registration of the public key of `private_key` by `safe`, with the transaction `register_public_key` sends
+async fn prepare_safe_registration<M: Middleware>(
+    zk_registry: &ZKRegistry<M>,
+    private_key: &SecretKey,
+    safe: &SafeParams,
+    name: &str,
+) -> Result<(), String> {
+    let chain_id = zk_registry
+        .client()
+        .get_chainid()
+        .await
+        .map_err(|e| format!("Could not get chain id: {}", e))?;
+    let (commitment, proof) = registration_commitment(
+        private_key,
+        safe.address,
+        chain_id.as_u64(),
+        zk_registry.address(),
+    )?;
+
+    let transaction = SafeTransaction::new(
+        &zk_registry.register_with_proof(POSEIDON_INTERFACE_ID, commitment, proof.into()),
+        "key commitment registration",
+    )?;
+
+    safe::output_transactions(safe, zk_registry.client(), &[transaction], name).await
+}
+
+/// The commitment registered for the public key of `private_key` by `sender`, along with the encoded proof of possession
+/// of the key for the registration, which is published with it for anyone to check, as the ZKRegistry does not.
+/// The proof is checked against the commitment before it is returned.
+fn registration_commitment(
+    private_key: &SecretKey,
+    sender: Address,
+    chain_id: u64,
+    zk_registry_address: Address,
+) -> Result<(EthersU256, Vec<u8>), String> {
+    let public_key = private_key.public();
+    let proof = PossessionProof::new(
+        private_key,
+        sender,
+        U256::from_u64(chain_id),
+        zk_registry_address,
+    )?
+    .encode(&public_key);
+
+    verify_registration(
+        &proof,
+        wrap_into!(key_commitment(&public_key)?),
+        sender,
+        U256::from_u64(chain_id),
+        zk_registry_address,
+    )
+    .map_err(|e| format!("Error building the proof of possession: {}", e))?;
+
+    Ok((public_key_commitment(&public_key)?, proof))
+}
+
+/// Checks the proof of possession that `tx_hash` published with the registration of `commitment` by `address`
+async fn check_registration_proof<M: Middleware>(
+    zk_registry: &ZKRegistry<M>,
+    address: Address,
+    tx_hash: H256,
+    commitment: EthersU256,
+) -> Result<(), String> {
+    let receipt = zk_registry
+        .client()
+        .get_transaction_receipt(tx_hash)
+        .await
+        .map_err(|e| format!("Error getting the receipt of {:?}: {}", tx_hash, e))?
+        .ok_or(format!("The transaction {:?} is not mined", tx_hash))?;
+    let registration = receipt
+        .logs
+        .iter()
+        .filter(|log| log.address == zk_registry.address())
+        .filter_map(|log| parse_log::<RegisteredFilter>(log.clone()).ok())
+        .filter(|event| event.interface_id == POSEIDON_INTERFACE_ID && event.addr == address)
+        .last()
+        .ok_or(format!(
+            "The transaction {:?} published no key registration by {:?}",
+            tx_hash, address
+        ))?;
+    if registration.value != commitment {
+        return Err(format!(
+            "The transaction {:?} registered {} instead",
+            tx_hash,
+            format_registered_commitment(&registration.value)
+        ));
+    }
+
+    let chain_id = zk_registry
+        .client()
+        .get_chainid()
+        .await
+        .map_err(|e| format!("Could not get chain id: {}", e))?;
+    verify_registration(
+        &registration.proof,
+        wrap_into!(commitment),
+        address,
+        wrap_into!(chain_id),
+        zk_registry.address(),
+    )
+}
+
+/// The account `client` sends transactions from
+fn sender<M: Middleware>(client: &M) -> Result<Address, String> {
+    client
+        .default_sender()
+        .ok_or("No transaction signer was given".to_string())
+}
+
+/// Sends a transaction and waits for it to succeed
+async fn send_confirmed_tx<M: Middleware>(
+    request: ContractCall<M, ()>,
+    description: &str,
+) -> Result<H256, String> {
+    let pending_tx = request
+        .send()
+        .await
+        .map_err(|e| format!("Error sending {} tx {e:?}", description))?;
+    let tx_hash = pending_tx.tx_hash();
+
+    pending_tx
+        .await
+        .map_err(|e| format!("Error waiting for {} tx: {}", description, e))?
+        .filter(|receipt| receipt.status == Some(1.into()))
+        .ok_or(format!("The {} tx {:?} failed", description, tx_hash))?;
+
+    Ok(tx_hash)
+}
+
+/// Reads the commitment to the key registered by `sender`, along with the voting processes whose census already took it
+fn check_registered_key<M: Middleware + Clone + 'static>(
+    client: M,
+    nouns_voting_address: Address,
+    sender: Address,
+) -> Result<(ZKRegistry<M>, EthersU256, Vec<EthersU256>), String> {
+    exec_with_progress("Checking registered key", move || {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let nouns_voting = NounsVoting::new(nouns_voting_address, Arc::new(client.clone()));
+            let zk_registry = get_zk_registry(&nouns_voting, client).await?;
+
+            let registered_commitment =
+                get_registered_commitment(&zk_registry, sender, None).await?;
+            let captured_processes = if registered_commitment.is_zero() {
+                vec![]
+            } else {
+                processes_with_registered_key(
+                    &nouns_voting,
+                    &zk_registry,
+                    sender,
+                    registered_commitment,
+                )
+                .await?
+            };
+
+            Ok((zk_registry, registered_commitment, captured_processes))
+        })
+    })
+}
+
+/// Lists the voting processes that are not over and whose census already took the key with `commitment`
+/// as the key registered by `address`, so that votes in them still need that key.
+/// Processes whose census cannot be read, e.g. from a node without archive state, are listed too.
+async fn processes_with_registered_key<M: Middleware, N: Middleware>(
+    nouns_voting: &NounsVoting<M>,
+    zk_registry: &ZKRegistry<N>,
+    address: Address,
+    commitment: EthersU256,
+) -> Result<Vec<EthersU256>, String> {
+    let latest_block = nouns_voting
+        .client()
+        .get_block_number()
+        .await
+        .map_err(|e| format!("Error getting current block number: {}", e))?;
+    let next_process_id = nouns_voting
+        .next_process_id()
+        .call()
+        .await
+        .map_err(|e| format!("Error getting the number of voting processes: {e:?}"))?;
+
+    let mut processes = vec![];
+    let mut process_id = EthersU256::zero();
+    while process_id < next_process_id {
+        let voting_process = get_voting_process(nouns_voting, process_id).await?;
+        let phase = ProcessPhase::of(&voting_process, latest_block.as_u64());
+        if matches!(phase, ProcessPhase::Pending | ProcessPhase::Active) {
+            let captured = match get_registered_commitment(
+                zk_registry,
+                address,
+                Some(voting_process.census_block),
+            )
+            .await
+            {
+                Ok(census_commitment) => census_commitment == commitment,
+                Err(_) => true,
+            };
+            if captured {
+                processes.push(process_id);
+            }
+        }
+        process_id += EthersU256::one();
+    }
+
+    Ok(processes)
+}
+
+fn warn_captured_processes(processes: &[EthersU256], key_description: &str) {
+    if processes.is_empty() {
+        return;
+    }
+
+    let processes = processes
+        .iter()
+        .map(|process_id| process_id.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    println!(
+        "Warning: the census of voting processes {} already took the {}, so votes in them still need it",
+        processes, key_description
+    );
+}
+
+/// Formats a registered BBJJ Public Key commitment
+fn format_registered_commitment(commitment: &EthersU256) -> String {
+    if commitment.is_zero() {
+        return "not registered".to_string();
+    }
+
+    format!("{:#x}", commitment)
+}
+
+/// The outcome of a series of named checks, printed as a JSON report
+#[derive(Default)]
+struct CheckReport {
+    checks: Vec<serde_json::Value>,
+}
+
+impl CheckReport {
+    /// Records a check and returns whether it passed
+    fn check(&mut self, name: &str, pass: bool, detail: String) -> bool {
+        self.checks.push(serde_json::json!({
+            "check": name,
+            "pass": pass,
+            "detail": detail,
+        }));
+        pass
+    }
+
+    /// Records a check that cannot be made yet, which does not fail the report
+    fn pending(&mut self, name: &str, detail: String) {
+        self.checks.push(serde_json::json!({
+            "check": name,
+            "pass": null,
+            "detail": detail,
+        }));
+    }
+
+    /// Prints the report with `fields` and fails with `failure` unless all `expected` checks
+    /// were made and none failed
+    fn finish(
+        self,
+        expected: usize,
+        mut fields: serde_json::Value,
+        failure: &str,
+    ) -> Result<(), String> {
+        let pass = self.checks.len() == expected && self.checks.iter().all(|c| c["pass"] != false);
+        fields["pass"] = pass.into();
+        fields["checks"] = self.checks.into();
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&fields)
+                .map_err(|e| format!("Error serialising report: {}", e))?
+        );
+
+        if pass {
+            Ok(())
+        } else {
+            Err(failure.to_string())
+        }
+    }
+}
+
+/// The phase of a voting process at a given block
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProcessPhase {
+    /// The voting period has not started
+    Pending,
+    /// Votes are being accepted
+    Active,
+    /// The voting period is over but no tally was submitted
+    EndedUntallied,
+    Tallied,
+}
+
+impl ProcessPhase {
+    fn of(voting_process: &VotingProcess, block_number: u64) -> Self {
+        Self::at(
+            voting_process.start_block,
+            voting_process.end_block,
+            voting_process.tallied,
+            block_number,
+        )
+    }
+
+    /// The phase at `block_number` of a process voting from `start_block` to `end_block`
+    fn at(start_block: u64, end_block: u64, tallied: bool, block_number: u64) -> Self {
+        if tallied {
+            ProcessPhase::Tallied
+        } else if block_number < start_block {
+            ProcessPhase::Pending
+        } else if block_number < end_block {
+            ProcessPhase::Active
+        } else {
+            ProcessPhase::EndedUntallied
+        }
+    }
+}
+
+impl fmt::Display for ProcessPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let phase_str = match self {
+            ProcessPhase::Pending => "pending",
+            ProcessPhase::Active => "active",
+            ProcessPhase::EndedUntallied => "ended",
+            ProcessPhase::Tallied => "tallied",
+        };
+        write!(f, "{}", phase_str)
+    }
+}
+
+/// The state of a voting process in the NounsVoting contract
+#[derive(Clone, Debug)]
+pub(crate) struct VotingProcess {
+    pub ipfs_hash: [u8; 32],
+    pub census_block: u64,
+    pub start_block: u64,
+    pub block_hash: [u8; 32],
+    pub end_block: u64,
+    pub zk_registry_storage_root: [u8; 32],
+    pub nouns_token_storage_root: [u8; 32],
+    pub tlcs_round_number: u64,
+    pub tlcs_public_key: [EthersU256; 2],
+    pub ballots_hash: EthersU256,
+    pub votes_for: EthersU256,
+    pub votes_against: EthersU256,
+    pub votes_abstain: EthersU256,
+    pub tallied: bool,
+}
+
+/// Reads the full state of a voting process from the NounsVoting contract.
+/// The `votingProcesses` getter leaves out the TLCS public key, as Solidity getters omit array members,
+/// so it is read from the storage of the process instead.
+pub(crate) async fn get_voting_process<M: Middleware>(
+    nouns_voting: &NounsVoting<M>,
+    process_id: EthersU256,
+) -> Result<VotingProcess, String> {
+    let (
+        ipfs_hash,
+        census_block,
+        start_block,
+        block_hash,
+        end_block,
+        zk_registry_storage_root,
+        nouns_token_storage_root,
+        tlcs_round_number,
+        ballots_hash,
+        votes_for,
+        votes_against,
+        votes_abstain,
+        tallied,
+        _action,
+    ) = NounsVotingProcesses::new(nouns_voting.address(), nouns_voting.client())
+        .voting_processes(process_id)
+        .call()
+        .await
+        .map_err(|e| format!("Error getting voting process {}: {e:?}", process_id))?;
+    if end_block == 0 {
+        return Err(format!("Voting process {} does not exist", process_id));
+    }
+
+    let client = nouns_voting.client();
+    let process_slot = map_storage_slot(
+        H256::from_uint(&VOTING_PROCESSES_SLOT.into()),
+        vec![H256::from_uint(&process_id)],
+    )
+    .into_uint();
+    let mut storage = [EthersU256::zero(); 3];
+    for (value, offset) in storage.iter_mut().zip(TLCS_STORAGE_OFFSETS) {
+        let slot = H256::from_uint(&(process_slot + offset));
+        *value = client
+            .get_storage_at(nouns_voting.address(), slot, None)
+            .await
+            .map_err(|e| {
+                format!(
+                    "Error reading the TLCS public key of voting process {}: {}",
+                    process_id, e
+                )
+            })?
+            .into_uint();
+    }
+    // The round number is read back to catch a storage layout other than the one assumed
+    let [stored_round_number, tlcs_public_key @ ..] = storage;
+    if stored_round_number != tlcs_round_number.into() {
+        return Err(format!(
+            "The storage of voting process {} does not have the expected layout",
+            process_id
+        ));
+    }
+
+    Ok(VotingProcess {
+        ipfs_hash,
+        census_block,
+        start_block,
+        block_hash,
+        end_block,
+        zk_registry_storage_root,
+        nouns_token_storage_root,
+        tlcs_round_number,
+        tlcs_public_key,
+        ballots_hash,
+        votes_for,
+        votes_against,
+        votes_abstain,
+        tallied,
+    })
+}
+
+/// Checks that the census block of the voting process is part of the chain followed by the RPC node
+/// and that the node can serve proofs of the state at that block.
+async fn check_census_block(
+    eth_connection: &Provider<FailoverHttp>,
+    voting_process: &VotingProcess,
+    zk_registry_address: Address,
+) -> Result<(), String> {
+    let census_block_number = U64::from(voting_process.census_block);
+    let census_block = eth_connection
+        .get_block(census_block_number)
+        .await
+        .map_err(|e| format!("Error obtaining census block data: {}", e))?
+        .ok_or(format!(
+            "Census block {} not found. Is the RPC node connected to the right chain?",
+            census_block_number
+        ))?;
+
+    let recorded_block_hash = H256::from(voting_process.block_hash);
+    if census_block.hash != Some(recorded_block_hash) {
+        return Err(format!(
+            "The hash of census block {} reported by the RPC node ({:?}) differs from the one recorded in the voting process ({:?}). The chain may have been reorganised since the process was created, or the node follows a different chain.",
+            census_block_number,
+            census_block.hash.unwrap_or_default(),
+            recorded_block_hash
+        ));
+    }
+
+    eth_connection
+        .get_proof(zk_registry_address, vec![], Some(census_block_number.into()))
+        .await
+        .map_err(|e| {
+            format!(
+                "The RPC node cannot serve the state at census block {} ({}). An archive node is needed for older census blocks.",
+                census_block_number, e
+            )
+        })?;
+
+    Ok(())
+}
+
+/// Checks that the storage roots proven at the census block are the ones recorded in the voting process.
+/// The vote proof is verified on-chain against the recorded roots, so it would be rejected otherwise.
+fn check_census_roots(
+    voting_process: &VotingProcess,
+    registry_storage_root: EthersU256,
+    nft_storage_root: EthersU256,
+) -> Result<(), String> {
+    for (contract_name, proven_root, recorded_root) in [
+        ("ZKRegistry", registry_storage_root, voting_process.zk_registry_storage_root),
+        ("NounsToken", nft_storage_root, voting_process.nouns_token_storage_root),
+    ] {
+        let proven_root = H256::from_uint(&proven_root);
+        let recorded_root = H256::from(recorded_root);
+        if proven_root != recorded_root {
+            return Err(format!(
+                "The {} storage root at census block {} ({:?}) differs from the one recorded in the voting process ({:?}). The process was not created with this census block or contract.",
+                contract_name, voting_process.census_block, proven_root, recorded_root
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Asks the user a question on the terminal and returns the trimmed, lowercase answer
+fn prompt(question: &str) -> Result<String, String> {
+    print!("{} ", question);
+    std::io::stdout()
+        .flush()
+        .map_err(|e| format!("Error writing to the terminal: {}", e))?;
+
+    let mut answer = String::new();
+    let read = std::io::stdin()
+        .read_line(&mut answer)
+        .map_err(|e| format!("Error reading the answer: {}", e))?;
+    if read == 0 {
+        return Err("No answer given: the input is closed".to_string());
+    }
+
+    Ok(answer.trim().to_lowercase())
+}
+
 fn exec_with_progress<
     F: FnOnce() -> Result<T, String> + std::marker::Send + 'static,
     T: std::marker::Send + 'static,
@@ -1050,9 +3293,14 @@ pub(crate) mod tlcs {
         private_key: String,
     }
 
+    /// Unix time of the first LOE round
+    const LOE_EPOCH: u64 = 1677685200;
+    /// A new round starts every 3 seconds.
+    const ROUND_DURATION: u64 = 3;
+
     fn seconds_since_loe_epoch() -> u64 {
         let loe_epoch: SystemTime = UNIX_EPOCH
-            .checked_add(Duration::from_secs(1677685200))
+            .checked_add(Duration::from_secs(LOE_EPOCH))
             .unwrap();
 
         SystemTime::now()
@@ -1068,8 +3316,7 @@ pub(crate) mod tlcs {
     ) -> Result<u64, String> {
         let t = seconds_since_loe_epoch();
 
-        // A new round starts every 3 seconds.
-        let round_number = (t + start_delay + process_duration) / 3;
+        let round_number = (t + start_delay + process_duration) / ROUND_DURATION;
 
         // Send request for public/private key pair for particular round number
         // TODO: Parse at least some of the body and handle errors.
@@ -1084,6 +3331,11 @@ pub(crate) mod tlcs {
         Ok(round_number)
     }
 
+    /// Unix time at which the given round starts
+    pub(crate) fn round_timestamp(round_number: u64) -> u64 {
+        LOE_EPOCH + round_number * ROUND_DURATION
+    }
+
     // /// This function fetches LOE data, which is required for computing the TLCS private key (server side).
     // pub(crate) async fn fetch_loe_data(
     //     round_number: u64
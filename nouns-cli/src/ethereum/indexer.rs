@@ -0,0 +1,711 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use ethers::contract::parse_log;
+use ethers::prelude::{Address, BlockNumber, Filter, Middleware, H256, U64};
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::ethereum::ballots::{log_position, CastBallot, LogPages, ProcessBallots};
+use crate::ethereum::contract_interactions::{
+    get_voting_process, BallotCastFilter, NounsVoting, NounsVotingEvents,
+};
+use crate::EthersU256;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS meta (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    -- Hashes of the indexed blocks holding events or ending a page, used to detect reorgs
+    CREATE TABLE IF NOT EXISTS blocks (
+        block_number INTEGER PRIMARY KEY,
+        block_hash TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS processes (
+        process_id TEXT PRIMARY KEY,
+        block_number INTEGER NOT NULL,
+        ipfs_hash TEXT NOT NULL,
+        census_block INTEGER NOT NULL,
+        start_block INTEGER NOT NULL,
+        end_block INTEGER NOT NULL,
+        tlcs_round_number INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS ballots (
+        block_number INTEGER NOT NULL,
+        log_index INTEGER NOT NULL,
+        transaction_hash TEXT NOT NULL,
+        process_id TEXT NOT NULL,
+        a_x TEXT NOT NULL,
+        a_y TEXT NOT NULL,
+        b TEXT NOT NULL,
+        PRIMARY KEY (block_number, log_index)
+    );
+    CREATE INDEX IF NOT EXISTS ballots_by_process ON ballots (process_id);
+    CREATE TABLE IF NOT EXISTS tallies (
+        process_id TEXT PRIMARY KEY,
+        block_number INTEGER NOT NULL,
+        votes_for TEXT NOT NULL,
+        votes_against TEXT NOT NULL,
+        votes_abstain TEXT NOT NULL
+    );
+";
+
+/// A local SQLite store of the processes, ballots and tally results of a NounsVoting contract,
+/// kept up to date by `sync`
+pub(crate) struct Index {
+    db: Connection,
+}
+
+/// A voting process as created in the NounsVoting contract
+#[derive(Clone, Debug)]
+pub(crate) struct IndexedProcess {
+    pub(crate) process_id: EthersU256,
+    /// The block the process was created in
+    pub(crate) block_number: U64,
+    pub(crate) ipfs_hash: H256,
+    pub(crate) census_block: u64,
+    pub(crate) start_block: u64,
+    pub(crate) end_block: u64,
+    pub(crate) tlcs_round_number: u64,
+}
+
+/// The tally result submitted for a voting process
+#[derive(Clone, Debug)]
+pub(crate) struct IndexedTally {
+    pub(crate) process_id: EthersU256,
+    /// The block the result was submitted in
+    pub(crate) block_number: U64,
+    pub(crate) votes_for: EthersU256,
+    pub(crate) votes_against: EthersU256,
+    pub(crate) votes_abstain: EthersU256,
+}
+
+/// The events found in a page of blocks
+#[derive(Default)]
+struct IndexedPage {
+    /// The blocks holding the events and the last block of the page
+    blocks: Vec<(U64, H256)>,
+    processes: Vec<IndexedProcess>,
+    ballots: Vec<CastBallot>,
+    tallies: Vec<IndexedTally>,
+}
+
+/// What a call to `sync` did to the index
+pub(crate) struct SyncReport {
+    /// Whether blocks were rolled back because of a reorg
+    pub(crate) rolled_back: bool,
+    pub(crate) from_block: U64,
+    /// The last indexed block
+    pub(crate) to_block: U64,
+    /// Whether indexing stopped early because the chain was reorganised meanwhile
+    pub(crate) interrupted: bool,
+    pub(crate) processes: usize,
+    pub(crate) ballots: usize,
+    pub(crate) tallies: usize,
+}
+
+impl Index {
+    /// Opens the index at the given path, creating it if it does not exist
+    pub(crate) fn open(path: &Path) -> Result<Self, String> {
+        let db = Connection::open(path)
+            .map_err(|e| format!("Error opening index {}: {}", path.display(), e))?;
+        db.execute_batch(SCHEMA)
+            .map_err(|e| format!("Error creating index {}: {}", path.display(), e))?;
+
+        Ok(Index { db })
+    }
+
+    /// Binds an empty index to a NounsVoting contract, to be indexed from `from_block`
+    fn init_source(
+        &self,
+        chain_id: u64,
+        nouns_voting_address: Address,
+        from_block: U64,
+    ) -> Result<(), String> {
+        if self.meta("chain_id")?.is_some() {
+            return self.check_source(chain_id, nouns_voting_address);
+        }
+
+        for (key, value) in [
+            ("chain_id", chain_id.to_string()),
+            ("contract_address", format!("{:?}", nouns_voting_address)),
+            ("from_block", from_block.to_string()),
+        ] {
+            self.db
+                .execute(
+                    "INSERT INTO meta (key, value) VALUES (?1, ?2)",
+                    params![key, value],
+                )
+                .map_err(|e| format!("Error writing to index: {}", e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the index follows the given NounsVoting contract
+    pub(crate) fn check_source(
+        &self,
+        chain_id: u64,
+        nouns_voting_address: Address,
+    ) -> Result<(), String> {
+        let indexed_chain_id = self.meta("chain_id")?;
+        let indexed_address = self.meta("contract_address")?;
+
+        match (indexed_chain_id, indexed_address) {
+            (Some(indexed_chain_id), Some(indexed_address))
+                if indexed_chain_id == chain_id.to_string()
+                    && indexed_address == format!("{:?}", nouns_voting_address) =>
+            {
+                Ok(())
+            }
+            (Some(indexed_chain_id), Some(indexed_address)) => Err(format!(
+                "The index follows contract {} on chain {}, not contract {:?} on chain {}",
+                indexed_address, indexed_chain_id, nouns_voting_address, chain_id
+            )),
+            _ => Err("The index is empty. Run the `index` command first.".to_string()),
+        }
+    }
+
+    fn meta(&self, key: &str) -> Result<Option<String>, String> {
+        self.db
+            .query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(|e| format!("Error reading index: {}", e))
+    }
+
+    /// The first block to index
+    fn first_block(&self) -> Result<U64, String> {
+        let from_block = self.meta("from_block")?.unwrap_or_default();
+        u64::from_str(&from_block)
+            .map(U64::from)
+            .map_err(|e| format!("Invalid first block in index: {}", e))
+    }
+
+    /// The last indexed block, if any
+    pub(crate) fn head(&self) -> Result<Option<(U64, H256)>, String> {
+        Ok(self.blocks()?.into_iter().next())
+    }
+
+    /// The hashes of the indexed blocks, latest first
+    fn blocks(&self) -> Result<Vec<(U64, H256)>, String> {
+        let mut statement = self
+            .db
+            .prepare("SELECT block_number, block_hash FROM blocks ORDER BY block_number DESC")
+            .map_err(|e| format!("Error reading index: {}", e))?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, u64>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(|e| format!("Error reading index: {}", e))?;
+
+        rows.map(|row| {
+            let (block_number, block_hash) =
+                row.map_err(|e| format!("Error reading index: {}", e))?;
+            Ok((U64::from(block_number), parse_h256(&block_hash)?))
+        })
+        .collect()
+    }
+
+    /// Removes everything indexed after `block_number`, or everything if it is `None`
+    fn rollback(&mut self, block_number: Option<U64>) -> Result<(), String> {
+        // Block numbers are stored as signed integers
+        let block_number = block_number.map_or(-1, |n| n.as_u64() as i64);
+        let tx = self
+            .db
+            .transaction()
+            .map_err(|e| format!("Error rolling back index: {}", e))?;
+        for table in ["blocks", "processes", "ballots", "tallies"] {
+            tx.execute(
+                &format!("DELETE FROM {} WHERE block_number > ?1", table),
+                [block_number],
+            )
+            .map_err(|e| format!("Error rolling back index: {}", e))?;
+        }
+
+        tx.commit()
+            .map_err(|e| format!("Error rolling back index: {}", e))
+    }
+
+    /// Stores the events of a page atomically
+    fn apply(&mut self, page: &IndexedPage) -> Result<(), String> {
+        let write_error = |e: rusqlite::Error| format!("Error writing to index: {}", e);
+        let tx = self.db.transaction().map_err(write_error)?;
+
+        for (block_number, block_hash) in &page.blocks {
+            tx.execute(
+                "INSERT OR REPLACE INTO blocks (block_number, block_hash) VALUES (?1, ?2)",
+                params![block_number.as_u64(), format!("{:?}", block_hash)],
+            )
+            .map_err(write_error)?;
+        }
+
+        for process in &page.processes {
+            tx.execute(
+                "INSERT OR REPLACE INTO processes (process_id, block_number, ipfs_hash, census_block, start_block, end_block, tlcs_round_number) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    process.process_id.to_string(),
+                    process.block_number.as_u64(),
+                    format!("{:?}", process.ipfs_hash),
+                    process.census_block,
+                    process.start_block,
+                    process.end_block,
+                    process.tlcs_round_number,
+                ],
+            )
+            .map_err(write_error)?;
+        }
+
+        for ballot in &page.ballots {
+            tx.execute(
+                "INSERT OR REPLACE INTO ballots (block_number, log_index, transaction_hash, process_id, a_x, a_y, b) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    ballot.block_number.as_u64(),
+                    ballot.log_index.as_u64(),
+                    format!("{:?}", ballot.transaction_hash),
+                    ballot.event.process_id.to_string(),
+                    ballot.event.a_x.to_string(),
+                    ballot.event.a_y.to_string(),
+                    ballot.event.b.to_string(),
+                ],
+            )
+            .map_err(write_error)?;
+        }
+
+        for tally in &page.tallies {
+            tx.execute(
+                "INSERT OR REPLACE INTO tallies (process_id, block_number, votes_for, votes_against, votes_abstain) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    tally.process_id.to_string(),
+                    tally.block_number.as_u64(),
+                    tally.votes_for.to_string(),
+                    tally.votes_against.to_string(),
+                    tally.votes_abstain.to_string(),
+                ],
+            )
+            .map_err(write_error)?;
+        }
+
+        tx.commit().map_err(write_error)
+    }
+
+    /// All the indexed voting processes, in the order they were created
+    pub(crate) fn processes(&self) -> Result<Vec<IndexedProcess>, String> {
+        self.query_processes("", params![])
+    }
+
+    pub(crate) fn process(&self, process_id: EthersU256) -> Result<Option<IndexedProcess>, String> {
+        Ok(self
+            .query_processes("WHERE process_id = ?1", [process_id.to_string()])?
+            .pop())
+    }
+
+    fn query_processes<P: rusqlite::Params>(
+        &self,
+        condition: &str,
+        params: P,
+    ) -> Result<Vec<IndexedProcess>, String> {
+        let read_error = |e: rusqlite::Error| format!("Error reading index: {}", e);
+        let mut statement = self
+            .db
+            .prepare(&format!(
+                "SELECT process_id, block_number, ipfs_hash, census_block, start_block, end_block, tlcs_round_number FROM processes {} ORDER BY block_number",
+                condition
+            ))
+            .map_err(read_error)?;
+        let rows = statement
+            .query_map(params, |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, u64>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, u64>(3)?,
+                    row.get::<_, u64>(4)?,
+                    row.get::<_, u64>(5)?,
+                    row.get::<_, u64>(6)?,
+                ))
+            })
+            .map_err(read_error)?;
+
+        rows.map(|row| {
+            let (
+                process_id,
+                block_number,
+                ipfs_hash,
+                census_block,
+                start_block,
+                end_block,
+                tlcs_round_number,
+            ) = row.map_err(read_error)?;
+            Ok(IndexedProcess {
+                process_id: parse_u256(&process_id)?,
+                block_number: U64::from(block_number),
+                ipfs_hash: parse_h256(&ipfs_hash)?,
+                census_block,
+                start_block,
+                end_block,
+                tlcs_round_number,
+            })
+        })
+        .collect()
+    }
+
+    /// The ballots cast in a voting process up to the last indexed block, in the order they were cast
+    pub(crate) fn ballots(&self, process_id: EthersU256) -> Result<ProcessBallots, String> {
+        let process = self
+            .process(process_id)?
+            .ok_or(format!("Voting process {} is not in the index", process_id))?;
+        let head = self
+            .head()?
+            .map(|(block_number, _)| block_number)
+            .unwrap_or_default();
+
+        let read_error = |e: rusqlite::Error| format!("Error reading index: {}", e);
+        let mut statement = self
+            .db
+            .prepare("SELECT block_number, log_index, transaction_hash, a_x, a_y, b FROM ballots WHERE process_id = ?1 ORDER BY block_number, log_index")
+            .map_err(read_error)?;
+        let rows = statement
+            .query_map([process_id.to_string()], |row| {
+                Ok((
+                    row.get::<_, u64>(0)?,
+                    row.get::<_, u64>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, String>(5)?,
+                ))
+            })
+            .map_err(read_error)?;
+
+        let ballots = rows
+            .map(|row| {
+                let (block_number, log_index, transaction_hash, a_x, a_y, b) =
+                    row.map_err(read_error)?;
+                Ok(CastBallot {
+                    event: BallotCastFilter {
+                        process_id,
+                        a_x: parse_u256(&a_x)?,
+                        a_y: parse_u256(&a_y)?,
+                        b: parse_u256(&b)?,
+                    },
+                    block_number: U64::from(block_number),
+                    transaction_hash: parse_h256(&transaction_hash)?,
+                    log_index: EthersU256::from(log_index),
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        // Votes are accepted up to the block before the end block
+        let last_voting_block = U64::from(process.end_block.saturating_sub(1));
+
+        Ok(ProcessBallots {
+            ballots,
+            to_block: head.min(last_voting_block),
+            complete: head >= last_voting_block,
+        })
+    }
+
+    /// The tally result submitted for a voting process, if any
+    pub(crate) fn tally(&self, process_id: EthersU256) -> Result<Option<IndexedTally>, String> {
+        let read_error = |e: rusqlite::Error| format!("Error reading index: {}", e);
+        let row = self
+            .db
+            .query_row(
+                "SELECT block_number, votes_for, votes_against, votes_abstain FROM tallies WHERE process_id = ?1",
+                [process_id.to_string()],
+                |row| {
+                    Ok((
+                        row.get::<_, u64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                    ))
+                },
+            )
+            .optional()
+            .map_err(read_error)?;
+
+        row.map(|(block_number, votes_for, votes_against, votes_abstain)| {
+            Ok(IndexedTally {
+                process_id,
+                block_number: U64::from(block_number),
+                votes_for: parse_u256(&votes_for)?,
+                votes_against: parse_u256(&votes_against)?,
+                votes_abstain: parse_u256(&votes_abstain)?,
+            })
+        })
+        .transpose()
+    }
+}
+
+/// Checks that the last indexed block is still part of the chain
+pub(crate) async fn check_head<M: Middleware>(index: &Index, client: &M) -> Result<(), String> {
+    if let Some((block_number, block_hash)) = index.head()? {
+        if get_block_hash(client, block_number).await? != Some(block_hash) {
+            return Err(format!(
+                "Indexed block {} was reorganised. Run the `index` command again.",
+                block_number
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Brings the index up to the latest finalized block of the chain followed by the NounsVoting contract,
+/// so that it only holds events that cannot be reorganised anymore.
+/// Blocks that are no longer part of the chain are rolled back first.
+/// An empty index starts at `from_block`.
+pub(crate) async fn sync<M: Middleware>(
+    index: &mut Index,
+    nouns_voting: &NounsVoting<M>,
+    from_block: U64,
+) -> Result<SyncReport, String> {
+    let client = nouns_voting.client();
+    let chain_id = client
+        .get_chainid()
+        .await
+        .map_err(|e| format!("Error getting chain id: {}", e))?;
+    index.init_source(chain_id.as_u64(), nouns_voting.address(), from_block)?;
+
+    // Find the last indexed block that is still part of the chain
+    let blocks = index.blocks()?;
+    let mut common_block = None;
+    for (block_number, block_hash) in &blocks {
+        if get_block_hash(client.as_ref(), *block_number).await? == Some(*block_hash) {
+            common_block = Some(*block_number);
+            break;
+        }
+    }
+
+    let rolled_back = common_block != blocks.first().map(|(block_number, _)| *block_number);
+    if rolled_back {
+        index.rollback(common_block)?;
+    }
+
+    let from_block = match common_block {
+        Some(block_number) => block_number + 1,
+        None => index.first_block()?,
+    };
+    let to_block = client
+        .get_block(BlockNumber::Finalized)
+        .await
+        .map_err(|e| format!("Error getting finalized block: {}", e))?
+        .and_then(|block| block.number)
+        .ok_or("The node did not return a finalized block")?;
+
+    let mut report = SyncReport {
+        rolled_back,
+        from_block,
+        to_block: from_block.saturating_sub(U64::one()),
+        interrupted: false,
+        processes: 0,
+        ballots: 0,
+        tallies: 0,
+    };
+
+    let filter = Filter::new().address(nouns_voting.address());
+    let mut pages = LogPages::new(filter, from_block, to_block);
+    loop {
+        let page_start = pages.next_block();
+        let (page_end, logs) = match pages.next(client.as_ref()).await {
+            Ok(Some(page)) => page,
+            Ok(None) => break,
+            Err(e) => {
+                return Err(format!(
+                    "Error getting NounsVoting events in block {}: {}",
+                    page_start, e
+                ))
+            }
+        };
+
+        let mut page = IndexedPage::default();
+        for log in logs {
+            if log.removed == Some(true) {
+                continue;
+            }
+
+            let (block_number, transaction_hash, log_index) = log_position(&log)?;
+            if let Some(block_hash) = log.block_hash {
+                if !page.blocks.contains(&(block_number, block_hash)) {
+                    page.blocks.push((block_number, block_hash));
+                }
+            }
+
+            let event: NounsVotingEvents = parse_log(log).map_err(|e| {
+                format!(
+                    "Error decoding event in transaction {:?}: {}",
+                    transaction_hash, e
+                )
+            })?;
+
+            match event {
+                NounsVotingEvents::BallotCastFilter(event) => page.ballots.push(CastBallot {
+                    event,
+                    block_number,
+                    transaction_hash,
+                    log_index,
+                }),
+                NounsVotingEvents::ProcessCreatedFilter(event) => {
+                    // The rest of the process data is not part of the event
+                    let voting_process = get_voting_process(nouns_voting, event.process_id).await?;
+                    page.processes.push(IndexedProcess {
+                        process_id: event.process_id,
+                        block_number,
+                        ipfs_hash: H256::from(voting_process.ipfs_hash),
+                        census_block: event.census_block,
+                        start_block: event.start_block,
+                        end_block: event.end_block,
+                        tlcs_round_number: voting_process.tlcs_round_number,
+                    })
+                }
+                NounsVotingEvents::TallySubmittedFilter(event) => page.tallies.push(IndexedTally {
+                    process_id: event.process_id,
+                    block_number,
+                    votes_for: event.votes_for,
+                    votes_against: event.votes_against,
+                    votes_abstain: event.votes_abstain,
+                }),
+            }
+        }
+
+        // The hash of the last block of the page tells whether it is reorganised later on
+        let page_end_hash = get_block_hash(client.as_ref(), page_end)
+            .await?
+            .ok_or(format!("Block {} not found", page_end))?;
+
+        // Logs served from a fork dropped since then are left for the next run
+        let mut reorganised = false;
+        for (block_number, block_hash) in &page.blocks {
+            if get_block_hash(client.as_ref(), *block_number).await? != Some(*block_hash) {
+                reorganised = true;
+                break;
+            }
+        }
+        if reorganised {
+            report.interrupted = true;
+            break;
+        }
+
+        page.blocks.push((page_end, page_end_hash));
+        index.apply(&page)?;
+        report.to_block = page_end;
+        report.processes += page.processes.len();
+        report.ballots += page.ballots.len();
+        report.tallies += page.tallies.len();
+    }
+
+    Ok(report)
+}
+
+async fn get_block_hash<M: Middleware>(
+    client: &M,
+    block_number: U64,
+) -> Result<Option<H256>, String> {
+    Ok(client
+        .get_block(block_number)
+        .await
+        .map_err(|e| format!("Error getting block {}: {}", block_number, e))?
+        .and_then(|block| block.hash))
+}
+
+fn parse_u256(value: &str) -> Result<EthersU256, String> {
+    EthersU256::from_dec_str(value).map_err(|e| format!("Invalid number in index: {}", e))
+}
+
+fn parse_h256(value: &str) -> Result<H256, String> {
+    H256::from_str(value).map_err(|e| format!("Invalid hash in index: {}", e))
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::prelude::{Address, H256, U64};
+
+    use crate::ethereum::ballots::CastBallot;
+    use crate::ethereum::contract_interactions::BallotCastFilter;
+    use crate::ethereum::indexer::{Index, IndexedPage, IndexedProcess, IndexedTally};
+    use crate::EthersU256;
+
+    #[test]
+    fn test_index_rollback() -> Result<(), String> {
+        let mut index = Index::open(":memory:".as_ref())?;
+        let address = Address::repeat_byte(1);
+        index.init_source(5, address, U64::from(100))?;
+        assert!(index.check_source(1, address).is_err());
+
+        let page = |block_number: u64| {
+            let ballot = CastBallot {
+                event: BallotCastFilter {
+                    process_id: EthersU256::zero(),
+                    a_x: EthersU256::from(block_number),
+                    a_y: EthersU256::from(2),
+                    b: EthersU256::from(3),
+                },
+                block_number: U64::from(block_number),
+                transaction_hash: H256::repeat_byte(block_number as u8),
+                log_index: EthersU256::zero(),
+            };
+            IndexedPage {
+                blocks: vec![(
+                    U64::from(block_number),
+                    H256::repeat_byte(block_number as u8),
+                )],
+                ballots: vec![ballot],
+                ..Default::default()
+            }
+        };
+
+        index.apply(&IndexedPage {
+            blocks: vec![(U64::from(100), H256::repeat_byte(100))],
+            processes: vec![IndexedProcess {
+                process_id: EthersU256::zero(),
+                block_number: U64::from(100),
+                ipfs_hash: H256::zero(),
+                census_block: 99,
+                start_block: 100,
+                end_block: 120,
+                tlcs_round_number: 7,
+            }],
+            ..Default::default()
+        })?;
+        index.apply(&page(110))?;
+        index.apply(&page(115))?;
+        index.apply(&IndexedPage {
+            blocks: vec![(U64::from(120), H256::repeat_byte(120))],
+            tallies: vec![IndexedTally {
+                process_id: EthersU256::zero(),
+                block_number: U64::from(120),
+                votes_for: EthersU256::from(2),
+                votes_against: EthersU256::zero(),
+                votes_abstain: EthersU256::zero(),
+            }],
+            ..Default::default()
+        })?;
+
+        let ballots = index.ballots(EthersU256::zero())?;
+        assert_eq!(ballots.ballots.len(), 2);
+        assert!(ballots.complete);
+        assert!(index.tally(EthersU256::zero())?.is_some());
+
+        // Roll back past the second ballot and the tally
+        index.rollback(Some(U64::from(110)))?;
+        assert_eq!(
+            index.head()?,
+            Some((U64::from(110), H256::repeat_byte(110)))
+        );
+        let ballots = index.ballots(EthersU256::zero())?;
+        assert_eq!(ballots.ballots.len(), 1);
+        assert_eq!(ballots.ballots[0].event.a_x, EthersU256::from(110));
+        assert!(!ballots.complete);
+        assert!(index.tally(EthersU256::zero())?.is_none());
+
+        index.rollback(None)?;
+        assert_eq!(index.head()?, None);
+        assert!(index.process(EthersU256::zero())?.is_none());
+        assert_eq!(index.first_block()?, U64::from(100));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,217 @@
+use std::path::Path;
+
+use ethers::abi::Address;
+use ethers::prelude::{BigEndianHash, H256};
+use serde::{Deserialize, Serialize};
+
+use crate::ethereum::proofs::map_storage_slot;
+use crate::EthersU256;
+
+/// Bit flagging a burned token in an ERC721A packed ownership slot
+const ERC721A_BURNED_BIT: usize = 224;
+
+/// How a token's owner is stored in its ownership slot
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OwnershipEncoding {
+    /// The slot holds the owner's address and nothing else (OpenZeppelin-style `_owners` mapping)
+    Address,
+    /// ERC721A packed ownership: the owner's address in the lower 160 bits, followed by the
+    /// start timestamp and flags. Tokens minted in a batch only have their first slot set.
+    Erc721a,
+}
+
+/// The storage slots that hold token ownership, vote delegation and registered keys.
+/// Offsets are the Solidity storage slots of the respective mappings.
+/// The vote circuit only supports the NounsToken layout: other layouts are what `find-slot` reports
+/// for other token contracts, which cannot be voted with yet.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TokenStorageLayout {
+    /// Slot of the `tokenId => owner` mapping in the token contract
+    pub owner_slot: u64,
+    pub ownership_encoding: OwnershipEncoding,
+    /// Slot of the `owner => delegate` mapping in the token contract, if it supports delegation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delegate_slot: Option<u64>,
+    /// Slot of the `interfaceId => address => value` mapping in the ZKRegistry contract
+    pub registry_slot: u64,
+}
+
+impl TokenStorageLayout {
+    /// NounsToken (ERC721Checkpointable) and ZKRegistry, as assumed by the vote circuit
+    pub const NOUNS: TokenStorageLayout = TokenStorageLayout {
+        owner_slot: 3,
+        ownership_encoding: OwnershipEncoding::Address,
+        delegate_slot: Some(0x0b),
+        registry_slot: 0,
+    };
+
+    /// Names of the built-in layouts
+    pub const PRESETS: [&'static str; 2] = ["nouns", "lil-nouns"];
+
+    /// Returns the built-in layout with the given name
+    pub fn preset(name: &str) -> Result<Self, String> {
+        match name {
+            // Lil Nouns inherits the NounsToken storage layout
+            "nouns" | "lil-nouns" => Ok(Self::NOUNS),
+            _ => Err(format!(
+                "Unknown token storage layout `{}`. Available layouts: {}",
+                name,
+                Self::PRESETS.join(", ")
+            )),
+        }
+    }
+
+    /// Reads a layout from a TOML file, e.g.
+    /// ```toml
+    /// owner_slot = 3
+    /// ownership_encoding = "address"
+    /// delegate_slot = 11
+    /// registry_slot = 0
+    /// ```
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            format!(
+                "Error reading token storage layout file {}: {}",
+                path.display(),
+                e
+            )
+        })?;
+
+        toml::from_str(&contents).map_err(|e| {
+            format!(
+                "Error parsing token storage layout file {}: {}",
+                path.display(),
+                e
+            )
+        })
+    }
+
+    /// Storage slot holding the ownership of the given token
+    pub(crate) fn owner_slot(&self, nft_id: EthersU256) -> H256 {
+        map_storage_slot(
+            H256::from_uint(&self.owner_slot.into()),
+            vec![H256::from_uint(&nft_id)],
+        )
+    }
+
+    /// Storage slot holding the delegate of the given account
+    pub(crate) fn delegate_slot(&self, owner: Address) -> Result<H256, String> {
+        let delegate_slot = self
+            .delegate_slot
+            .ok_or("The token storage layout does not support delegation")?;
+
+        Ok(map_storage_slot(
+            H256::from_uint(&delegate_slot.into()),
+            vec![H256::from(owner)],
+        ))
+    }
+
+    /// Storage slot of the value registered by the given account under the given interface
+    pub(crate) fn registry_slot(&self, interface_id: u8, address: Address) -> H256 {
+        map_storage_slot(
+            H256::from_uint(&self.registry_slot.into()),
+            vec![H256::from_uint(&interface_id.into()), H256::from(address)],
+        )
+    }
+
+    /// Decodes the owner from the value of an ownership slot.
+    /// Returns `None` if the slot is empty, which for ERC721A may mean that
+    /// the ownership is stored in the slot of a preceding token.
+    pub(crate) fn decode_owner(&self, value: EthersU256) -> Result<Option<Address>, String> {
+        match self.ownership_encoding {
+            OwnershipEncoding::Address => decode_address(value),
+            OwnershipEncoding::Erc721a => {
+                if value.bit(ERC721A_BURNED_BIT) {
+                    return Err("The token has been burned".to_string());
+                }
+
+                let mut address_bytes = [0u8; 32];
+                (value & ((EthersU256::one() << 160) - 1)).to_big_endian(&mut address_bytes);
+
+                Ok(Some(Address::from_slice(&address_bytes[12..])).filter(|a| !a.is_zero()))
+            }
+        }
+    }
+
+    /// Decodes the delegate from the value of a delegation slot.
+    /// Returns `None` if the account has not delegated.
+    pub(crate) fn decode_delegate(&self, value: EthersU256) -> Result<Option<Address>, String> {
+        decode_address(value)
+    }
+}
+
+/// Decodes a slot holding a plain address, returning `None` for the zero address
+fn decode_address(value: EthersU256) -> Result<Option<Address>, String> {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+
+    if bytes[..12].iter().any(|b| *b != 0) {
+        return Err(format!("Storage value {:#x} is not an address", value));
+    }
+
+    Ok(Some(Address::from_slice(&bytes[12..])).filter(|a| !a.is_zero()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use ethers::abi::Address;
+
+    use crate::ethereum::layout::{OwnershipEncoding, TokenStorageLayout};
+    use crate::EthersU256;
+
+    #[test]
+    fn test_layout_presets_and_config() {
+        assert_eq!(
+            TokenStorageLayout::preset("lil-nouns"),
+            Ok(TokenStorageLayout::NOUNS)
+        );
+        assert!(TokenStorageLayout::preset("cryptopunks").is_err());
+
+        // `find-slot` prints layouts that can be read back as config files
+        let nouns_config = toml::to_string(&TokenStorageLayout::NOUNS).unwrap();
+        assert_eq!(
+            toml::from_str::<TokenStorageLayout>(&nouns_config),
+            Ok(TokenStorageLayout::NOUNS)
+        );
+
+        let layout: TokenStorageLayout =
+            toml::from_str("owner_slot = 5\nownership_encoding = \"erc721a\"\nregistry_slot = 0\n")
+                .unwrap();
+        assert_eq!(layout.owner_slot, 5);
+        assert_eq!(layout.ownership_encoding, OwnershipEncoding::Erc721a);
+        assert_eq!(layout.delegate_slot, None);
+        assert!(layout.delegate_slot(Address::zero()).is_err());
+    }
+
+    #[test]
+    fn test_decode_owner() {
+        let owner = Address::from_str("0x2573C60a6D127755aA2DC85e342F7da2378a0Cc5").unwrap();
+        let owner_value = EthersU256::from_big_endian(owner.as_bytes());
+        let start_timestamp = EthersU256::from(1_690_000_000u64) << 160;
+        let burned = EthersU256::one() << 224;
+
+        let nouns = TokenStorageLayout::NOUNS;
+        assert_eq!(nouns.decode_owner(owner_value), Ok(Some(owner)));
+        assert_eq!(nouns.decode_owner(EthersU256::zero()), Ok(None));
+        assert!(nouns.decode_owner(owner_value | start_timestamp).is_err());
+
+        let erc721a = TokenStorageLayout {
+            owner_slot: 4,
+            ownership_encoding: OwnershipEncoding::Erc721a,
+            delegate_slot: None,
+            registry_slot: 0,
+        };
+        assert_eq!(
+            erc721a.decode_owner(owner_value | start_timestamp),
+            Ok(Some(owner))
+        );
+        assert_eq!(erc721a.decode_owner(EthersU256::zero()), Ok(None));
+        assert!(erc721a
+            .decode_owner(owner_value | start_timestamp | burned)
+            .is_err());
+    }
+}
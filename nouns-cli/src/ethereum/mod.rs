@@ -3,23 +3,32 @@ use std::str::FromStr;
 use ethers::abi::Address;
 use ethers::core::k256::ecdsa::SigningKey;
 use ethers::prelude::{
-    Http, LocalWallet, Middleware, Provider, ProviderExt, Signer, SignerMiddleware, Wallet, H160,
-    U256,
+    LocalWallet, Middleware, Provider, Signer, SignerMiddleware, Wallet, H160, U256,
 };
 
+use crate::ethereum::provider::FailoverHttp;
+
+pub(crate) mod ballots;
 pub mod contract_interactions;
+pub(crate) mod indexer;
+pub mod layout;
+pub(crate) mod mpt;
 pub(crate) mod proofs;
+pub mod provider;
+pub(crate) mod receipts;
+pub mod safe;
+pub mod signer;
 
 pub async fn setup_connection(
     tx_private_key: String,
     rpc_url: String,
 ) -> (
-    Provider<Http>,
+    Provider<FailoverHttp>,
     Address,
-    SignerMiddleware<Provider<Http>, Wallet<SigningKey>>,
+    SignerMiddleware<Provider<FailoverHttp>, Wallet<SigningKey>>,
     U256,
 ) {
-    let eth_connection = Provider::<Http>::connect(rpc_url.as_str()).await;
+    let eth_connection = provider::connect(&[rpc_url]).await.unwrap();
     // create the signer for the txs
 
     let wallet = LocalWallet::from_str(tx_private_key.as_str()).unwrap();
@@ -0,0 +1,479 @@
+use ethers::prelude::{Address, BigEndianHash, Bytes, EIP1186ProofResponse, StorageProof, H256};
+use ethers::utils::keccak256;
+use ethers::utils::rlp::Rlp;
+
+use crate::EthersU256;
+
+/// Root hash of an empty Merkle-Patricia trie, i.e. `keccak256(rlp(""))`
+pub(crate) const EMPTY_TRIE_ROOT: [u8; 32] = [
+    0x56, 0xe8, 0x1f, 0x17, 0x1b, 0xcc, 0x55, 0xa6, 0xff, 0x83, 0x45, 0xe6, 0x92, 0xc0, 0xf8, 0x6e,
+    0x5b, 0x48, 0xe0, 0x1b, 0x99, 0x6c, 0xad, 0xc0, 0x01, 0x62, 0x2f, 0xb5, 0xe3, 0x63, 0xb4, 0x21,
+];
+
+/// Code hash of an account without code, i.e. `keccak256("")`
+pub(crate) const EMPTY_CODE_HASH: [u8; 32] = [
+    0xc5, 0xd2, 0x46, 0x01, 0x86, 0xf7, 0x23, 0x3c, 0x92, 0x7e, 0x7d, 0xb2, 0xdc, 0xc7, 0x03, 0xc0,
+    0xe5, 0x00, 0xb6, 0x53, 0xca, 0x82, 0x27, 0x3b, 0x7b, 0xfa, 0xd8, 0x04, 0x5d, 0x85, 0xa4, 0x70,
+];
+
+/// The decoded account state stored in the leaves of the state trie
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct AccountState {
+    pub(crate) nonce: EthersU256,
+    pub(crate) balance: EthersU256,
+    pub(crate) storage_root: H256,
+    pub(crate) code_hash: H256,
+}
+
+impl AccountState {
+    /// The state of an account that is not present in the state trie
+    pub(crate) fn empty() -> Self {
+        AccountState {
+            nonce: EthersU256::zero(),
+            balance: EthersU256::zero(),
+            storage_root: H256::from(EMPTY_TRIE_ROOT),
+            code_hash: H256::from(EMPTY_CODE_HASH),
+        }
+    }
+
+    /// Decodes an RLP-encoded account of the form `[nonce, balance, storageRoot, codeHash]`
+    pub(crate) fn decode(rlp_account: &[u8]) -> Result<Self, String> {
+        let account = Rlp::new(rlp_account);
+
+        let num_fields = account
+            .item_count()
+            .map_err(|e| format!("Invalid account RLP encoding: {}", e))?;
+        if !account.is_list() || num_fields != 4 {
+            return Err(format!(
+                "Invalid account RLP encoding: expected a list of 4 fields, got {}",
+                num_fields
+            ));
+        }
+
+        Ok(AccountState {
+            nonce: decode_u256(&account, 0)?,
+            balance: decode_u256(&account, 1)?,
+            storage_root: decode_h256(&account, 2)?,
+            code_hash: decode_h256(&account, 3)?,
+        })
+    }
+}
+
+/// Verifies a Merkle-Patricia trie proof for `key` against `root`.
+/// The path through the trie is given by `keccak256(key)`, as is the case for the
+/// state and storage tries.
+/// Returns the RLP-encoded value stored under the key, or `None` if the proof shows that
+/// the key is not present in the trie.
+pub(crate) fn verify_proof(
+    root: H256,
+    key: &[u8],
+    proof: &[Bytes],
+) -> Result<Option<Vec<u8>>, String> {
+    let path = to_nibbles(&keccak256(key));
+
+    if proof.is_empty() {
+        return if root == H256::from(EMPTY_TRIE_ROOT) {
+            Ok(None)
+        } else {
+            Err("Proof is empty, but the trie is not".to_string())
+        };
+    }
+
+    let mut expected_hash = root;
+    let mut path_offset = 0;
+
+    for (depth, node) in proof.iter().enumerate() {
+        if H256::from(keccak256(node)) != expected_hash {
+            return Err(format!("Hash mismatch for proof node at depth {}", depth));
+        }
+
+        let is_last = depth == proof.len() - 1;
+        let mut current = Rlp::new(node);
+
+        // Nodes whose encoding is shorter than 32 bytes are embedded in their parent,
+        // so we may have to descend several levels within a single proof node.
+        let next_hash = loop {
+            let num_items = current
+                .item_count()
+                .map_err(|e| format!("Invalid proof node at depth {}: {}", depth, e))?;
+
+            let child = match num_items {
+                // Branch node
+                17 => {
+                    if path_offset == path.len() {
+                        let value = rlp_data(&current.at(16).map_err(rlp_err)?)?;
+                        return terminate(is_last, (!value.is_empty()).then(|| value.to_vec()));
+                    }
+
+                    let child = current.at(path[path_offset] as usize).map_err(rlp_err)?;
+                    path_offset += 1;
+                    child
+                }
+                // Extension or leaf node
+                2 => {
+                    let (is_leaf, partial_path) =
+                        decode_hex_prefix(rlp_data(&current.at(0).map_err(rlp_err)?)?)?;
+
+                    if is_leaf {
+                        let value = (path[path_offset..] == partial_path[..])
+                            .then(|| {
+                                current
+                                    .at(1)
+                                    .map_err(rlp_err)
+                                    .and_then(|v| rlp_data(&v).map(|d| d.to_vec()))
+                            })
+                            .transpose()?;
+                        return terminate(is_last, value);
+                    }
+
+                    if !path[path_offset..].starts_with(&partial_path) {
+                        return terminate(is_last, None);
+                    }
+                    path_offset += partial_path.len();
+
+                    current.at(1).map_err(rlp_err)?
+                }
+                _ => {
+                    return Err(format!(
+                        "Invalid proof node at depth {}: unexpected number of items {}",
+                        depth, num_items
+                    ))
+                }
+            };
+
+            if child.is_list() {
+                current = child;
+                continue;
+            }
+
+            let child_ref = rlp_data(&child)?;
+            match child_ref.len() {
+                0 => return terminate(is_last, None),
+                32 => break H256::from_slice(child_ref),
+                len => {
+                    return Err(format!(
+                        "Invalid child reference of length {} at depth {}",
+                        len, depth
+                    ))
+                }
+            }
+        };
+
+        expected_hash = next_hash;
+    }
+
+    Err("Proof ended before reaching a leaf".to_string())
+}
+
+/// Verifies the account proof of an `eth_getProof` response against the given state root
+/// and checks that the account fields reported by the node match the decoded account.
+pub(crate) fn verify_account_proof(
+    state_root: H256,
+    proof: &EIP1186ProofResponse,
+) -> Result<AccountState, String> {
+    let account = match verify_proof(state_root, proof.address.as_bytes(), &proof.account_proof)
+        .map_err(|e| format!("Invalid account proof for {:?}: {}", proof.address, e))?
+    {
+        Some(rlp_account) => AccountState::decode(&rlp_account)?,
+        None => AccountState::empty(),
+    };
+
+    if account.nonce != EthersU256::from(proof.nonce.as_u64())
+        || account.balance != proof.balance
+        || account.storage_root != proof.storage_hash
+        || account.code_hash != proof.code_hash
+    {
+        return Err(format!(
+            "Account state of {:?} does not match its proof",
+            proof.address
+        ));
+    }
+
+    Ok(account)
+}
+
+/// Verifies a single storage proof against the given storage root and checks that
+/// the value reported by the node matches the one in the trie.
+pub(crate) fn verify_storage_proof(
+    storage_root: H256,
+    storage_proof: &StorageProof,
+) -> Result<(), String> {
+    let value = verify_proof(
+        storage_root,
+        H256::from_uint(&storage_proof.key).as_bytes(),
+        &storage_proof.proof,
+    )
+    .map_err(|e| {
+        format!(
+            "Invalid storage proof for slot {:?}: {}",
+            storage_proof.key, e
+        )
+    })?;
+
+    let value = match value {
+        Some(rlp_value) => {
+            let value = Rlp::new(&rlp_value);
+            if !value.is_data() {
+                return Err(format!(
+                    "Invalid storage value encoding for slot {:?}",
+                    storage_proof.key
+                ));
+            }
+            u256_from_bytes(rlp_data(&value)?)?
+        }
+        None => EthersU256::zero(),
+    };
+
+    if value != storage_proof.value {
+        return Err(format!(
+            "Storage value for slot {:?} does not match its proof",
+            storage_proof.key
+        ));
+    }
+
+    Ok(())
+}
+
+/// Verifies the account proof and all storage proofs contained in an `eth_getProof` response
+/// against the state root of the block it was requested for.
+pub(crate) fn verify_eip1186_proof(
+    state_root: H256,
+    address: Address,
+    proof: &EIP1186ProofResponse,
+) -> Result<AccountState, String> {
+    if proof.address != address {
+        return Err(format!(
+            "Proof is for address {:?} instead of {:?}",
+            proof.address, address
+        ));
+    }
+
+    let account = verify_account_proof(state_root, proof)?;
+
+    for storage_proof in proof.storage_proof.iter() {
+        verify_storage_proof(account.storage_root, storage_proof)?;
+    }
+
+    Ok(account)
+}
+
+/// Ends the walk through the trie. The node we stopped at must be the last one in the proof.
+fn terminate(is_last: bool, value: Option<Vec<u8>>) -> Result<Option<Vec<u8>>, String> {
+    if !is_last {
+        return Err("Proof contains unexpected trailing nodes".to_string());
+    }
+
+    Ok(value)
+}
+
+/// Decodes a hex-prefix encoded path into a leaf flag and the path's nibbles
+fn decode_hex_prefix(encoded: &[u8]) -> Result<(bool, Vec<u8>), String> {
+    let first = *encoded.first().ok_or("Empty hex-prefix encoded path")?;
+    let flag = first >> 4;
+
+    if flag > 3 {
+        return Err(format!("Invalid hex-prefix flag {}", flag));
+    }
+
+    let is_leaf = flag & 2 != 0;
+    let mut nibbles = Vec::with_capacity(2 * encoded.len());
+
+    // An odd number of nibbles keeps its first nibble in the low bits of the prefix byte
+    if flag & 1 != 0 {
+        nibbles.push(first & 0x0f);
+    } else if first & 0x0f != 0 {
+        return Err("Invalid hex-prefix padding".to_string());
+    }
+    nibbles.extend(to_nibbles(&encoded[1..]));
+
+    Ok((is_leaf, nibbles))
+}
+
+fn to_nibbles(bytes: &[u8]) -> Vec<u8> {
+    bytes.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect()
+}
+
+fn rlp_data<'a>(rlp: &Rlp<'a>) -> Result<&'a [u8], String> {
+    rlp.data().map_err(rlp_err)
+}
+
+fn rlp_err(e: ethers::utils::rlp::DecoderError) -> String {
+    format!("Invalid RLP encoding: {}", e)
+}
+
+fn decode_u256(list: &Rlp, index: usize) -> Result<EthersU256, String> {
+    u256_from_bytes(rlp_data(&list.at(index).map_err(rlp_err)?)?)
+}
+
+fn decode_h256(list: &Rlp, index: usize) -> Result<H256, String> {
+    let bytes = rlp_data(&list.at(index).map_err(rlp_err)?)?;
+
+    if bytes.len() != 32 {
+        return Err(format!(
+            "Expected a 32-byte hash, got {} bytes",
+            bytes.len()
+        ));
+    }
+
+    Ok(H256::from_slice(bytes))
+}
+
+/// Decodes a big-endian integer, rejecting non-canonical encodings with leading zeros
+fn u256_from_bytes(bytes: &[u8]) -> Result<EthersU256, String> {
+    if bytes.len() > 32 || bytes.first() == Some(&0) {
+        return Err("Invalid integer encoding".to_string());
+    }
+
+    Ok(EthersU256::from_big_endian(bytes))
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::prelude::{BigEndianHash, Bytes, StorageProof, H256};
+    use ethers::utils::keccak256;
+    use ethers::utils::rlp::RlpStream;
+
+    use crate::ethereum::mpt::{to_nibbles, verify_proof, verify_storage_proof};
+    use crate::EthersU256;
+
+    /// Hex-prefix encodes a nibble path
+    fn hex_prefix(nibbles: &[u8], is_leaf: bool) -> Vec<u8> {
+        let flag = if is_leaf { 2 } else { 0 };
+        let mut out = if nibbles.len() % 2 == 1 {
+            vec![((flag + 1) << 4) | nibbles[0]]
+        } else {
+            vec![flag << 4]
+        };
+        let rest = if nibbles.len() % 2 == 1 {
+            &nibbles[1..]
+        } else {
+            nibbles
+        };
+        out.extend(rest.chunks(2).map(|c| (c[0] << 4) | c[1]));
+        out
+    }
+
+    fn leaf(nibbles: &[u8], value: &[u8]) -> Vec<u8> {
+        let mut stream = RlpStream::new_list(2);
+        stream.append(&hex_prefix(nibbles, true));
+        stream.append(&value.to_vec());
+        stream.out().to_vec()
+    }
+
+    fn rlp_u256(value: u64) -> Vec<u8> {
+        let mut stream = RlpStream::new();
+        stream.append(&EthersU256::from(value));
+        stream.out().to_vec()
+    }
+
+    /// Builds a trie containing two slots whose paths diverge at the first nibble
+    /// and returns its root together with the proof for each slot
+    fn two_leaf_trie(slots: [H256; 2], values: [u64; 2]) -> (H256, [Vec<Bytes>; 2]) {
+        let paths = slots.map(|s| to_nibbles(&keccak256(s)));
+        assert_ne!(paths[0][0], paths[1][0]);
+
+        let leaves = [
+            leaf(&paths[0][1..], &rlp_u256(values[0])),
+            leaf(&paths[1][1..], &rlp_u256(values[1])),
+        ];
+
+        let mut branch = RlpStream::new_list(17);
+        for i in 0..16u8 {
+            match (0..2).find(|&j| paths[j][0] == i) {
+                Some(j) => branch.append(&keccak256(&leaves[j]).to_vec()),
+                None => branch.append_empty_data(),
+            };
+        }
+        branch.append_empty_data();
+        let branch = branch.out().to_vec();
+
+        let root = H256::from(keccak256(&branch));
+        let proofs =
+            [0, 1].map(|j| vec![Bytes::from(branch.clone()), Bytes::from(leaves[j].clone())]);
+
+        (root, proofs)
+    }
+
+    fn diverging_slots() -> [H256; 2] {
+        let first = H256::from_low_u64_be(0);
+        let first_nibble = keccak256(first)[0] >> 4;
+        let second = (1..)
+            .map(H256::from_low_u64_be)
+            .find(|s| keccak256(s)[0] >> 4 != first_nibble)
+            .unwrap();
+        [first, second]
+    }
+
+    #[test]
+    fn test_inclusion_proof() -> Result<(), String> {
+        let slots = diverging_slots();
+        let (root, proofs) = two_leaf_trie(slots, [42, 7]);
+
+        for j in 0..2 {
+            verify_storage_proof(
+                root,
+                &StorageProof {
+                    key: slots[j].into_uint(),
+                    proof: proofs[j].clone(),
+                    value: EthersU256::from([42, 7][j]),
+                },
+            )?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wrong_value_is_rejected() {
+        let slots = diverging_slots();
+        let (root, proofs) = two_leaf_trie(slots, [42, 7]);
+
+        assert!(verify_storage_proof(
+            root,
+            &StorageProof {
+                key: slots[0].into_uint(),
+                proof: proofs[0].clone(),
+                value: EthersU256::from(43),
+            },
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_tampered_node_is_rejected() {
+        let slots = diverging_slots();
+        let (root, mut proofs) = two_leaf_trie(slots, [42, 7]);
+
+        let mut tampered = proofs[0][1].to_vec();
+        *tampered.last_mut().unwrap() ^= 1;
+        proofs[0][1] = Bytes::from(tampered);
+
+        assert!(verify_proof(root, slots[0].as_bytes(), &proofs[0]).is_err());
+    }
+
+    #[test]
+    fn test_exclusion_proof() -> Result<(), String> {
+        let slots = diverging_slots();
+        let (root, proofs) = two_leaf_trie(slots, [42, 7]);
+
+        // A slot sharing the first nibble with `slots[0]` ends at the same leaf,
+        // whose remaining path differs, so it proves that the slot is empty.
+        let first_nibble = keccak256(slots[0])[0] >> 4;
+        let absent = (2..)
+            .map(H256::from_low_u64_be)
+            .find(|s| *s != slots[0] && keccak256(s)[0] >> 4 == first_nibble)
+            .unwrap();
+
+        assert_eq!(verify_proof(root, absent.as_bytes(), &proofs[0])?, None);
+        verify_storage_proof(
+            root,
+            &StorageProof {
+                key: absent.into_uint(),
+                proof: proofs[0].clone(),
+                value: EthersU256::zero(),
+            },
+        )?;
+
+        Ok(())
+    }
+}
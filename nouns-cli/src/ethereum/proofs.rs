@@ -1,6 +1,6 @@
 use ethers::abi::Address;
 use ethers::prelude::{
-    BigEndianHash, BlockId, Http, Middleware, Provider, StorageProof, H256, U64,
+    BigEndianHash, BlockId, EIP1186ProofResponse, Middleware, Provider, StorageProof, H256, U64,
 };
 use ethers::utils::keccak256;
 
@@ -11,19 +11,17 @@ use nouns_protocol::{BlockHeader, StateProof};
 use ethers::types::{Block, Bytes};
 use ethers::utils::rlp;
 
+use crate::ethereum::layout::TokenStorageLayout;
+use crate::ethereum::mpt;
+use crate::ethereum::provider::FailoverHttp;
 use crate::EthersU256;
 
-pub(crate) const REGISTRY_SLOT_OFFSET: u64 = 0;
-pub(crate) const NFT_OWNER_SLOT_OFFSET: u64 = 3;
-pub(crate) const DELEGATE_SLOT_OFFSET: u64 = 0x0b;
-
-pub(crate) const BBJJ_INTERFACE_X_ID: u8 = 0;
-pub(crate) const BBJJ_INTERFACE_Y_ID: u8 = 1;
+pub(crate) const POSEIDON_INTERFACE_ID: u8 = 0xbf;
 
 /// This function calculates the storage location of the Ethereum multidimensional mapping
 /// map_keys is a vector of mapping indices, starting from the highest
 /// For instance, `a[0][1][2]` would be represented by `map_keys = vec![0, 1, 2]`
-fn map_storage_slot(slot_number: H256, map_keys: Vec<H256>) -> H256 {
+pub(crate) fn map_storage_slot(slot_number: H256, map_keys: Vec<H256>) -> H256 {
     let mut location = slot_number;
     for key in map_keys {
         location = keccak256(&[key.as_bytes(), location.as_bytes()].concat()).into()
@@ -32,23 +30,119 @@ fn map_storage_slot(slot_number: H256, map_keys: Vec<H256>) -> H256 {
     return location;
 }
 
+/// This function fetches the state root of the given block
+async fn get_state_root(
+    eth_connection: &Provider<FailoverHttp>,
+    block_number: U64,
+) -> Result<H256, String> {
+    Ok(eth_connection
+        .get_block(block_number)
+        .await
+        .map_err(|e| format!("Error obtaining block data: {}", e))?
+        .ok_or(format!("Block {} not found", block_number))?
+        .state_root)
+}
+
+/// This function calls `eth_getProof` for the given storage slots and verifies the returned
+/// account and storage proofs against the state root of the block, so that inconsistent
+/// RPC data is rejected before it reaches the circuits.
+async fn get_verified_proof(
+    eth_connection: &Provider<FailoverHttp>,
+    address: Address,
+    storage_slots: Vec<H256>,
+    block_number: U64,
+) -> Result<EIP1186ProofResponse, String> {
+    let state_root = get_state_root(eth_connection, block_number).await?;
+
+    let proof = eth_connection
+        .get_proof(
+            address,
+            storage_slots.clone(),
+            Some(BlockId::from(block_number.as_u64())),
+        )
+        .await
+        .map_err(|e| format!("{}", e))?;
+
+    // Make sure we got a proof for each of the requested slots, in order
+    if proof.storage_proof.len() != storage_slots.len()
+        || std::iter::zip(&proof.storage_proof, &storage_slots)
+            .any(|(sp, slot)| H256::from_uint(&sp.key) != *slot)
+    {
+        return Err(format!(
+            "Storage proofs returned for {} do not match the requested slots",
+            address
+        ));
+    }
+
+    mpt::verify_eip1186_proof(state_root, address, &proof)?;
+
+    Ok(proof)
+}
+
+/// This function searches the first `max_slot` storage slots of a contract for a mapping whose
+/// entry for `map_key` satisfies `is_expected`, and returns its base slot and the entry's value.
+/// The entry found is double-checked against a verified storage proof.
+pub(crate) async fn find_mapping_slot(
+    eth_connection: &Provider<FailoverHttp>,
+    contract_address: Address,
+    map_key: H256,
+    block_number: U64,
+    max_slot: u64,
+    is_expected: impl Fn(EthersU256) -> bool,
+) -> Result<Option<(u64, EthersU256)>, String> {
+    for slot in 0..max_slot {
+        let location = map_storage_slot(H256::from_uint(&slot.into()), vec![map_key]);
+        let value = eth_connection
+            .get_storage_at(
+                contract_address,
+                location,
+                Some(BlockId::from(block_number.as_u64())),
+            )
+            .await
+            .map_err(|e| format!("Error reading storage slot {}: {}", slot, e))?
+            .into_uint();
+
+        if value.is_zero() || !is_expected(value) {
+            continue;
+        }
+
+        let proof = get_verified_proof(
+            eth_connection,
+            contract_address,
+            vec![location],
+            block_number,
+        )
+        .await
+        .map_err(|e| format!("Error getting proof of storage slot {}: {}", slot, e))?;
+        if proof.storage_proof[0].value != value {
+            return Err(format!(
+                "Storage slot {} does not match its storage proof",
+                slot
+            ));
+        }
+
+        return Ok(Some((slot, value)));
+    }
+
+    Ok(None)
+}
+
+/// Fetches the storage proof of the slot holding the ownership of the given NFT
 pub(crate) async fn get_nft_ownership_proof(
-    eth_connection: Provider<Http>,
+    eth_connection: Provider<FailoverHttp>,
     nft_id: EthersU256,
     start_block_number: U64,
     nouns_token_address: Address,
+    token_layout: &TokenStorageLayout,
 ) -> Result<(EthersU256, StorageProof), String> {
-    let nft_account_proof = eth_connection
-        .get_proof(
-            nouns_token_address,
-            vec![map_storage_slot(
-                H256::from_uint(&NFT_OWNER_SLOT_OFFSET.into()),
-                vec![H256::from_uint(&nft_id)],
-            )],
-            Some(BlockId::from(start_block_number.as_u64())),
-        )
-        .await
-        .map_err(|e| format!("Error getting NFT account proof: {}", e))?;
+    let nft_account_proof = get_verified_proof(
+        &eth_connection,
+        nouns_token_address,
+        vec![token_layout.owner_slot(nft_id)],
+        start_block_number,
+    )
+    .await
+    .map_err(|e| format!("Error getting NFT account proof: {}", e))?;
 
     // Validate the proof
     if let Err(err) = validate_proof(&nft_account_proof.storage_proof[0].proof) {
@@ -65,22 +159,20 @@ pub(crate) async fn get_nft_ownership_proof(
 }
 
 pub(crate) async fn get_delegation_proof(
-    eth_connection: Provider<Http>,
+    eth_connection: Provider<FailoverHttp>,
     address: Address,
     start_block_number: U64,
     nouns_token_address: Address,
+    token_layout: &TokenStorageLayout,
 ) -> Result<(EthersU256, StorageProof), String> {
-    let delegation_proof = eth_connection
-        .get_proof(
-            nouns_token_address,
-            vec![map_storage_slot(
-                H256::from_uint(&DELEGATE_SLOT_OFFSET.into()),
-                vec![H256::from(address)],
-            )],
-            Some(BlockId::from(start_block_number.as_u64())),
-        )
-        .await
-        .map_err(|e| format!("Error getting delegation proof: {}", e))?;
+    let delegation_proof = get_verified_proof(
+        &eth_connection,
+        nouns_token_address,
+        vec![token_layout.delegate_slot(address)?],
+        start_block_number,
+    )
+    .await
+    .map_err(|e| format!("Error getting delegation proof: {}", e))?;
 
     // Validate the proof
     if let Err(err) = validate_proof(&delegation_proof.storage_proof[0].proof) {
@@ -97,45 +189,31 @@ pub(crate) async fn get_delegation_proof(
 }
 
 pub(crate) async fn get_zk_registry_proof(
-    eth_connection: &Provider<Http>,
+    eth_connection: &Provider<FailoverHttp>,
     nft_owner: Address,
     start_block_number: U64,
     zk_registry_address: Address,
+    token_layout: &TokenStorageLayout,
 ) -> Result<(EthersU256, StorageProof), String> {
-    let zk_registry_proof = eth_connection
-        .get_proof(
-            zk_registry_address,
-            vec![
-                map_storage_slot(
-                    H256::from_uint(&REGISTRY_SLOT_OFFSET.into()),
-                    vec![
-                        H256::from_uint(&BBJJ_INTERFACE_X_ID.into()),
-                        H256::from(nft_owner),
-                    ],
-                ),
-                map_storage_slot(
-                    H256::from_uint(&REGISTRY_SLOT_OFFSET.into()),
-                    vec![
-                        H256::from_uint(&BBJJ_INTERFACE_Y_ID.into()),
-                        H256::from(nft_owner),
-                    ],
-                ),
-            ],
-            Some(BlockId::from(start_block_number.as_u64())),
-        )
-        .await
-        .map_err(|_| format!("Error getting ZKRegistry proof"))?;
-
-    // Validate the proof
-    if let Err(err) = validate_proof(&zk_registry_proof.storage_proof[0].proof) {
+    let zk_registry_proof = get_verified_proof(
+        eth_connection,
+        zk_registry_address,
+        vec![token_layout.registry_slot(POSEIDON_INTERFACE_ID, nft_owner)],
+        start_block_number,
+    )
+    .await
+    .map_err(|e| format!("Error getting ZKRegistry proof: {}", e))?;
+
+    // Validate the proof of the key commitment
+    let registry_account_state_proof = zk_registry_proof
+        .storage_proof
+        .get(0)
+        .ok_or("Error getting ZKRegistry proof")?;
+    if let Err(err) = validate_proof(&registry_account_state_proof.proof) {
         return Err(format!("Invalid ZKRegistry proof: {}", err));
     }
 
     let registry_account_state_hash = zk_registry_proof.storage_hash.into_uint();
-    let registry_account_state_proof = zk_registry_proof
-        .storage_proof
-        .get(0) // TODO: we currently only provide storage proof of the X coordinate, however we need to provide both for protocol soundness
-        .ok_or("Error getting ZKRegistry state proof")?;
 
     Ok((
         registry_account_state_hash,
@@ -164,15 +242,18 @@ fn validate_proof(proof: &Vec<Bytes>) -> Result<Option<()>, String> {
 }
 
 pub(crate) async fn get_state_proof(
-    eth_connection: &Provider<Http>,
+    eth_connection: &Provider<FailoverHttp>,
     block_number: U64,
     address: Address,
 ) -> Result<StateProof, String> {
-    // Call eth_getProof
+    let state_root = get_state_root(eth_connection, block_number).await?;
+
+    // Call eth_getProof and verify the account proof against the block's state root
     let proof_data = eth_connection
         .get_proof(address, vec![], Some(block_number.into()))
         .await
-        .expect("Error getting state proof");
+        .map_err(|e| format!("Error getting state proof for address {}: {}", address, e))?;
+    mpt::verify_eip1186_proof(state_root, address, &proof_data)?;
 
     // Form proof in the form of a path
     let proof = proof_data.account_proof;
@@ -185,18 +266,11 @@ pub(crate) async fn get_state_proof(
         ));
     }
 
-    // Extract value in RLP form
-    // TODO: Integrity check
-    let value = rlp::Rlp::new(
-        proof
-            .last() // Terminal proof node
-            .expect("Error: State proof empty"),
-    ) // Proof should have been non-empty
-    .as_list::<Vec<u8>>()
-    .expect("Error: Invalid RLP encoding")
-    .last() // Extract value
-    .expect("Error: RLP list empty")
-    .clone();
+    // Extract value in RLP form. The account has to exist for the circuit to prove its storage root.
+    let value = mpt::verify_proof(state_root, address.as_bytes(), &proof)?.ok_or(format!(
+        "Account {} does not exist at block {}",
+        address, block_number
+    ))?;
 
     Ok(StateProof {
         key: address,
@@ -205,30 +279,170 @@ pub(crate) async fn get_state_proof(
     })
 }
 
+/// Block header layouts, named after the fork that introduced them
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum HeaderFork {
+    London,
+    Shanghai,
+    Cancun,
+    Prague,
+}
+
+impl HeaderFork {
+    /// Determines the header layout of a block from the fields returned by the node
+    pub(crate) fn of_block(block: &Block<H256>) -> Result<Self, String> {
+        let has_cancun_fields = [
+            block.blob_gas_used.is_some(),
+            block.excess_blob_gas.is_some(),
+            block.parent_beacon_block_root.is_some(),
+        ];
+
+        let fork = if block.other.contains_key("requestsHash") {
+            HeaderFork::Prague
+        } else if has_cancun_fields.contains(&true) {
+            HeaderFork::Cancun
+        } else if block.withdrawals_root.is_some() {
+            HeaderFork::Shanghai
+        } else if block.base_fee_per_gas.is_some() {
+            HeaderFork::London
+        } else {
+            return Err("Error: Blocks before the London fork are not supported.".to_string());
+        };
+
+        if fork >= HeaderFork::Cancun && has_cancun_fields.contains(&false) {
+            return Err(format!(
+                "Error: Incomplete {:?} block header returned by the node.",
+                fork
+            ));
+        }
+
+        Ok(fork)
+    }
+
+    /// Number of fields in the RLP-encoded block header
+    pub(crate) fn num_fields(self) -> usize {
+        match self {
+            HeaderFork::London => 16,
+            HeaderFork::Shanghai => 17,
+            HeaderFork::Cancun => 20,
+            HeaderFork::Prague => 21,
+        }
+    }
+}
+
+/// Reads the `requestsHash` header field, which ethers does not know about, from the block's extra fields
+fn requests_hash(block: &Block<H256>) -> Result<H256, String> {
+    block
+        .other
+        .get_deserialized("requestsHash")
+        .ok_or("Error: Block header is missing the requestsHash field.")?
+        .map_err(|e| format!("Error decoding the requestsHash block header field: {}", e))
+}
+
+/// Encodes the header of the given block according to the fork active at that block
+/// and checks that it hashes to the block hash reported by the node.
 pub(crate) fn header_from_block(block: &Block<H256>) -> Result<BlockHeader, String> {
-    let fork_headsup = "Error: Should be on Shanghai fork.";
+    let missing = |field: &str| format!("Error: Block header is missing the {} field.", field);
 
-    let mut block_header = rlp::RlpStream::new_list(17);
+    let fork = HeaderFork::of_block(block)?;
+    let mut block_header = rlp::RlpStream::new_list(fork.num_fields());
 
     block_header.append(&block.parent_hash);
     block_header.append(&block.uncles_hash);
-    block_header.append(&block.author.unwrap());
+    block_header.append(&block.author.ok_or(missing("miner"))?);
     block_header.append(&block.state_root);
     block_header.append(&block.transactions_root);
     block_header.append(&block.receipts_root);
-    block_header.append(&block.logs_bloom.unwrap());
+    block_header.append(&block.logs_bloom.ok_or(missing("logsBloom"))?);
     block_header.append(&block.difficulty);
-    block_header.append(&block.number.unwrap());
+    block_header.append(&block.number.ok_or(missing("number"))?);
     block_header.append(&block.gas_limit);
     block_header.append(&block.gas_used);
     block_header.append(&block.timestamp);
-    block_header.append(&block.extra_data.as_ref()); // ...
-    block_header.append(&block.mix_hash.unwrap());
-    block_header.append(&block.nonce.unwrap());
-    block_header.append(&block.base_fee_per_gas.expect(fork_headsup));
-    block_header.append(&block.withdrawals_root.expect(fork_headsup));
+    block_header.append(&block.extra_data.as_ref());
+    block_header.append(&block.mix_hash.ok_or(missing("mixHash"))?);
+    block_header.append(&block.nonce.ok_or(missing("nonce"))?);
+    block_header.append(&block.base_fee_per_gas.ok_or(missing("baseFeePerGas"))?);
+    if fork >= HeaderFork::Shanghai {
+        block_header.append(&block.withdrawals_root.ok_or(missing("withdrawalsRoot"))?);
+    }
+    if fork >= HeaderFork::Cancun {
+        block_header.append(&block.blob_gas_used.ok_or(missing("blobGasUsed"))?);
+        block_header.append(&block.excess_blob_gas.ok_or(missing("excessBlobGas"))?);
+        block_header.append(
+            &block
+                .parent_beacon_block_root
+                .ok_or(missing("parentBeaconBlockRoot"))?,
+        );
+    }
+    if fork >= HeaderFork::Prague {
+        block_header.append(&requests_hash(block)?);
+    }
+
+    let block_header = block_header.out();
+
+    // Make sure the encoding is the one the block hash commits to
+    let block_hash = block.hash.ok_or(missing("hash"))?;
+    let header_hash = H256::from(keccak256(&block_header));
+    if header_hash != block_hash {
+        return Err(format!(
+            "Error: The {:?} block header encodes to hash {:?}, but the block hash is {:?}.",
+            fork, header_hash, block_hash
+        ));
+    }
 
-    Ok(BlockHeader(block_header.out().into()))
+    if block_header.len() > noir::MAX_BLOCK_HEADER_SIZE {
+        return Err(format!(
+            "Error: Block header is {} bytes long, which exceeds the maximum of {} bytes.",
+            block_header.len(),
+            noir::MAX_BLOCK_HEADER_SIZE
+        ));
+    }
+
+    Ok(BlockHeader(block_header.into()))
+}
+
+/// Fetches the headers of the blocks from `block_number` back to the child of `ancestor_number`
+/// and returns them together with the hashes of the first block and of the ancestor
+pub(crate) async fn get_header_chain(
+    eth_connection: &Provider<FailoverHttp>,
+    block_number: U64,
+    ancestor_number: U64,
+) -> Result<(H256, Vec<BlockHeader>, H256), String> {
+    if ancestor_number >= block_number {
+        return Err(format!(
+            "Error: Block {} is not an ancestor of block {}.",
+            ancestor_number, block_number
+        ));
+    }
+
+    let mut block_hash = None;
+    let mut block_headers = vec![];
+    let mut parent_hash = None;
+
+    for number in (ancestor_number.as_u64() + 1..=block_number.as_u64()).rev() {
+        let block = eth_connection
+            .get_block(number)
+            .await
+            .map_err(|e| format!("Error obtaining block {}: {}", number, e))?
+            .ok_or(format!("Error: Block {} not found", number))?;
+        let block_header = header_from_block(&block)?;
+
+        // Each block must be the parent of the previously fetched one
+        if parent_hash.is_some() && parent_hash != block.hash {
+            return Err(format!(
+                "Error: Block {} is not the parent of block {}. The chain may have been reorganised.",
+                number,
+                number + 1
+            ));
+        }
+
+        block_hash = block_hash.or(block.hash);
+        block_headers.push(block_header);
+        parent_hash = Some(block.parent_hash);
+    }
+
+    Ok((block_hash.unwrap(), block_headers, parent_hash.unwrap()))
 }
 
 #[cfg(test)]
@@ -240,9 +454,70 @@ mod test {
     use ethers::prelude::{
         Address, BigEndianHash, BlockId, Http, Middleware, Provider, ProviderExt, H256, U256,
     };
+    use ethers::types::Block;
+    use serde_json::json;
 
     use crate::ethereum::contract_interactions::NounsToken;
-    use crate::ethereum::proofs::map_storage_slot;
+    use crate::ethereum::proofs::{header_from_block, map_storage_slot, HeaderFork};
+
+    /// Block 1 of a local Shanghai devnet, as returned by `eth_getBlockByNumber`.
+    /// Its header is the one used in the `hash_proof` circuit test.
+    fn shanghai_block() -> serde_json::Value {
+        json!({
+            "hash": "0x3d9aa3a42417ba4dc4c0155fe01a611631e772e7a70d7c43d6b5254cdfc8199c",
+            "parentHash": "0x250d0d587a2759d839bdbf44c17022713a854a73b13d43f7843595106075de5e",
+            "sha3Uncles": "0x1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347",
+            "miner": "0x0000000000000000000000000000000000000000",
+            "stateRoot": "0x16d92e35d98a0ae31e64c851be55916cfe57c66c562b596bf3926152c4719005",
+            "transactionsRoot": "0xa9132199215361968209c9f9809624b28168d1c62c20f3a367fa00174177283f",
+            "receiptsRoot": "0x00be823e177e0469335b6718756e7abdeb130904d0296efad13300a77af1864e",
+            "logsBloom": format!("0x{}", "0".repeat(512)),
+            "difficulty": "0x0",
+            "number": "0x1",
+            "gasLimit": "0x6691b7",
+            "gasUsed": "0x127aaf",
+            "timestamp": "0x64aee1d7",
+            "extraData": "0x",
+            "mixHash": "0x1477a6384a37dabad834f04a7e9b23acf5a85286f7ca73575248e9815c880293",
+            "nonce": "0x0000000000000000",
+            "baseFeePerGas": "0x342770c0",
+            "withdrawalsRoot": "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421",
+            "uncles": [],
+            "transactions": [],
+        })
+    }
+
+    #[test]
+    fn test_shanghai_header_encoding() -> Result<(), String> {
+        let block: Block<H256> = serde_json::from_value(shanghai_block()).unwrap();
+
+        assert_eq!(HeaderFork::of_block(&block)?, HeaderFork::Shanghai);
+        let block_header = header_from_block(&block)?;
+        assert_eq!(block_header.0.len(), 544);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cancun_header_encoding() {
+        let mut block_json = shanghai_block();
+        block_json["blobGasUsed"] = json!("0x0");
+        block_json["excessBlobGas"] = json!("0x0");
+        block_json["parentBeaconBlockRoot"] = json!(format!("0x{}", "0".repeat(64)));
+
+        let block: Block<H256> = serde_json::from_value(block_json.clone()).unwrap();
+        assert_eq!(HeaderFork::of_block(&block), Ok(HeaderFork::Cancun));
+        // The block hash commits to the Shanghai header, so the Cancun encoding must not match it
+        assert!(header_from_block(&block).is_err());
+
+        // Cancun headers must carry all three new fields
+        block_json
+            .as_object_mut()
+            .unwrap()
+            .remove("parentBeaconBlockRoot");
+        let block: Block<H256> = serde_json::from_value(block_json).unwrap();
+        assert!(HeaderFork::of_block(&block).is_err());
+    }
 
     #[tokio::test]
     async fn test_nft_ownership_proof() -> Result<(), String> {
@@ -0,0 +1,389 @@
+use std::fmt::{Debug, Display, Formatter};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use ethers::prelude::{
+    is_local_endpoint, Chain, Http, HttpClientError, JsonRpcClient, JsonRpcError, Middleware,
+    Provider, ProviderError, RpcError, DEFAULT_LOCAL_POLL_INTERVAL,
+};
+use ethers::types::{Bytes, Transaction, H256};
+use ethers::utils::keccak256;
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Number of passes over the list of endpoints before giving up
+const MAX_ROUNDS: u32 = 3;
+
+/// Delay before the first retry, doubled after every failed attempt
+const INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+
+/// Upper bound on the delay between attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(8);
+
+/// Fragments of the error messages returned by nodes that do not keep the state of past blocks
+const MISSING_STATE_ERRORS: [&str; 9] = [
+    "missing trie node",
+    "header not found",
+    "state not available",
+    "state is not available",
+    "historical state",
+    "pruned",
+    "proof window",
+    "archive",
+    "state histories",
+];
+
+/// Fragments of the error messages returned by rate-limited endpoints
+const RATE_LIMIT_ERRORS: [&str; 3] = ["rate limit", "too many requests", "limit exceeded"];
+
+/// Fragments of the error messages returned by nodes that already have the transaction being sent
+const KNOWN_TRANSACTION_ERRORS: [&str; 3] =
+    ["already known", "known transaction", "already imported"];
+
+/// An HTTP JSON-RPC transport over an ordered list of endpoints.
+/// Requests go to the first endpoint and move on to the next one if it is unreachable,
+/// rate-limited or lacks the state of the requested block, backing off between attempts.
+/// Error responses that do not depend on the endpoint, such as reverted calls, are returned as is.
+/// Transactions signed by the node are only sent once, as every attempt may send a new transaction.
+#[derive(Clone, Debug)]
+pub struct FailoverHttp {
+    endpoints: Vec<(String, Http)>,
+}
+
+/// The error of a request on a single endpoint
+#[derive(Debug)]
+pub struct EndpointError {
+    /// The host of the endpoint, as its URL may contain an API key
+    pub endpoint: String,
+    pub error: HttpClientError,
+}
+
+#[derive(Debug)]
+pub enum FailoverError {
+    /// An error response that any endpoint would have returned
+    Rpc(EndpointError),
+    /// Every endpoint failed, with the last error of each
+    Exhausted(Vec<EndpointError>),
+}
+
+/// How a failed request should be handled
+#[derive(Debug, PartialEq, Eq)]
+enum Failure {
+    /// The endpoint does not have the state of the requested block
+    MissingState,
+    /// The endpoint is unreachable or rate-limited, so the request may succeed later
+    Transient,
+    /// The request itself failed
+    Permanent,
+}
+
+impl FailoverHttp {
+    /// Creates a transport over the given endpoints, in order of preference
+    pub fn new(urls: &[String]) -> Result<Self, String> {
+        if urls.is_empty() {
+            return Err("No RPC URL given".to_string());
+        }
+
+        let endpoints = urls
+            .iter()
+            .map(|url| {
+                let url = Url::parse(url).map_err(|e| format!("Invalid RPC URL: {}", e))?;
+                let endpoint = url.host_str().unwrap_or("unknown host").to_string();
+                Ok((endpoint, Http::new(url)))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(FailoverHttp { endpoints })
+    }
+}
+
+/// Connects to the given endpoints, in order of preference, polling at the pace of the chain
+pub async fn connect(urls: &[String]) -> Result<Provider<FailoverHttp>, String> {
+    let provider = Provider::new(FailoverHttp::new(urls)?);
+
+    if is_local_endpoint(&urls[0]) {
+        return Ok(provider.interval(DEFAULT_LOCAL_POLL_INTERVAL));
+    }
+
+    let block_time = provider
+        .get_chainid()
+        .await
+        .ok()
+        .and_then(|chain_id| Chain::try_from(chain_id).ok())
+        .and_then(|chain| chain.average_blocktime_hint());
+
+    Ok(match block_time {
+        Some(block_time) => provider.interval(block_time / 2),
+        None => provider,
+    })
+}
+
+fn classify(error: &HttpClientError) -> Failure {
+    match error {
+        HttpClientError::ReqwestError(_) => Failure::Transient,
+        // Failing endpoints often answer with an HTML error page
+        HttpClientError::SerdeJson { .. } => Failure::Transient,
+        HttpClientError::JsonRpcError(error) => classify_response(error),
+    }
+}
+
+fn classify_response(error: &JsonRpcError) -> Failure {
+    let message = error.message.to_lowercase();
+
+    if MISSING_STATE_ERRORS.iter().any(|m| message.contains(m)) {
+        Failure::MissingState
+    } else if error.code == 429 || RATE_LIMIT_ERRORS.iter().any(|m| message.contains(m)) {
+        Failure::Transient
+    } else {
+        Failure::Permanent
+    }
+}
+
+/// The hash of the signed transaction in `params` if the endpoint failed to take it because it already has it.
+/// A nonce that is too low may also belong to another transaction of the account, so the endpoint is asked for it.
+async fn resent_transaction_hash<T: Serialize>(
+    http: &Http,
+    params: &T,
+    error: &HttpClientError,
+) -> Option<H256> {
+    let message = error.as_error_response()?.message.to_lowercase();
+    let [raw_transaction]: [Bytes; 1] =
+        serde_json::from_value(serde_json::to_value(params).ok()?).ok()?;
+    let hash = H256::from(keccak256(raw_transaction));
+
+    if KNOWN_TRANSACTION_ERRORS.iter().any(|m| message.contains(m)) {
+        return Some(hash);
+    }
+    if message.contains("nonce too low") {
+        let transaction: Option<Transaction> = http
+            .request("eth_getTransactionByHash", [hash])
+            .await
+            .ok()?;
+        return transaction.map(|_| hash);
+    }
+
+    None
+}
+
+#[async_trait]
+impl JsonRpcClient for FailoverHttp {
+    type Error = FailoverError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        if method == "eth_sendTransaction" {
+            let (endpoint, http) = &self.endpoints[0];
+            return http.request(method, params).await.map_err(|error| {
+                FailoverError::Rpc(EndpointError {
+                    endpoint: endpoint.clone(),
+                    error,
+                })
+            });
+        }
+
+        // Endpoints lacking the requested state are not retried
+        let mut failures: Vec<Option<(EndpointError, Failure)>> =
+            self.endpoints.iter().map(|_| None).collect();
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempted = false;
+
+        for _ in 0..MAX_ROUNDS {
+            for (i, (endpoint, http)) in self.endpoints.iter().enumerate() {
+                if matches!(failures[i], Some((_, Failure::MissingState))) {
+                    continue;
+                }
+
+                if failures.iter().any(Option::is_some) {
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+
+                let error = match http.request(method, &params).await {
+                    Ok(response) => return Ok(response),
+                    Err(error) => error,
+                };
+
+                // A failed attempt to send a signed transaction may still have reached the network
+                if attempted && method == "eth_sendRawTransaction" {
+                    if let Some(hash) = resent_transaction_hash(http, &params, &error).await {
+                        if let Ok(response) = serde_json::from_value(serde_json::json!(hash)) {
+                            return Ok(response);
+                        }
+                    }
+                }
+                attempted = true;
+
+                let failure = classify(&error);
+                let error = EndpointError {
+                    endpoint: endpoint.clone(),
+                    error,
+                };
+                if failure == Failure::Permanent {
+                    return Err(FailoverError::Rpc(error));
+                }
+
+                failures[i] = Some((error, failure));
+            }
+
+            if failures
+                .iter()
+                .all(|f| matches!(f, Some((_, Failure::MissingState))))
+            {
+                break;
+            }
+        }
+
+        Err(FailoverError::Exhausted(
+            failures.into_iter().flatten().map(|(e, _)| e).collect(),
+        ))
+    }
+}
+
+impl FailoverError {
+    /// The error the request failed with last
+    fn last_error(&self) -> Option<&HttpClientError> {
+        match self {
+            FailoverError::Rpc(error) => Some(&error.error),
+            FailoverError::Exhausted(errors) => errors.last().map(|e| &e.error),
+        }
+    }
+}
+
+impl Display for EndpointError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.endpoint, self.error)?;
+        if classify(&self.error) == Failure::MissingState {
+            write!(f, " (the node does not keep the state of past blocks)")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for FailoverError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FailoverError::Rpc(error) => write!(f, "{}", error),
+            FailoverError::Exhausted(errors) => {
+                if errors
+                    .iter()
+                    .all(|e| classify(&e.error) == Failure::MissingState)
+                {
+                    write!(f, "No RPC endpoint has the state of the requested block. An archive node is needed.")?;
+                } else {
+                    write!(f, "All RPC endpoints failed.")?;
+                }
+
+                for error in errors {
+                    write!(f, "\n  {}", error)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for FailoverError {}
+
+impl RpcError for FailoverError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        self.last_error()?.as_error_response()
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        self.last_error()?.as_serde_error()
+    }
+}
+
+impl From<FailoverError> for ProviderError {
+    fn from(src: FailoverError) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(src))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::prelude::{Http, HttpClientError, JsonRpcError};
+    use ethers::types::{Bytes, H256};
+    use ethers::utils::keccak256;
+
+    use crate::ethereum::provider::{
+        classify_response, resent_transaction_hash, FailoverHttp, Failure,
+    };
+
+    #[test]
+    fn test_error_classification() {
+        let response = |code: i64, message: &str| JsonRpcError {
+            code,
+            message: message.to_string(),
+            data: None,
+        };
+
+        // Geth, Erigon and hosted providers without archive state
+        for message in [
+            "missing trie node 5ee3b1b1b0b6a3b8e4e0e2f2f9a1d0c2b3a4e5f6a7b8c9d0e1f2a3b4c5d6e7f8 (path ) state 0x5ee3b1 is not available",
+            "distance to target block exceeds maximum proof window",
+            "header not found",
+            "Requested historical state is not available. Use an archive node.",
+        ] {
+            assert_eq!(
+                classify_response(&response(-32000, message)),
+                Failure::MissingState
+            );
+        }
+
+        assert_eq!(
+            classify_response(&response(429, "Too Many Requests")),
+            Failure::Transient
+        );
+        assert_eq!(
+            classify_response(&response(-32005, "daily request count limit exceeded")),
+            Failure::Transient
+        );
+        assert_eq!(
+            classify_response(&response(3, "execution reverted")),
+            Failure::Permanent
+        );
+
+        assert!(FailoverHttp::new(&[]).is_err());
+        assert!(FailoverHttp::new(&["not a url".to_string()]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resent_transaction() {
+        let error = |message: &str| {
+            HttpClientError::JsonRpcError(JsonRpcError {
+                code: -32000,
+                message: message.to_string(),
+                data: None,
+            })
+        };
+        // Nothing listens there, so the endpoint cannot confirm any transaction
+        let http = Http::new(reqwest::Url::parse("http://127.0.0.1:1").unwrap());
+        let raw_transaction = Bytes::from(vec![0x02, 0xf8, 0x6f]);
+        let hash = H256::from(keccak256(&raw_transaction));
+
+        assert_eq!(
+            resent_transaction_hash(&http, &[&raw_transaction], &error("already known")).await,
+            Some(hash)
+        );
+        assert_eq!(
+            resent_transaction_hash(&http, &[&raw_transaction], &error("nonce too low")).await,
+            None
+        );
+        assert_eq!(
+            resent_transaction_hash(
+                &http,
+                &[&raw_transaction],
+                &error("insufficient funds for gas * price + value")
+            )
+            .await,
+            None
+        );
+    }
+}
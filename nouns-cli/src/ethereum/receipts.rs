@@ -0,0 +1,300 @@
+use std::path::{Path, PathBuf};
+
+use ark_ff::{BigInteger, PrimeField};
+use ethers::core::k256::U256;
+use ethers::core::rand::thread_rng;
+use ethers::prelude::{Address, H256};
+use ethers::utils::hex;
+use serde::{Deserialize, Serialize};
+
+use nouns_protocol::{
+    wrap, wrap_into, BBJJ_Ec, BBJJ_Fr, BN254_Fr, SpoiledBallot, VoteChoice, VoteReceipt, Wrapper,
+};
+
+use crate::EthersU256;
+
+/// A vote receipt along with the vote it was issued for
+pub(crate) struct StoredReceipt {
+    pub(crate) receipt: VoteReceipt,
+    pub(crate) chain_id: u64,
+    pub(crate) contract_address: Address,
+    /// The transaction that cast the ballot
+    pub(crate) transaction_hash: H256,
+}
+
+/// The contents of a spoiled ballot file, which is not encrypted as the ballot is never cast
+#[derive(Serialize, Deserialize)]
+struct SpoiledBallotFile {
+    chain_id: EthersU256,
+    contract_address: Address,
+    process_id: EthersU256,
+    tlcs_public_key: [EthersU256; 2],
+    a: [EthersU256; 2],
+    b: EthersU256,
+    vote_choice: String,
+    blinding_factor: EthersU256,
+}
+
+/// The contents of a receipt file, before encryption
+#[derive(Serialize, Deserialize)]
+struct ReceiptFile {
+    chain_id: u64,
+    contract_address: Address,
+    transaction_hash: H256,
+    process_id: EthersU256,
+    nullifier: EthersU256,
+    a: [EthersU256; 2],
+    b: EthersU256,
+    vote_choice: String,
+    blinding_factor: EthersU256,
+}
+
+impl StoredReceipt {
+    /// Encrypts the receipt with `password` into a new file in `dir`, which is created if needed
+    pub(crate) fn save(&self, dir: &Path, password: &[u8]) -> Result<PathBuf, String> {
+        let receipt = &self.receipt;
+        let a: [U256; 2] = wrap_into!(receipt.a.clone());
+        let file = ReceiptFile {
+            chain_id: self.chain_id,
+            contract_address: self.contract_address,
+            transaction_hash: self.transaction_hash,
+            process_id: wrap_into!(receipt.process_id),
+            nullifier: fr_to_u256(receipt.nullifier),
+            a: a.map(|x| wrap_into!(x)),
+            b: fr_to_u256(receipt.b),
+            vote_choice: receipt.vote_choice.to_string(),
+            blinding_factor: bbjj_fr_to_u256(receipt.blinding_factor),
+        };
+        let contents = serde_json::to_vec(&file)
+            .map_err(|e| format!("Error serialising vote receipt: {}", e))?;
+
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Error creating receipt directory {}: {}", dir.display(), e))?;
+        let nullifier: [u8; 32] = file.nullifier.into();
+        let name = format!(
+            "receipt-{}-{}.json",
+            file.process_id,
+            hex::encode(&nullifier[..8])
+        );
+        eth_keystore::encrypt_key(dir, &mut thread_rng(), contents, password, Some(&name))
+            .map_err(|e| format!("Error encrypting vote receipt: {}", e))?;
+
+        Ok(dir.join(name))
+    }
+
+    /// Decrypts a receipt file with `password`
+    pub(crate) fn load(path: &Path, password: &[u8]) -> Result<Self, String> {
+        let contents = eth_keystore::decrypt_key(path, password).map_err(|e| {
+            format!(
+                "Error decrypting vote receipt {}. Is the key right? {}",
+                path.display(),
+                e
+            )
+        })?;
+        let file: ReceiptFile = serde_json::from_slice(&contents)
+            .map_err(|e| format!("Invalid vote receipt {}: {}", path.display(), e))?;
+
+        let vote_choice = parse_vote_choice(&file.vote_choice)?;
+        let a: [U256; 2] = file.a.map(|x| wrap_into!(x));
+
+        Ok(StoredReceipt {
+            receipt: VoteReceipt {
+                process_id: wrap_into!(file.process_id),
+                nullifier: u256_to_fr(file.nullifier)?,
+                a: wrap_into!(a),
+                b: u256_to_fr(file.b)?,
+                vote_choice,
+                blinding_factor: u256_to_bbjj_fr(file.blinding_factor),
+            },
+            chain_id: file.chain_id,
+            contract_address: file.contract_address,
+            transaction_hash: file.transaction_hash,
+        })
+    }
+
+    /// The first part of the encrypted vote, as found in the `BallotCast` events
+    pub(crate) fn a(&self) -> [EthersU256; 2] {
+        let a: [U256; 2] = wrap_into!(self.receipt.a.clone());
+        a.map(|x| wrap_into!(x))
+    }
+
+    /// The second part of the encrypted vote, as found in the `BallotCast` events
+    pub(crate) fn b(&self) -> EthersU256 {
+        fr_to_u256(self.receipt.b)
+    }
+}
+
+/// Saves a spoiled ballot into a new file in `dir`, which is created if needed
+pub(crate) fn save_spoiled_ballot(ballot: &SpoiledBallot, dir: &Path) -> Result<PathBuf, String> {
+    let chain_id: EthersU256 = wrap_into!(ballot.chain_id);
+    let a: [U256; 2] = wrap_into!(ballot.a.clone());
+    let file = SpoiledBallotFile {
+        chain_id,
+        contract_address: ballot.contract_addr,
+        process_id: wrap_into!(ballot.process_id),
+        tlcs_public_key: {
+            let tlcs_public_key: [U256; 2] = wrap_into!(ballot.tlcs_pk.clone());
+            tlcs_public_key.map(|x| wrap_into!(x))
+        },
+        a: a.map(|x| wrap_into!(x)),
+        b: fr_to_u256(ballot.b),
+        vote_choice: ballot.vote_choice.to_string(),
+        blinding_factor: bbjj_fr_to_u256(ballot.blinding_factor),
+    };
+    let contents = serde_json::to_string_pretty(&file)
+        .map_err(|e| format!("Error serialising spoiled ballot: {}", e))?;
+
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("Error creating directory {}: {}", dir.display(), e))?;
+    let commitment: [u8; 32] = fr_to_u256(ballot.commitment()?).into();
+    let path = dir.join(format!(
+        "spoiled-{}-{}.json",
+        file.process_id,
+        hex::encode(&commitment[..8])
+    ));
+    std::fs::write(&path, contents)
+        .map_err(|e| format!("Error writing spoiled ballot {}: {}", path.display(), e))?;
+
+    Ok(path)
+}
+
+/// Loads a spoiled ballot saved by `save_spoiled_ballot`
+pub(crate) fn load_spoiled_ballot(path: &Path) -> Result<SpoiledBallot, String> {
+    let contents = std::fs::read(path)
+        .map_err(|e| format!("Error reading spoiled ballot {}: {}", path.display(), e))?;
+    let file: SpoiledBallotFile = serde_json::from_slice(&contents)
+        .map_err(|e| format!("Invalid spoiled ballot {}: {}", path.display(), e))?;
+
+    let a: [U256; 2] = file.a.map(|x| wrap_into!(x));
+    Ok(SpoiledBallot {
+        process_id: wrap_into!(file.process_id),
+        contract_addr: file.contract_address,
+        chain_id: wrap_into!(file.chain_id),
+        tlcs_pk: tlcs_public_key(file.tlcs_public_key),
+        a: wrap_into!(a),
+        b: u256_to_fr(file.b)?,
+        vote_choice: parse_vote_choice(&file.vote_choice)?,
+        blinding_factor: u256_to_bbjj_fr(file.blinding_factor),
+    })
+}
+
+fn parse_vote_choice(vote_choice: &str) -> Result<VoteChoice, String> {
+    match vote_choice {
+        "No" => Ok(VoteChoice::No),
+        "Yes" => Ok(VoteChoice::Yes),
+        "Abstain" => Ok(VoteChoice::Abstain),
+        _ => Err(format!("Invalid vote choice: {}", vote_choice)),
+    }
+}
+
+fn bbjj_fr_to_u256(value: BBJJ_Fr) -> EthersU256 {
+    EthersU256::from_big_endian(&value.into_bigint().to_bytes_be())
+}
+
+fn u256_to_bbjj_fr(value: EthersU256) -> BBJJ_Fr {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    BBJJ_Fr::from_be_bytes_mod_order(&bytes)
+}
+
+/// Converts a field element, such as a ballot commitment, into a number
+pub(crate) fn fr_to_u256(value: BN254_Fr) -> EthersU256 {
+    let value: U256 = wrap_into!(value);
+    wrap_into!(value)
+}
+
+fn u256_to_fr(value: EthersU256) -> Result<BN254_Fr, String> {
+    let modulus = EthersU256::from_big_endian(&BN254_Fr::MODULUS.to_bytes_be());
+    if value >= modulus {
+        return Err("Invalid field element in vote receipt".to_string());
+    }
+
+    let value: U256 = wrap_into!(value);
+    Ok(wrap_into!(value))
+}
+
+/// Converts the TLCS public key of a voting process into a curve point
+pub(crate) fn tlcs_public_key(tlcs_public_key: [EthersU256; 2]) -> BBJJ_Ec {
+    let tlcs_public_key: [U256; 2] = tlcs_public_key.map(|x| wrap_into!(x));
+    wrap_into!(tlcs_public_key)
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::core::k256::U256;
+    use ethers::prelude::{Address, H256};
+    use nouns_protocol::{BBJJ_Fr, BN254_Fr, SpoiledBallot, VoteChoice, VoteReceipt, BBJJ_G1};
+
+    use crate::ethereum::receipts::{load_spoiled_ballot, save_spoiled_ballot, StoredReceipt};
+
+    #[test]
+    fn test_receipt_roundtrip() -> Result<(), String> {
+        let dir = std::env::temp_dir().join(format!("nouns-receipts-{}", std::process::id()));
+        let blinding_factor = BBJJ_Fr::from(123456789u64);
+        let stored = StoredReceipt {
+            receipt: VoteReceipt {
+                process_id: U256::from_u64(7),
+                nullifier: BN254_Fr::from(42u64),
+                a: BBJJ_G1.mul_scalar(&blinding_factor),
+                b: BN254_Fr::from(43u64),
+                vote_choice: VoteChoice::Abstain,
+                blinding_factor,
+            },
+            chain_id: 11155111,
+            contract_address: Address::repeat_byte(1),
+            transaction_hash: H256::repeat_byte(2),
+        };
+
+        let path = stored.save(&dir, b"password")?;
+        assert!(StoredReceipt::load(&path, b"wrong password").is_err());
+
+        let loaded = StoredReceipt::load(&path, b"password")?;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded.receipt.process_id, stored.receipt.process_id);
+        assert_eq!(loaded.receipt.nullifier, stored.receipt.nullifier);
+        assert_eq!(loaded.receipt.a, stored.receipt.a);
+        assert_eq!(loaded.receipt.b, stored.receipt.b);
+        assert_eq!(loaded.receipt.vote_choice, stored.receipt.vote_choice);
+        assert_eq!(loaded.receipt.blinding_factor, blinding_factor);
+        assert_eq!(loaded.chain_id, stored.chain_id);
+        assert_eq!(loaded.contract_address, stored.contract_address);
+        assert_eq!(loaded.transaction_hash, stored.transaction_hash);
+        assert_eq!(loaded.a(), stored.a());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_spoiled_ballot_roundtrip() -> Result<(), String> {
+        let dir = std::env::temp_dir().join(format!("nouns-spoiled-{}", std::process::id()));
+        let blinding_factor = BBJJ_Fr::from(987654321u64);
+        let tlcs_pk = BBJJ_G1.mul_scalar(&BBJJ_Fr::from(5u64));
+        let spoiled_ballot = SpoiledBallot {
+            process_id: U256::from_u64(7),
+            contract_addr: Address::repeat_byte(1),
+            chain_id: U256::from_u64(11155111),
+            tlcs_pk: tlcs_pk.clone(),
+            a: BBJJ_G1.mul_scalar(&blinding_factor),
+            b: BN254_Fr::from(43u64),
+            vote_choice: VoteChoice::No,
+            blinding_factor,
+        };
+
+        let path = save_spoiled_ballot(&spoiled_ballot, &dir)?;
+        let loaded = load_spoiled_ballot(&path)?;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded.process_id, spoiled_ballot.process_id);
+        assert_eq!(loaded.contract_addr, spoiled_ballot.contract_addr);
+        assert_eq!(loaded.chain_id, spoiled_ballot.chain_id);
+        assert_eq!(loaded.tlcs_pk, tlcs_pk);
+        assert_eq!(loaded.a, spoiled_ballot.a);
+        assert_eq!(loaded.b, spoiled_ballot.b);
+        assert_eq!(loaded.vote_choice, spoiled_ballot.vote_choice);
+        assert_eq!(loaded.blinding_factor, blinding_factor);
+        assert_eq!(loaded.commitment()?, spoiled_ballot.commitment()?);
+
+        Ok(())
+    }
+}
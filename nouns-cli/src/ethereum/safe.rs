@@ -0,0 +1,320 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chrono::Utc;
+use ethers::abi::{self, Detokenize, Token};
+use ethers::contract::builders::ContractCall;
+use ethers::prelude::{abigen, Address, Bytes, Middleware, H256};
+use ethers::utils::{keccak256, to_checksum};
+
+use crate::EthersU256;
+
+/// The EIP-712 domain of Safe transactions (Safe v1.3.0 and later)
+const DOMAIN_SEPARATOR_TYPE: &str = "EIP712Domain(uint256 chainId,address verifyingContract)";
+
+/// The EIP-712 type of Safe transactions
+const SAFE_TX_TYPE: &str = "SafeTx(address to,uint256 value,bytes data,uint8 operation,uint256 safeTxGas,uint256 baseGas,uint256 gasPrice,address gasToken,address refundReceiver,uint256 nonce)";
+
+abigen!(
+    Safe,
+    r#"[
+            function nonce() view returns (uint256)
+        ]"#,
+);
+
+/// How the transactions prepared for a Safe are handed to its owners
+pub enum SafeOutput {
+    /// A Safe Transaction Builder JSON batch, written to the given file
+    TransactionBuilder(PathBuf),
+    /// The EIP-712 SafeTx hash of each transaction, printed for the owners to sign
+    SafeTxHash,
+}
+
+/// The Safe that write commands prepare their transactions for, instead of sending them
+pub struct SafeParams {
+    pub address: Address,
+    pub output: SafeOutput,
+}
+
+/// A call to be made by the Safe
+pub(crate) struct SafeTransaction {
+    pub(crate) to: Address,
+    pub(crate) data: Bytes,
+    pub(crate) description: String,
+}
+
+impl SafeTransaction {
+    /// The Safe transaction making a contract call
+    pub(crate) fn new<M: Middleware, D: Detokenize>(
+        call: &ContractCall<M, D>,
+        description: &str,
+    ) -> Result<Self, String> {
+        let to = *call
+            .tx
+            .to_addr()
+            .ok_or(format!("The {} call has no target contract", description))?;
+        let data = call
+            .calldata()
+            .ok_or(format!("The {} call has no calldata", description))?;
+
+        Ok(SafeTransaction {
+            to,
+            data,
+            description: description.to_string(),
+        })
+    }
+}
+
+/// Hands `transactions` to the owners of `safe` on the chain of `client`, in the format chosen by `safe.output`.
+/// They are executed in order: the SafeTx hashes use consecutive nonces from the current one.
+pub(crate) async fn output_transactions<M: Middleware>(
+    safe: &SafeParams,
+    client: Arc<M>,
+    transactions: &[SafeTransaction],
+    name: &str,
+) -> Result<(), String> {
+    let chain_id = client
+        .get_chainid()
+        .await
+        .map_err(|e| format!("Could not get chain id: {}", e))?
+        .as_u64();
+
+    match &safe.output {
+        SafeOutput::TransactionBuilder(path) => {
+            let batch = transaction_builder_batch(chain_id, safe.address, transactions, name);
+            let contents = serde_json::to_vec_pretty(&batch)
+                .map_err(|e| format!("Error serialising Safe transactions: {}", e))?;
+
+            // A batch waiting to be proposed is never overwritten
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)
+                .and_then(|mut file| file.write_all(&contents))
+                .map_err(|e| format!("Error writing Safe batch {}: {}", path.display(), e))?;
+
+            println!(
+                "Safe Transaction Builder batch of {} transactions written to {}, to be imported by the Safe {} on chain {}",
+                transactions.len(),
+                path.display(),
+                to_checksum(&safe.address, None),
+                chain_id
+            );
+        }
+        SafeOutput::SafeTxHash => {
+            let nonce = Safe::new(safe.address, client)
+                .nonce()
+                .call()
+                .await
+                .map_err(|e| format!("Error getting the nonce of the Safe: {e:?}"))?;
+
+            println!(
+                "Transactions of the Safe {} on chain {}, to be signed and executed in order:",
+                to_checksum(&safe.address, None),
+                chain_id
+            );
+            for (i, transaction) in transactions.iter().enumerate() {
+                let nonce = nonce + i;
+                println!("  {}. {} (nonce {})", i + 1, transaction.description, nonce);
+                println!("     To:          {}", to_checksum(&transaction.to, None));
+                println!("     Data:        {}", transaction.data);
+                println!(
+                    "     SafeTx hash: {:?}",
+                    safe_tx_hash(chain_id, safe.address, transaction, nonce)
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A Safe Transaction Builder batch of `transactions`, as imported in the Safe web interface
+fn transaction_builder_batch(
+    chain_id: u64,
+    safe: Address,
+    transactions: &[SafeTransaction],
+    name: &str,
+) -> serde_json::Value {
+    let description = transactions
+        .iter()
+        .map(|transaction| transaction.description.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let transactions = transactions
+        .iter()
+        .map(|transaction| {
+            serde_json::json!({
+                "to": to_checksum(&transaction.to, None),
+                "value": "0",
+                "data": transaction.data.to_string(),
+                "contractMethod": null,
+                "contractInputsValues": null,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    serde_json::json!({
+        "version": "1.0",
+        "chainId": chain_id.to_string(),
+        "createdAt": Utc::now().timestamp_millis(),
+        "meta": {
+            "name": name,
+            "description": description,
+            "createdFromSafeAddress": to_checksum(&safe, None),
+        },
+        "transactions": transactions,
+    })
+}
+
+/// The EIP-712 hash signed by the owners of `safe` to execute `transaction` with `nonce`.
+/// The transaction is a plain call, with no value and no gas refund.
+fn safe_tx_hash(
+    chain_id: u64,
+    safe: Address,
+    transaction: &SafeTransaction,
+    nonce: EthersU256,
+) -> H256 {
+    let domain_separator = keccak256(abi::encode(&[
+        Token::FixedBytes(keccak256(DOMAIN_SEPARATOR_TYPE).to_vec()),
+        Token::Uint(chain_id.into()),
+        Token::Address(safe),
+    ]));
+    let safe_tx = keccak256(abi::encode(&[
+        Token::FixedBytes(keccak256(SAFE_TX_TYPE).to_vec()),
+        Token::Address(transaction.to),
+        Token::Uint(EthersU256::zero()),
+        Token::FixedBytes(keccak256(&transaction.data).to_vec()),
+        Token::Uint(EthersU256::zero()), // `Call` operation
+        Token::Uint(EthersU256::zero()),
+        Token::Uint(EthersU256::zero()),
+        Token::Uint(EthersU256::zero()),
+        Token::Address(Address::zero()),
+        Token::Address(Address::zero()),
+        Token::Uint(nonce),
+    ]));
+
+    keccak256([&[0x19, 0x01], &domain_separator[..], &safe_tx[..]].concat()).into()
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use ethers::prelude::{Address, Bytes, H256};
+    use ethers::types::transaction::eip712::{Eip712, TypedData};
+    use ethers::utils::keccak256;
+    use serde_json::json;
+
+    use crate::ethereum::safe::{
+        safe_tx_hash, transaction_builder_batch, SafeTransaction, DOMAIN_SEPARATOR_TYPE,
+        SAFE_TX_TYPE,
+    };
+    use crate::EthersU256;
+
+    #[test]
+    fn test_safe_transactions() {
+        // The type hashes of the Safe contracts
+        assert_eq!(
+            H256::from(keccak256(DOMAIN_SEPARATOR_TYPE)),
+            H256::from_str("0x47e79534a245952e8b16893a336b85a3d9ea9fa8c573f3d803afb92a79469218")
+                .unwrap()
+        );
+        assert_eq!(
+            H256::from(keccak256(SAFE_TX_TYPE)),
+            H256::from_str("0xbb8310d486368db6bd6f849402fdd73ad53d316b5a4b2644ad6efe0f941286d8")
+                .unwrap()
+        );
+
+        let safe = Address::from_low_u64_be(0x5afe);
+        let transactions = [
+            SafeTransaction {
+                to: Address::from_low_u64_be(1),
+                data: Bytes::from(vec![0x12, 0x34]),
+                description: "first call".to_string(),
+            },
+            SafeTransaction {
+                to: Address::from_low_u64_be(2),
+                data: Bytes::from(vec![0x56]),
+                description: "second call".to_string(),
+            },
+        ];
+
+        let batch = transaction_builder_batch(1, safe, &transactions, "Batch");
+        assert_eq!(batch["chainId"], "1");
+        assert_eq!(
+            batch["meta"]["createdFromSafeAddress"],
+            "0x0000000000000000000000000000000000005aFE"
+        );
+        assert_eq!(batch["meta"]["description"], "first call, second call");
+        assert_eq!(batch["transactions"][0]["data"], "0x1234");
+        assert_eq!(
+            batch["transactions"][1]["to"],
+            "0x0000000000000000000000000000000000000002"
+        );
+
+        // The hash is the EIP-712 hash of the SafeTx, as encoded by ethers from its typed data
+        let hash = safe_tx_hash(1, safe, &transactions[0], EthersU256::zero());
+        let typed_data: TypedData = serde_json::from_value(json!({
+            "types": {
+                "EIP712Domain": [
+                    { "name": "chainId", "type": "uint256" },
+                    { "name": "verifyingContract", "type": "address" }
+                ],
+                "SafeTx": [
+                    { "name": "to", "type": "address" },
+                    { "name": "value", "type": "uint256" },
+                    { "name": "data", "type": "bytes" },
+                    { "name": "operation", "type": "uint8" },
+                    { "name": "safeTxGas", "type": "uint256" },
+                    { "name": "baseGas", "type": "uint256" },
+                    { "name": "gasPrice", "type": "uint256" },
+                    { "name": "gasToken", "type": "address" },
+                    { "name": "refundReceiver", "type": "address" },
+                    { "name": "nonce", "type": "uint256" }
+                ]
+            },
+            "primaryType": "SafeTx",
+            "domain": { "chainId": 1, "verifyingContract": safe },
+            "message": {
+                "to": transactions[0].to,
+                "value": "0",
+                "data": "0x1234",
+                "operation": 0,
+                "safeTxGas": "0",
+                "baseGas": "0",
+                "gasPrice": "0",
+                "gasToken": Address::zero(),
+                "refundReceiver": Address::zero(),
+                "nonce": "0"
+            }
+        }))
+        .unwrap();
+        assert_eq!(hash, H256::from(typed_data.encode_eip712().unwrap()));
+        assert_eq!(
+            hash,
+            H256::from_str("0x2ea7619cf80d9e654763fc4e1fe594c8603d77cc067adc4af9c5d903d8c05581")
+                .unwrap()
+        );
+
+        // The hash binds the chain, the Safe, the call and the nonce
+        assert_ne!(
+            hash,
+            safe_tx_hash(5, safe, &transactions[0], EthersU256::zero())
+        );
+        assert_ne!(
+            hash,
+            safe_tx_hash(1, Address::zero(), &transactions[0], EthersU256::zero())
+        );
+        assert_ne!(
+            hash,
+            safe_tx_hash(1, safe, &transactions[1], EthersU256::zero())
+        );
+        assert_ne!(
+            hash,
+            safe_tx_hash(1, safe, &transactions[0], EthersU256::one())
+        );
+    }
+}
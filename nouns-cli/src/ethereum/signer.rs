@@ -0,0 +1,316 @@
+use std::fmt::{Debug, Display, Formatter};
+use std::path::Path;
+
+use async_trait::async_trait;
+use ethers::prelude::{Http, JsonRpcClient};
+use ethers::signers::coins_bip39::English;
+use ethers::signers::{LocalWallet, MnemonicBuilder, Signer, WalletError};
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::transaction::eip712::Eip712;
+use ethers::types::{Address, Bytes, Signature};
+use ethers::utils::rlp::Rlp;
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// The derivation path of the first account of a mnemonic
+pub const DEFAULT_DERIVATION_PATH: &str = "m/44'/60'/0'/0/0";
+
+/// The signer of the transactions: a local wallet, whatever its key was loaded from, or an account of a remote signer
+#[derive(Clone, Debug)]
+pub enum TxSigner {
+    Local(LocalWallet),
+    Remote(RemoteSigner),
+}
+
+/// An account of a signer daemon that signs over JSON-RPC (`eth_signTransaction` and `eth_sign`), such as web3signer.
+/// The key never leaves the daemon, and every signature it returns is checked against the account.
+#[derive(Clone, Debug)]
+pub struct RemoteSigner {
+    client: Http,
+    /// The host of the signer, as its URL may contain credentials
+    endpoint: String,
+    address: Address,
+    chain_id: u64,
+}
+
+#[derive(Debug)]
+pub enum TxSignerError {
+    Wallet(WalletError),
+    Remote(String),
+}
+
+impl TxSigner {
+    /// A wallet with a raw private key
+    pub fn from_private_key(private_key: &[u8; 32]) -> Result<Self, String> {
+        LocalWallet::from_bytes(private_key)
+            .map(TxSigner::Local)
+            .map_err(|e| format!("Invalid transaction private key: {}", e))
+    }
+
+    /// A wallet with the key of an encrypted Ethereum JSON keystore
+    pub fn from_keystore(path: &Path, password: &str) -> Result<Self, String> {
+        LocalWallet::decrypt_keystore(path, password)
+            .map(TxSigner::Local)
+            .map_err(|e| {
+                format!(
+                    "Error decrypting keystore {}. Is the password right? {}",
+                    path.display(),
+                    e
+                )
+            })
+    }
+
+    /// A wallet with the key derived from a BIP-39 mnemonic at `derivation_path`
+    pub fn from_mnemonic(mnemonic: &str, derivation_path: &str) -> Result<Self, String> {
+        MnemonicBuilder::<English>::default()
+            .phrase(mnemonic)
+            .derivation_path(derivation_path)
+            .map_err(|e| format!("Invalid derivation path {}: {}", derivation_path, e))?
+            .build()
+            .map(TxSigner::Local)
+            .map_err(|e| format!("Invalid mnemonic: {}", e))
+    }
+
+    /// The account `address` of the signer daemon at `url`.
+    /// The account is given, as a daemon may hold several keys.
+    pub fn remote(url: &str, address: Address) -> Result<Self, String> {
+        let url = Url::parse(url).map_err(|e| format!("Invalid remote signer URL: {}", e))?;
+        let endpoint = url.host_str().unwrap_or("unknown host").to_string();
+
+        Ok(TxSigner::Remote(RemoteSigner {
+            client: Http::new(url),
+            endpoint,
+            address,
+            chain_id: 1,
+        }))
+    }
+
+    /// The local wallet, which can sign without a round trip to a daemon
+    pub fn local_wallet(&self) -> Option<&LocalWallet> {
+        match self {
+            TxSigner::Local(wallet) => Some(wallet),
+            TxSigner::Remote(_) => None,
+        }
+    }
+}
+
+impl RemoteSigner {
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, TxSignerError>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        self.client.request(method, params).await.map_err(|e| {
+            TxSignerError::Remote(format!("{} failed on {}: {}", method, self.endpoint, e))
+        })
+    }
+}
+
+#[async_trait]
+impl Signer for TxSigner {
+    type Error = TxSignerError;
+
+    async fn sign_message<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        message: S,
+    ) -> Result<Signature, Self::Error> {
+        match self {
+            TxSigner::Local(wallet) => wallet
+                .sign_message(message)
+                .await
+                .map_err(TxSignerError::Wallet),
+            TxSigner::Remote(remote) => {
+                let signature: Bytes = remote
+                    .request(
+                        "eth_sign",
+                        (remote.address, Bytes::from(message.as_ref().to_vec())),
+                    )
+                    .await?;
+                let signature = Signature::try_from(signature.as_ref()).map_err(|e| {
+                    TxSignerError::Remote(format!("Invalid signature from remote signer: {}", e))
+                })?;
+                signature
+                    .verify(message.as_ref(), remote.address)
+                    .map_err(|_| {
+                        TxSignerError::Remote(format!(
+                            "The remote signer did not sign the message with {:?}",
+                            remote.address
+                        ))
+                    })?;
+
+                Ok(signature)
+            }
+        }
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, Self::Error> {
+        match self {
+            TxSigner::Local(wallet) => wallet
+                .sign_transaction(tx)
+                .await
+                .map_err(TxSignerError::Wallet),
+            TxSigner::Remote(remote) => {
+                let mut tx = tx.clone();
+                tx.set_from(remote.address);
+                if tx.chain_id().is_none() {
+                    tx.set_chain_id(remote.chain_id);
+                }
+
+                // Signer daemons answer with the signed transaction, either as is or within an object
+                let signed: serde_json::Value =
+                    remote.request("eth_signTransaction", [&tx]).await?;
+                let raw = signed.get("raw").unwrap_or(&signed).clone();
+                let raw: Bytes = serde_json::from_value(raw).map_err(|e| {
+                    TxSignerError::Remote(format!(
+                        "Invalid signed transaction from remote signer: {}",
+                        e
+                    ))
+                })?;
+
+                signature_from_signed_transaction(&tx, &raw, remote.address)
+            }
+        }
+    }
+
+    async fn sign_typed_data<T: Eip712 + Send + Sync>(
+        &self,
+        payload: &T,
+    ) -> Result<Signature, Self::Error> {
+        match self {
+            TxSigner::Local(wallet) => wallet
+                .sign_typed_data(payload)
+                .await
+                .map_err(TxSignerError::Wallet),
+            TxSigner::Remote(_) => Err(TxSignerError::Remote(
+                "Remote signers do not sign typed data".to_string(),
+            )),
+        }
+    }
+
+    fn address(&self) -> Address {
+        match self {
+            TxSigner::Local(wallet) => wallet.address(),
+            TxSigner::Remote(remote) => remote.address,
+        }
+    }
+
+    fn chain_id(&self) -> u64 {
+        match self {
+            TxSigner::Local(wallet) => wallet.chain_id(),
+            TxSigner::Remote(remote) => remote.chain_id,
+        }
+    }
+
+    fn with_chain_id<T: Into<u64>>(self, chain_id: T) -> Self {
+        match self {
+            TxSigner::Local(wallet) => TxSigner::Local(wallet.with_chain_id(chain_id)),
+            TxSigner::Remote(remote) => TxSigner::Remote(RemoteSigner {
+                chain_id: chain_id.into(),
+                ..remote
+            }),
+        }
+    }
+}
+
+/// Takes the signature out of the `signed` transaction returned by a remote signer.
+/// It must be a signature of `tx` itself by `address`: a daemon that changed the transaction is caught here.
+fn signature_from_signed_transaction(
+    tx: &TypedTransaction,
+    signed: &Bytes,
+    address: Address,
+) -> Result<Signature, TxSignerError> {
+    let (_, signature) = TypedTransaction::decode_signed(&Rlp::new(signed)).map_err(|e| {
+        TxSignerError::Remote(format!(
+            "Invalid signed transaction from remote signer: {}",
+            e
+        ))
+    })?;
+
+    match signature.recover(tx.sighash()) {
+        Ok(signer) if signer == address => Ok(signature),
+        _ => Err(TxSignerError::Remote(format!(
+            "The remote signer did not sign the transaction as sent with {:?}",
+            address
+        ))),
+    }
+}
+
+impl Display for TxSignerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TxSignerError::Wallet(e) => write!(f, "{}", e),
+            TxSignerError::Remote(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for TxSignerError {}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use ethers::core::rand::thread_rng;
+    use ethers::signers::Signer;
+    use ethers::types::transaction::eip2718::TypedTransaction;
+    use ethers::types::{Address, Eip1559TransactionRequest, TransactionRequest};
+
+    use crate::ethereum::signer::{
+        signature_from_signed_transaction, TxSigner, DEFAULT_DERIVATION_PATH,
+    };
+
+    #[tokio::test]
+    async fn test_tx_signers() -> Result<(), String> {
+        // The first account of the mnemonic of the Hardhat and Anvil test accounts
+        let mnemonic = "test test test test test test test test test test test junk";
+        let signer = TxSigner::from_mnemonic(mnemonic, DEFAULT_DERIVATION_PATH)?;
+        assert_eq!(
+            signer.address(),
+            Address::from_str("0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266").unwrap()
+        );
+
+        // A keystore gives back the wallet it was made from
+        let dir = std::env::temp_dir().join(format!("nouns-tx-keystore-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let wallet = signer.local_wallet().unwrap();
+        eth_keystore::encrypt_key(
+            &dir,
+            &mut thread_rng(),
+            wallet.signer().to_bytes(),
+            "password",
+            Some("tx-key"),
+        )
+        .unwrap();
+        let keystore_signer = TxSigner::from_keystore(&dir.join("tx-key"), "password");
+        let wrong_password = TxSigner::from_keystore(&dir.join("tx-key"), "wrong");
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(keystore_signer?.address(), signer.address());
+        assert!(wrong_password.is_err());
+
+        // The signature of a signed transaction is only taken for that transaction and account
+        let signer = signer.with_chain_id(5u64);
+        for tx in [
+            TypedTransaction::Legacy(TransactionRequest::pay(Address::zero(), 1).chain_id(5)),
+            TypedTransaction::Eip1559(
+                Eip1559TransactionRequest::new()
+                    .to(Address::zero())
+                    .value(1)
+                    .chain_id(5),
+            ),
+        ] {
+            let signature = signer.sign_transaction(&tx).await.unwrap();
+            let signed = tx.rlp_signed(&signature);
+
+            assert!(signature_from_signed_transaction(&tx, &signed, signer.address()).is_ok());
+            assert!(signature_from_signed_transaction(&tx, &signed, Address::zero()).is_err());
+            let mut other_tx = tx.clone();
+            other_tx.set_nonce(1);
+            assert!(
+                signature_from_signed_transaction(&other_tx, &signed, signer.address()).is_err()
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,337 @@
+use std::path::{Path, PathBuf};
+
+use console::Term;
+use ethers::core::k256::U256;
+use ethers::core::rand::thread_rng;
+use ethers::prelude::LocalWallet;
+use ethers::utils::{hash_message, keccak256};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use nouns_protocol::keys::{derive_private_key, mnemonic_seed, new_mnemonic, random_private_key};
+use nouns_protocol::{wrap, wrap_into, BBJJ_Ec, PrivateKey, SecretKey, Wrapper};
+
+use crate::EthersU256;
+
+/// The message a wallet signs to derive its registry key.
+/// Changing it changes every derived key, so it is versioned.
+const DERIVATION_MESSAGE: &str = "Nouns Anonymous Voting registry key\n\n\
+Signing this message derives your private voting key. \
+Only sign it in a voting client you trust.\n\n\
+Version: 1";
+
+/// The public part of a keystore file, readable without the password.
+/// The encrypted private key sits next to it in the Ethereum V3 keystore format
+/// (scrypt and AES-128-CTR with a Keccak MAC), so `eth_keystore` decrypts the file as is.
+#[derive(Serialize, Deserialize)]
+struct KeyInfo {
+    name: String,
+    public_key: [EthersU256; 2],
+}
+
+/// Generates a new BabyJubJub key and stores it in the keystore under `name`.
+/// With a `mnemonic_index`, the key is derived at that index from a new mnemonic, which is printed once
+/// so that the key can be recovered with `recover_key`.
+pub fn new_key(keystore_dir: &Path, name: &str, mnemonic_index: Option<u32>) -> Result<(), String> {
+    let private_key = match mnemonic_index {
+        Some(index) => {
+            let mnemonic = new_mnemonic(&mut thread_rng())?;
+            let private_key = derive_private_key(&*mnemonic_seed(&mnemonic)?, index)?;
+            println!("Mnemonic: {}", mnemonic.as_str());
+            println!("Write it down and keep it secret: it is the only backup of the key.");
+            private_key
+        }
+        None => random_private_key(&mut thread_rng()),
+    };
+
+    let password = prompt_new_password()?;
+    let path = save(keystore_dir, name, &private_key, &password)?;
+
+    println!("Key {} saved to {}", name, path.display());
+    println!("Public key: {}", format_public_key(&private_key.public()));
+    Ok(())
+}
+
+/// Stores an existing BabyJubJub key in the keystore under `name`.
+/// The key is asked for on the terminal if it is not given.
+pub fn import_key(
+    keystore_dir: &Path,
+    name: &str,
+    private_key: Option<SecretKey>,
+) -> Result<(), String> {
+    let private_key = match private_key {
+        Some(private_key) => private_key,
+        None => {
+            let private_key = read_secret("Private key to import: ")?;
+            SecretKey::import(crate::parsers::parse_private_key(&private_key)?.as_slice())?
+        }
+    };
+
+    let password = prompt_new_password()?;
+    let path = save(keystore_dir, name, &private_key, &password)?;
+
+    println!("Key {} saved to {}", name, path.display());
+    println!("Public key: {}", format_public_key(&private_key.public()));
+    Ok(())
+}
+
+/// Recovers the key derived at `index` from a mnemonic and stores it in the keystore under `name`.
+/// The mnemonic is asked for on the terminal.
+pub fn recover_key(keystore_dir: &Path, name: &str, index: u32) -> Result<(), String> {
+    let mnemonic = read_secret("Mnemonic: ")?;
+    let private_key = derive_private_key(&*mnemonic_seed(&mnemonic)?, index)?;
+
+    let password = prompt_new_password()?;
+    let path = save(keystore_dir, name, &private_key, &password)?;
+
+    println!("Key {} saved to {}", name, path.display());
+    println!("Public key: {}", format_public_key(&private_key.public()));
+    Ok(())
+}
+
+/// Prints the public key stored under `name`, as registered in the ZKRegistry
+pub fn export_public_key(keystore_dir: &Path, name: &str) -> Result<(), String> {
+    let info = read_info(&existing_key_path(keystore_dir, name)?)?;
+    println!("{:#x},{:#x}", info.public_key[0], info.public_key[1]);
+    Ok(())
+}
+
+/// Prints the names and public keys of the keys in the keystore
+pub fn list_keys(keystore_dir: &Path) -> Result<(), String> {
+    let entries = match std::fs::read_dir(keystore_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            println!("No keys in {}", keystore_dir.display());
+            return Ok(());
+        }
+        Err(e) => {
+            return Err(format!(
+                "Error reading keystore {}: {}",
+                keystore_dir.display(),
+                e
+            ))
+        }
+    };
+
+    let mut keys = vec![];
+    for entry in entries {
+        let path = entry
+            .map_err(|e| format!("Error reading keystore {}: {}", keystore_dir.display(), e))?
+            .path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            keys.push(read_info(&path)?);
+        }
+    }
+    keys.sort_by(|a, b| a.name.cmp(&b.name));
+
+    if keys.is_empty() {
+        println!("No keys in {}", keystore_dir.display());
+    }
+    for key in keys {
+        println!(
+            "{:<20} {:#x},{:#x}",
+            key.name, key.public_key[0], key.public_key[1]
+        );
+    }
+    Ok(())
+}
+
+/// Reads the public key stored under `name`, which needs no password
+pub(crate) fn public_key(keystore_dir: &Path, name: &str) -> Result<BBJJ_Ec, String> {
+    let info = read_info(&existing_key_path(keystore_dir, name)?)?;
+    let public_key: [U256; 2] = info.public_key.map(|x| wrap_into!(x));
+    Ok(wrap_into!(public_key))
+}
+
+/// Decrypts the key stored under `name`, asking for its password on the terminal
+pub(crate) fn unlock(keystore_dir: &Path, name: &str) -> Result<SecretKey, String> {
+    let path = existing_key_path(keystore_dir, name)?;
+
+    let password = read_secret(&format!("Password of key {}: ", name))?;
+    let key = eth_keystore::decrypt_key(&path, password.as_bytes()).map_err(|e| {
+        format!(
+            "Error decrypting key {}. Is the password right? {}",
+            name, e
+        )
+    })?;
+
+    SecretKey::import(&Zeroizing::new(key))
+}
+
+/// Derives a registry key from the signature of `wallet` over a fixed message,
+/// so that anyone holding the wallet can always rebuild it.
+/// Wallets sign deterministically (RFC 6979), hence the same wallet always derives the same key.
+pub(crate) fn derive_key(wallet: &LocalWallet) -> Result<SecretKey, String> {
+    let signature = wallet
+        .sign_hash(hash_message(DERIVATION_MESSAGE))
+        .map_err(|e| format!("Error signing the key derivation message: {}", e))?;
+
+    // `v` only tells which of two public keys produced the signature, so only `r` and `s` are hashed
+    let signature = Zeroizing::new(signature.to_vec());
+    SecretKey::import(Zeroizing::new(keccak256(&signature[..64])).as_slice())
+}
+
+fn save(
+    keystore_dir: &Path,
+    name: &str,
+    private_key: &PrivateKey,
+    password: &str,
+) -> Result<PathBuf, String> {
+    let path = key_path(keystore_dir, name)?;
+    if path.exists() {
+        return Err(format!("A key named {} already exists", name));
+    }
+
+    std::fs::create_dir_all(keystore_dir)
+        .map_err(|e| format!("Error creating keystore {}: {}", keystore_dir.display(), e))?;
+    let file_name = path
+        .file_name()
+        .and_then(|file_name| file_name.to_str())
+        .ok_or("Invalid key name")?;
+    eth_keystore::encrypt_key(
+        keystore_dir,
+        &mut thread_rng(),
+        private_key.key,
+        password,
+        Some(file_name),
+    )
+    .map_err(|e| format!("Error encrypting key: {}", e))?;
+
+    // Add the public part next to the encrypted key
+    let mut keystore: serde_json::Value = serde_json::from_slice(
+        &std::fs::read(&path).map_err(|e| format!("Error reading {}: {}", path.display(), e))?,
+    )
+    .map_err(|e| format!("Invalid keystore file {}: {}", path.display(), e))?;
+    let public_key: [U256; 2] = wrap_into!(private_key.public());
+    let info = serde_json::to_value(KeyInfo {
+        name: name.to_string(),
+        public_key: public_key.map(|x| wrap_into!(x)),
+    })
+    .map_err(|e| format!("Error serialising key: {}", e))?;
+    if let (Some(keystore), serde_json::Value::Object(info)) = (keystore.as_object_mut(), info) {
+        keystore.extend(info);
+    }
+    std::fs::write(&path, keystore.to_string())
+        .map_err(|e| format!("Error writing {}: {}", path.display(), e))?;
+
+    Ok(path)
+}
+
+fn read_info(path: &Path) -> Result<KeyInfo, String> {
+    let contents =
+        std::fs::read(path).map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+    serde_json::from_slice(&contents)
+        .map_err(|e| format!("Invalid keystore file {}: {}", path.display(), e))
+}
+
+/// The file of the key named `name`. Names are restricted so that they cannot escape the keystore.
+fn key_path(keystore_dir: &Path, name: &str) -> Result<PathBuf, String> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!(
+            "Invalid key name {}. Use letters, digits, `-` and `_`.",
+            name
+        ));
+    }
+
+    Ok(keystore_dir.join(format!("{}.json", name)))
+}
+
+fn existing_key_path(keystore_dir: &Path, name: &str) -> Result<PathBuf, String> {
+    let path = key_path(keystore_dir, name)?;
+    if !path.exists() {
+        return Err(format!(
+            "No key named {} in {}",
+            name,
+            keystore_dir.display()
+        ));
+    }
+
+    Ok(path)
+}
+
+/// Formats a BBJJ Public Key as printed by `key export-public`
+pub(crate) fn format_public_key(public_key: &BBJJ_Ec) -> String {
+    let public_key: [U256; 2] = wrap_into!(public_key.clone());
+    let public_key: [EthersU256; 2] = public_key.map(|x| wrap_into!(x));
+    format!("{:#x},{:#x}", public_key[0], public_key[1])
+}
+
+fn prompt_new_password() -> Result<Zeroizing<String>, String> {
+    let password = read_secret("New password: ")?;
+    if password.is_empty() {
+        return Err("The password must not be empty".to_string());
+    }
+    if read_secret("Repeat the password: ")? != password {
+        return Err("The passwords do not match".to_string());
+    }
+
+    Ok(password)
+}
+
+/// Reads a line from the terminal without echoing it. The line is wiped from memory when dropped.
+pub(crate) fn read_secret(prompt: &str) -> Result<Zeroizing<String>, String> {
+    let term = Term::stderr();
+    term.write_str(prompt)
+        .map_err(|e| format!("Error writing to the terminal: {}", e))?;
+    term.read_secure_line()
+        .map(Zeroizing::new)
+        .map_err(|e| format!("Error reading from the terminal: {}", e))
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::core::k256::U256;
+    use ethers::prelude::LocalWallet;
+    use nouns_protocol::{wrap, wrap_into, PrivateKey, Wrapper};
+
+    use crate::keystore::{derive_key, key_path, read_info, save};
+    use crate::EthersU256;
+
+    #[test]
+    fn test_keystore() -> Result<(), String> {
+        let dir = std::env::temp_dir().join(format!("nouns-keystore-{}", std::process::id()));
+        let private_key = PrivateKey::import(vec![7u8; 32])?;
+
+        let path = save(&dir, "voter-1", &private_key, "password")?;
+        assert!(save(&dir, "voter-1", &private_key, "password").is_err());
+
+        let info = read_info(&path)?;
+        let key = eth_keystore::decrypt_key(&path, "password").map_err(|e| e.to_string());
+        let wrong_password = eth_keystore::decrypt_key(&path, "wrong password");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(info.name, "voter-1");
+        let public_key: [U256; 2] = wrap_into!(private_key.public());
+        assert_eq!(
+            info.public_key,
+            public_key.map(|x| -> EthersU256 { wrap_into!(x) })
+        );
+        assert_eq!(key?, private_key.key.to_vec());
+        assert!(wrong_password.is_err());
+
+        for name in ["", "../voter", "voter/1", "voter.json"] {
+            assert!(key_path(&dir, name).is_err());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_derive_key() -> Result<(), String> {
+        let wallet = LocalWallet::from_bytes(&[1u8; 32]).unwrap();
+        let other_wallet = LocalWallet::from_bytes(&[2u8; 32]).unwrap();
+
+        let key = derive_key(&wallet)?;
+        assert_eq!(derive_key(&wallet)?.key, key.key);
+        assert_ne!(derive_key(&other_wallet)?.key, key.key);
+
+        Ok(())
+    }
+}
@@ -8,4 +8,5 @@ pub use ethereum::setup_env_parameters;
 
 pub mod cli;
 pub mod ethereum;
+pub mod keystore;
 mod parsers;
@@ -2,11 +2,15 @@ extern crate core;
 
 use ethers::middleware::SignerMiddleware;
 use ethers::prelude::Signer;
-use ethers::providers::{Http, Middleware, Provider, ProviderExt};
-use ethers::signers::LocalWallet;
+use ethers::providers::Middleware;
 
-use nouns_cli::cli::{get_user_input, CliCommand};
-use nouns_cli::ethereum::contract_interactions::{create_process, reg_key, tally, vote};
+use nouns_cli::cli::{get_user_input, CliCommand, KeyCommand, UserInput};
+use nouns_cli::ethereum::contract_interactions::{
+    audit, check_vote, create_process, delegate, deregister_key, find_slot, index, list_processes,
+    reg_key, registry_status, rotate_key, status, tally, verify_spoiled_ballot, vote,
+};
+use nouns_cli::ethereum::provider;
+use nouns_cli::keystore;
 use nouns_protocol::{wrap, wrap_into, Wrapper};
 
 static NOUNS_LOGO: &'static str = "\
@@ -25,24 +29,51 @@ static NOUNS_LOGO: &'static str = "\
 /// The CLI that to interact with the Nouns Anonymous Voting System
 /// As global parameters, it should take:
 /// 1. The EVM Address of the NounsVoting contract (could be also passed as an environmental variable)
-/// 2. The RPC URL of the target EVM blockchain (could be also passed as an environmental variable)
-/// 3. The Private Key of the account that will be used to send the transactions (should be passed as an environmental variable or as a password input)
+/// 2. The RPC URLs of the target EVM blockchain, tried in turn (could be also passed as an environmental variable)
+///    and, optionally, RPC URLs of the same chain to read historical state and take storage proofs from, e.g. an archive node
+/// 3. The signer of the transactions, for the commands that send them: the Private Key of the account
+///    (should be passed as an environmental variable), an encrypted JSON keystore (`--tx-keystore`),
+///    a mnemonic with a derivation path (`--tx-mnemonic`, `--tx-derivation-path`), both asked for on the terminal,
+///    or an account of a remote signer over JSON-RPC (`--remote-signer`, `--remote-signer-address`)
 ///
-/// The CLI will have 3 commands, which the user will choose from:
+/// The CLI has the following commands, which the user will choose from:
 /// 1. `reg-key`
 /// 2. `create-process`
 /// 3. `vote`
 /// 4. `tally`
+/// 5. `find-slot`
+/// 6. `index`
+/// 7. `status`
+/// 8. `list-processes`
+/// 9. `audit`
+/// 10. `check-vote`
+/// 11. `verify-spoiled-ballot`
+/// 12. `key`
+/// 13. `registry`
+/// 14. `delegate`
 ///
 /// The `reg-key` command registers a new BBJJ Public Key in the ZKRegistry contract.
+/// The key is registered as its Poseidon commitment `Poseidon(pk.x, pk.y)`, in a single slot
+/// that the vote proof opens with one storage proof.
 /// It should ask the user for the following additional information:
 /// 1. The BBJJ Private Key that will be registered in the ZKRegistry to the account that owns the NFT
 /// Note that the Account that sends the transaction should be the owner of the NFT
+/// The registration carries a proof of possession of the key, a signature with it over the account,
+/// the chain and the ZKRegistry, which the ZKRegistry publishes in its `Registered` event without checking it.
+/// Anyone can check it off-chain, e.g. with `registry status --registration-tx`, to disregard keys registered without it.
+/// It waits for the transaction and reads the commitment back to confirm the registration.
+/// Keys registered before as their x and y coordinates are not read by votes anymore:
+/// they must be registered again with `reg-key`, and `registry status` points them out.
 ///
 /// The `create-process` command creates a new voting process in the NounsVoting contract.
 /// It should ask the user for the following additional information:
 /// 1. Process Duration (in minutes/hours/days)
 /// 2. TLCS Public Key for the process end time used to encrypt the ballots
+/// 3. Optionally, the census block, which may be older than the last 256 blocks
+/// Older census blocks are recorded through header chain proofs of up to 64 headers, several per transaction.
+/// An interrupted recording resumes from the lowest block recorded so far.
+/// The census is taken on the chain of the NounsVoting contract, as the contract checks the census block
+/// against the block hashes of its own chain, so `--state-rpc-url` must serve that same chain.
 /// In future versions, we could allow to also pass the action to be executed after the process ends.
 ///
 /// The `vote` command allows the user to vote in an existing voting process.
@@ -53,56 +84,195 @@ static NOUNS_LOGO: &'static str = "\
 /// 4. The BBJJ Private Key mapped in the ZKRegistry to the account that owns the NFT
 /// 5. The Vote Choice (Yes/No/Abstain)
 /// 6. TLCS Public Key for the process end time used to encrypt the ballots
+/// A receipt of the vote, encrypted with the BBJJ Private Key, is saved for `check-vote`.
+/// With `--challenge`, it shows a commitment to the encrypted ballot and lets the user cast it
+/// or spoil it, revealing its blinding factor, until one is cast.
 ///
 /// The `tally` command allows the user to generate the tally for an existing voting process.
 /// It then submits the result to the NounsVoting contract with the proof of the tally.
 /// It should ask the user for the following additional information:
 /// 1. The Voting Process ID it is generating the results for
 /// 2. The TLCS Private Key corresponding to the TLCS Public Key used to encrypt the Ballots
+/// The ballots are read from the index database if one is given.
+///
+/// The `index` command follows the events of the NounsVoting contract and stores the voting processes,
+/// ballots and tally results in a local SQLite database, given as a global parameter.
+/// It indexes up to the latest finalized block, so the index lags behind the chain by about two epochs,
+/// and commands reading it wait for the voting period to be finalized.
+/// Blocks dropped by chain reorganisations are rolled back on the next run.
+///
+/// The `status` command prints the details of an existing voting process:
+/// its proposal, blocks, phase, TLCS key availability, ballots and tally result.
+///
+/// The `list-processes` command lists the voting processes with their blocks and tally status,
+/// optionally filtered by phase (`--active`, `--ended-untallied`, `--tallied`) and as JSON (`--json`).
+/// The processes are read from the index database if one is given.
+///
+/// The `audit` command checks the tally result submitted for a voting process without proving it again:
+/// it checks the ballots hash and the TLCS private key, decrypts the ballots and compares the result.
+/// It ends with a JSON report of the checks.
+///
+/// The `check-vote` command checks a vote using the receipt saved by `vote` and the BBJJ Private Key:
+/// that the ballot encrypts the chosen vote, that it was cast and is part of the ballots hash
+/// and, once the process is tallied, that it decrypts to the chosen vote.
+///
+/// The `verify-spoiled-ballot` command checks a ballot spoiled with `vote --challenge`:
+/// it recomputes the encryption from the revealed blinding factor and the TLCS public key of the process,
+/// and compares the ballot with the claimed vote choice and the commitment shown before spoiling it.
+///
+/// The `key` command manages a keystore of BBJJ Private Keys encrypted with a password
+/// (`new`, `recover`, `import`, `export-public`, `list`), without needing the global parameters.
+/// `key new --mnemonic` derives the key from a new BIP-39 mnemonic, printed once,
+/// from which `key recover` rebuilds it; `--index` picks one of the keys of a mnemonic.
+/// `reg-key`, `vote` and `check-vote` take the name of a key in the keystore with `--key-name`
+/// instead of the raw key, and ask for its password.
+/// With `--derive`, they derive the key from the signature of the transaction wallet instead,
+/// so it can always be rebuilt from the wallet. As votes are then sent from that wallet,
+/// deriving the key once with `key import --derive` keeps the voting wallet separate.
+///
+/// The `registry` command manages the key registered in the ZKRegistry contract.
+/// `registry status` shows the key commitment registered by an address, any key still registered
+/// as its coordinates and, given the key, whether it matches.
+/// Given the registration transaction, it also checks the proof of possession published with it.
+/// `registry rotate` registers a new key and `registry deregister` removes the registered one;
+/// both list the open voting processes whose census already took the old key, as votes in them still need it.
+///
+/// The `delegate` command delegates the votes of the NFTs of the account to another address in the NFT contract.
+///
+/// With `--safe <address>`, the transactions of `reg-key`, `registry rotate`, `registry deregister`,
+/// `create-process` and `delegate` are prepared for the owners of a Safe instead of being sent,
+/// so that keys are registered and NFTs delegated under the Safe that holds them.
+/// They are written as a Safe Transaction Builder JSON batch (`--safe-output`, `safe-batch.json` by default)
+/// or, with `--safe-tx-hash`, printed with the EIP-712 SafeTx hash each owner signs.
+/// No transaction signer is needed for them, except to derive the registry key with `--derive` and for
+/// `create-process`, which first records the hash of the census block from the signer's account,
+/// as the Safe may execute the process creation after `blockhash` no longer covers the census block.
 ///
 #[tokio::main]
 async fn main() {
     println!("{}", NOUNS_LOGO);
     // parse the CLI input
-    let (global_param, cli_command) = get_user_input().unwrap_or_else(|err| {
+    let user_input = get_user_input().unwrap_or_else(|err| {
         eprintln!("Error: {}", err);
         std::process::exit(1);
     });
 
-    // connect to the EVM
-    let eth_connection = Provider::<Http>::connect(global_param.rpc_url.as_str()).await;
-    // create the signer for the txs
-    let wallet = LocalWallet::from_bytes(global_param.tx_private_key.as_ref()).unwrap();
+    // the keystore is managed without connecting to any chain
+    let (global_param, cli_command) = match user_input {
+        UserInput::Contract(global_param, cli_command) => (*global_param, cli_command),
+        UserInput::Key(keystore_dir, key_command) => {
+            match key_command {
+                KeyCommand::New(name, mnemonic_index) => {
+                    keystore::new_key(&keystore_dir, &name, mnemonic_index)
+                }
+                KeyCommand::Recover(name, index) => {
+                    keystore::recover_key(&keystore_dir, &name, index)
+                }
+                KeyCommand::Import(name, private_key) => {
+                    keystore::import_key(&keystore_dir, &name, private_key)
+                }
+                KeyCommand::ExportPublic(name) => keystore::export_public_key(&keystore_dir, &name),
+                KeyCommand::List => keystore::list_keys(&keystore_dir),
+            }
+            .unwrap_or_else(|err| {
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
+            });
+            return;
+        }
+    };
+
+    // connect to the EVM chain of the NounsVoting contract
+    let eth_connection = provider::connect(&global_param.rpc_urls)
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        });
+    // and to the nodes historical state and storage proofs are read from, e.g. an archive node
+    let state_connection = provider::connect(&global_param.state_rpc_urls)
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        });
+    // The vote and tally circuits are bound to the chain the NounsVoting contract is on
     let chain_id = eth_connection
         .get_chainid()
         .await
         .map_err(|_e| "Could not get chain id".to_string())
         .unwrap();
+    // The NounsVoting contract only knows the block hashes of its own chain, so the state must come from it too
+    let state_chain_id = state_connection
+        .get_chainid()
+        .await
+        .map_err(|_e| "Could not get the chain id of the state RPC".to_string())
+        .unwrap();
+    if state_chain_id != chain_id {
+        eprintln!(
+            "Error: The state RPC is on chain {} instead of chain {} of the NounsVoting contract",
+            state_chain_id, chain_id
+        );
+        std::process::exit(1);
+    }
 
-    let client = SignerMiddleware::new(
-        eth_connection.clone(),
-        wallet.with_chain_id(chain_id.as_u64()),
-    );
+    // create the client that signs the txs, for the commands that send them.
+    // Commands whose transactions are prepared for a Safe run on the connection alone.
+    let client = global_param.tx_signer.map(|signer| {
+        SignerMiddleware::new(
+            eth_connection.clone(),
+            signer.with_chain_id(chain_id.as_u64()),
+        )
+    });
 
     match cli_command {
-        CliCommand::RegKey(bbjj_private_key) => {
-            reg_key(client, global_param.contract_address, bbjj_private_key).await
-        }
-        CliCommand::CreateProcess(ipfs_hash, start_delay, process_duration) => {
+        CliCommand::RegKey(bbjj_private_key) => match client {
+            Some(client) => {
+                reg_key(
+                    client,
+                    global_param.contract_address,
+                    bbjj_private_key,
+                    global_param.safe.as_ref(),
+                )
+                .await
+            }
+            None => {
+                reg_key(
+                    eth_connection,
+                    global_param.contract_address,
+                    bbjj_private_key,
+                    global_param.safe.as_ref(),
+                )
+                .await
+            }
+        },
+        CliCommand::CreateProcess(ipfs_hash, start_delay, process_duration, census_block) => {
+            let client = client.expect("The transaction signer is loaded when parsing");
             create_process(
                 client,
-                eth_connection,
+                state_connection,
                 global_param.contract_address,
                 ipfs_hash,
                 start_delay,
                 process_duration,
+                census_block,
+                global_param.safe.as_ref(),
             )
             .await
         }
-        CliCommand::Vote(voter_address, process_id, nft_id, bbjj_private_key, vote_choice) => {
+        CliCommand::Vote(
+            voter_address,
+            process_id,
+            nft_id,
+            bbjj_private_key,
+            vote_choice,
+            receipt_dir,
+            challenge,
+        ) => {
+            let client = client.expect("The transaction signer is loaded when parsing");
             vote(
                 client,
-                eth_connection,
+                state_connection,
                 voter_address,
                 global_param.contract_address,
                 process_id,
@@ -110,15 +280,163 @@ async fn main() {
                 wrap_into!(chain_id),
                 bbjj_private_key,
                 vote_choice,
+                global_param.token_layout,
+                receipt_dir,
+                challenge,
             )
             .await
         }
         CliCommand::Tally(process_id) => {
+            let client = client.expect("The transaction signer is loaded when parsing");
             tally(
                 client,
                 global_param.contract_address,
                 wrap_into!(chain_id),
                 process_id,
+                global_param.index_path,
+            )
+            .await
+        }
+        CliCommand::FindSlot(token_address, nft_id, delegator, max_slot) => {
+            find_slot(
+                eth_connection,
+                state_connection,
+                global_param.contract_address,
+                token_address,
+                nft_id,
+                delegator,
+                max_slot,
+                global_param.token_layout,
+            )
+            .await
+        }
+        CliCommand::Index(from_block) => {
+            index(
+                eth_connection,
+                global_param.contract_address,
+                global_param
+                    .index_path
+                    .expect("The index database is checked when parsing"),
+                from_block,
+            )
+            .await
+        }
+        CliCommand::Status(process_id) => {
+            status(
+                eth_connection,
+                global_param.contract_address,
+                process_id,
+                global_param.index_path,
+            )
+            .await
+        }
+        CliCommand::ListProcesses(phases, json) => {
+            list_processes(
+                eth_connection,
+                global_param.contract_address,
+                phases,
+                json,
+                global_param.index_path,
+            )
+            .await
+        }
+        CliCommand::Audit(process_id) => {
+            audit(
+                eth_connection,
+                global_param.contract_address,
+                wrap_into!(chain_id),
+                process_id,
+                global_param.index_path,
+            )
+            .await
+        }
+        CliCommand::CheckVote(receipt_path, bbjj_private_key) => {
+            check_vote(
+                eth_connection,
+                global_param.contract_address,
+                wrap_into!(chain_id),
+                receipt_path,
+                bbjj_private_key,
+                global_param.index_path,
+            )
+            .await
+        }
+        CliCommand::RegistryStatus(address, public_key, registration_tx) => {
+            registry_status(
+                eth_connection,
+                state_connection,
+                global_param.contract_address,
+                address,
+                public_key,
+                registration_tx,
+            )
+            .await
+        }
+        CliCommand::RegistryRotate(bbjj_private_key) => match client {
+            Some(client) => {
+                rotate_key(
+                    client,
+                    global_param.contract_address,
+                    bbjj_private_key,
+                    global_param.safe.as_ref(),
+                )
+                .await
+            }
+            None => {
+                rotate_key(
+                    eth_connection,
+                    global_param.contract_address,
+                    bbjj_private_key,
+                    global_param.safe.as_ref(),
+                )
+                .await
+            }
+        },
+        CliCommand::RegistryDeregister => match client {
+            Some(client) => {
+                deregister_key(
+                    client,
+                    global_param.contract_address,
+                    global_param.safe.as_ref(),
+                )
+                .await
+            }
+            None => {
+                deregister_key(
+                    eth_connection,
+                    global_param.contract_address,
+                    global_param.safe.as_ref(),
+                )
+                .await
+            }
+        },
+        CliCommand::Delegate(delegatee) => match client {
+            Some(client) => {
+                delegate(
+                    client,
+                    global_param.contract_address,
+                    delegatee,
+                    global_param.safe.as_ref(),
+                )
+                .await
+            }
+            None => {
+                delegate(
+                    eth_connection,
+                    global_param.contract_address,
+                    delegatee,
+                    global_param.safe.as_ref(),
+                )
+                .await
+            }
+        },
+        CliCommand::VerifySpoiledBallot(ballot_path, commitment) => {
+            verify_spoiled_ballot(
+                eth_connection,
+                global_param.contract_address,
+                wrap_into!(chain_id),
+                ballot_path,
+                commitment,
             )
             .await
         }
@@ -11,17 +11,17 @@ use multihash::Multihash;
 use std::str::FromStr;
 
 use nouns_protocol::{BBJJ_Ec, BBJJ_Fr, BN254_Fr, PrimeField};
+use zeroize::Zeroizing;
 
 /// Parses a hex string into BBJJ PrivateKey
 /// Example: `1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef` of 32 bytes
 pub(crate) fn parse_bbjj_prk(private_bbjj_key: &String) -> Result<BBJJ_Fr, String> {
-    let key = if private_bbjj_key.starts_with("0x") {
-        private_bbjj_key[2..].to_string()
-    } else {
-        private_bbjj_key.to_string()
-    };
+    let key = private_bbjj_key
+        .strip_prefix("0x")
+        .unwrap_or(private_bbjj_key);
 
-    let key = hex::decode(key).map_err(|e| format!("Failed to parse hex string: {}", e))?;
+    let key =
+        Zeroizing::new(hex::decode(key).map_err(|e| format!("Failed to parse hex string: {}", e))?);
 
     Ok(BBJJ_Fr::from_be_bytes_mod_order(key.as_slice()))
 }
@@ -81,6 +81,17 @@ pub(crate) fn parse_ipfs_hash<T: Into<String>>(s: T) -> Result<H256, String> {
     Ok(s_h256)
 }
 
+/// Formats the sha2-256 digest of a voting process as an IPFS CIDv1 (raw binary codec)
+/// Inverse of `parse_ipfs_hash`
+pub(crate) fn format_ipfs_cid(ipfs_hash: H256) -> Result<String, String> {
+    let cid_multihash = Multihash::wrap(0x12, ipfs_hash.as_bytes())
+        .map_err(|e| format!("Error forming CID multihash: {}", e))?;
+    let cid = Cid::new_v1(0x55, cid_multihash);
+
+    cid.to_string_of_base(Base::Base32Lower)
+        .map_err(|e| format!("Could not form CID string: {}", e))
+}
+
 /// Parses a be TLCS Public Key string into a BBJJ_Ec
 /// Example: `0x1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef,0x1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef`
 pub(crate) fn parse_tlcs_pbk<T: Into<String>>(s: T) -> Result<BBJJ_Ec, String> {
@@ -139,18 +150,21 @@ pub(crate) fn parse_u256<T: Into<String>>(s: T) -> Result<U256, String> {
     Ok(U256::from(number))
 }
 
-/// Parses a Private Key
+/// Parses a Private Key into bytes that are wiped from memory when dropped
 /// Example: `1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef` (be 32 bytes)
-pub(crate) fn parse_private_key(private_key: &String) -> Result<[u8; 32], String> {
-    let private_key = if private_key.starts_with("0x") {
-        private_key[2..].to_string()
-    } else {
-        private_key.to_string()
-    };
+pub(crate) fn parse_private_key(private_key: &String) -> Result<Zeroizing<[u8; 32]>, String> {
+    let private_key = private_key.strip_prefix("0x").unwrap_or(private_key);
 
-    let tx_private_key =
-        hex::decode(private_key).map_err(|e| format!("Invalid private key: {}", e))?;
-    let tx_private_key = <[u8; 32]>::try_from(tx_private_key.deref())
-        .map_err(|e| format!("Invalid private key: {}", e))?;
+    let decoded = Zeroizing::new(
+        hex::decode(private_key).map_err(|e| format!("Invalid private key: {}", e))?,
+    );
+    if decoded.len() != 32 {
+        return Err(format!(
+            "Invalid private key: expected 32 bytes, got {}",
+            decoded.len()
+        ));
+    }
+    let mut tx_private_key = Zeroizing::new([0u8; 32]);
+    tx_private_key.copy_from_slice(&decoded);
     Ok(tx_private_key)
 }
@@ -0,0 +1,190 @@
+use ark_ff::{BigInteger, PrimeField, Zero};
+use ark_std::rand::Rng;
+use ark_std::UniformRand;
+use coins_bip39::{English, Mnemonic};
+use hmac::{Hmac, Mac};
+use sha2::Sha512;
+use std::ops::Deref;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::{BBJJ_Fr, PrivateKey};
+
+/// Number of words of the generated mnemonics
+const MNEMONIC_WORDS: usize = 24;
+
+/// HMAC key of the master node, which separates BabyJubJub keys from the BIP-32 keys of the same mnemonic
+const MASTER_KEY: &[u8] = b"Nouns BabyJubJub seed";
+
+/// Index of the first hardened child, as in BIP-32
+const HARDENED: u32 = 0x8000_0000;
+
+/// A BabyJubJub private key that is wiped from memory when dropped.
+/// It is neither `Clone` nor `Debug`, so that the key is only ever copied on purpose.
+pub struct SecretKey(PrivateKey);
+
+impl SecretKey {
+    /// Reads a private key from its 32 bytes, without keeping any copy of them
+    pub fn import(key: &[u8]) -> Result<Self, String> {
+        let mut secret_key = SecretKey(PrivateKey { key: [0u8; 32] });
+        if key.len() != 32 {
+            return Err(format!(
+                "Private key must be 32 bytes long, got {} bytes",
+                key.len()
+            ));
+        }
+        secret_key.0.key.copy_from_slice(key);
+
+        Ok(secret_key)
+    }
+}
+
+impl Deref for SecretKey {
+    type Target = PrivateKey;
+
+    fn deref(&self) -> &PrivateKey {
+        &self.0
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        self.0.key.zeroize();
+    }
+}
+
+/// Generates a private key from a uniformly random non-zero scalar of the BabyJubJub subgroup,
+/// as its 32 little-endian bytes. Sampling the scalar, rather than raw bytes, keeps the key below the subgroup order.
+pub fn random_private_key<R: Rng>(rng: &mut R) -> SecretKey {
+    let scalar = loop {
+        let scalar = BBJJ_Fr::rand(rng);
+        if !scalar.is_zero() {
+            break scalar;
+        }
+    };
+
+    let mut secret_key = SecretKey(PrivateKey { key: [0u8; 32] });
+    let mut bytes = Zeroizing::new(scalar.into_bigint().to_bytes_le());
+    secret_key.0.key.copy_from_slice(&bytes);
+    bytes.zeroize();
+    secret_key
+}
+
+/// Generates a new BIP-39 mnemonic
+pub fn new_mnemonic<R: Rng>(rng: &mut R) -> Result<Zeroizing<String>, String> {
+    Mnemonic::<English>::new_with_count(rng, MNEMONIC_WORDS)
+        .map(|mnemonic| Zeroizing::new(mnemonic.to_phrase()))
+        .map_err(|e| format!("Error generating mnemonic: {}", e))
+}
+
+/// Computes the BIP-39 seed of a mnemonic, checking its words and checksum
+pub fn mnemonic_seed(phrase: &str) -> Result<Zeroizing<[u8; 64]>, String> {
+    let phrase = Zeroizing::new(
+        phrase
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>()
+            .join(" "),
+    );
+    let mnemonic = Mnemonic::<English>::new_from_phrase(&phrase)
+        .map_err(|e| format!("Invalid mnemonic: {}", e))?;
+
+    mnemonic
+        .to_seed(None)
+        .map(Zeroizing::new)
+        .map_err(|e| format!("Invalid mnemonic: {}", e))
+}
+
+/// Derives the private key at path `m/index'` from a BIP-39 seed.
+/// Nodes are derived with hardened steps only, as in SLIP-0010 for Ed25519, and the private key
+/// is the 32-byte key of the last one, which `scalar_key` hashes into its scalar.
+pub fn derive_private_key(seed: &[u8; 64], index: u32) -> Result<SecretKey, String> {
+    let master = hmac_sha512(MASTER_KEY, &[seed])?;
+    let (key, chain_code) = master.split_at(32);
+    let index = index
+        .checked_add(HARDENED)
+        .ok_or(format!("Invalid key index {}", index))?;
+    let child = hmac_sha512(chain_code, &[&[0], key, &index.to_be_bytes()])?;
+
+    SecretKey::import(&child[..32])
+}
+
+/// Computes `HMAC-SHA512(key, data)`, whose first half is the key of a node and second half its chain code
+fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> Result<Zeroizing<[u8; 64]>, String> {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).map_err(|e| e.to_string())?;
+    for data in data {
+        mac.update(data);
+    }
+    let mut output = mac.finalize().into_bytes();
+
+    let mut node = Zeroizing::new([0u8; 64]);
+    node.copy_from_slice(&output);
+    output[..].zeroize();
+    Ok(node)
+}
+
+#[cfg(test)]
+mod test {
+    use ark_ff::{BigInteger, PrimeField, Zero};
+
+    use crate::keys::{
+        derive_private_key, mnemonic_seed, new_mnemonic, random_private_key, SecretKey,
+    };
+    use crate::BBJJ_Fr;
+
+    #[test]
+    fn test_mnemonic_derivation() -> Result<(), String> {
+        let rng = &mut ark_std::test_rng();
+
+        // BIP-39 test vector without passphrase
+        let phrase = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let seed = mnemonic_seed(phrase)?;
+        assert_eq!(
+            hex::encode(*seed),
+            "5eb00bbddcf069084889a8ab9155568165f5c453ccb85e70811aaed6f6da5fc19a5ac40b389cd370d086206dec8aa6c43daea6690f20ad3d8d48b2d2ce9e38e4"
+        );
+        assert_eq!(
+            *mnemonic_seed(&format!("  {} ", phrase.to_uppercase()))?,
+            *seed
+        );
+        assert!(mnemonic_seed(&phrase.replace("about", "abandon")).is_err());
+
+        // Keys only depend on the seed and the index
+        let key = derive_private_key(&seed, 0)?;
+        assert_eq!(
+            hex::encode(key.key),
+            "3f79bcf8b73dbd42174f57bdbf4a95fe6ba9eafaf6d1f62895028d6c6444fddb"
+        );
+        assert_eq!(derive_private_key(&seed, 0)?.key, key.key);
+        assert_ne!(derive_private_key(&seed, 1)?.key, key.key);
+        assert!(derive_private_key(&seed, u32::MAX).is_err());
+
+        let phrase = new_mnemonic(rng)?;
+        assert_eq!(phrase.split(' ').count(), 24);
+        assert_ne!(
+            derive_private_key(&*mnemonic_seed(&phrase)?, 0)?.key,
+            key.key
+        );
+
+        assert_ne!(random_private_key(rng).key, random_private_key(rng).key);
+
+        assert_eq!(SecretKey::import(&key.key)?.key, key.key);
+        assert!(SecretKey::import(&key.key[1..]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_random_private_key() {
+        let rng = &mut ark_std::test_rng();
+
+        for _ in 0..100 {
+            let key = random_private_key(rng);
+
+            // The key is the canonical encoding of a non-zero scalar, so below the subgroup order
+            let scalar = BBJJ_Fr::from_le_bytes_mod_order(&key.key);
+            assert_eq!(scalar.into_bigint().to_bytes_le(), key.key);
+            assert!(!scalar.is_zero());
+            assert!(!key.scalar_key().is_zero());
+        }
+    }
+}
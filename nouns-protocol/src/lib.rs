@@ -4,6 +4,7 @@ pub use ark_bn254::Fr as BN254_Fr;
 pub use ark_ff::PrimeField;
 /// Define the reexported types from the arkworks libraries to be used in this crate
 pub use babyjubjub_ark::{Fr as BBJJ_Fr, Point as BBJJ_Ec, PrivateKey, B8 as BBJJ_G1};
+pub use keys::SecretKey;
 
 pub use noir::MAX_DEPTH;
 pub use noir::MAX_NODE_LEN;
@@ -12,12 +13,14 @@ pub use utils::wrapper::Wrapper;
 pub use utils::BlockHeader;
 pub use utils::StateProof;
 pub use utils::VoteChoice;
-pub use voter::Voter;
+pub use voter::{PreparedBallot, SpoiledBallot, VoteReceipt, Voter};
 
 mod utils;
 
 pub mod noir;
 
+pub mod keys;
+pub mod registry;
 mod tallier;
 pub mod voter;
 
@@ -12,8 +12,11 @@ pub const MAX_NODE_LEN: usize = 532;
 // Maximum account state size as RLP-encoded byte array
 pub const MAX_ACCOUNT_STATE_SIZE: usize = 134;
 
-// Maximum block header size in bytes
-pub const MAX_BLOCK_HEADER_SIZE: usize = 630;
+// Maximum block header size in bytes (Prague header with all fields at their maximum length)
+pub const MAX_BLOCK_HEADER_SIZE: usize = 718;
+
+// Maximum number of block headers covered by a header chain proof
+pub const MAX_CHAIN_LENGTH: usize = 64;
 
 // The maximum byte length of a node
 pub const MAX_DEPTH: usize = 8; // For technical reasons, we need a fixed maximum trie proof size.
@@ -30,6 +33,15 @@ pub struct BlockHashVerifierInput {
     pub nft_state_proof: StateProof,
     pub nft_storage_root: H256,
 }
+
+/// Input to the Noir header chain checker
+pub struct HeaderChainVerifierInput {
+    pub block_hash: H256,
+    pub ancestor_hash: H256,
+    /// Headers from `block_hash` back to the child of `ancestor_hash`
+    pub block_headers: Vec<BlockHeader>,
+}
+
 /// The input to the Noir Vote Prover Circuit
 pub(crate) struct VoteProverInput {
     // Public input for the circuit
@@ -56,7 +68,8 @@ pub(crate) struct VoteProverInput {
     pub(crate) k: BBJJ_Ec,
     /// The public key of the voter's `sk` that is registered in the `BBJJ` interface in the `zkRegistry`
     pub(crate) registered_pbk: BBJJ_Ec,
-    pub(crate) registry_key_sp: StorageProof,
+    /// Storage proof of the Poseidon commitment to `registered_pbk` in the `zkRegistry`
+    pub(crate) registry_commitment_sp: StorageProof,
     pub(crate) nft_ownership_proof: StorageProof,
     pub(crate) delegation_proof: StorageProof,
 }
@@ -90,6 +103,25 @@ pub fn prove_block_hash(input: BlockHashVerifierInput) -> Result<Vec<u8>, String
     Ok(proof)
 }
 
+/// Generates a proof that a block hash is an ancestor of another block hash
+/// given the chain of at most `MAX_CHAIN_LENGTH` block headers linking them.
+#[cfg(not(feature = "mock-prover"))]
+pub fn prove_header_chain(input: HeaderChainVerifierInput) -> Result<Vec<u8>, String> {
+    let header_chain_circuit = include_str!("../../../circuits/header_chain/src/main.nr");
+    let header_chain_circuit_config_toml = include_str!("../../../circuits/header_chain/Nargo.toml");
+
+    if input.block_headers.is_empty() || input.block_headers.len() > MAX_CHAIN_LENGTH {
+        return Err(format!("Header chains must contain between 1 and {} headers", MAX_CHAIN_LENGTH));
+    }
+
+    // Serialize the input into a toml string
+    let prover_input = self::toml::TomlSerializable::toml(input);
+
+    let proof = run_singleton_noir_project(header_chain_circuit_config_toml, header_chain_circuit, prover_input).map_err(|e| format!("Failed to generate proof: {}", e))?;
+
+    Ok(proof)
+}
+
 /// Generates a proof for a vote
 ///
 /// Note: This function is incompatible with the browser.
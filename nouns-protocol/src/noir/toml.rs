@@ -4,8 +4,8 @@ use ethers::types::{Address, StorageProof, H256, U64};
 use toml::Value;
 
 use crate::noir::{
-    BlockHashVerifierInput, TallyProverInput, VoteProverInput, MAX_ACCOUNT_STATE_SIZE,
-    MAX_BLOCK_HEADER_SIZE, MAX_DEPTH, MAX_NODE_LEN,
+    BlockHashVerifierInput, HeaderChainVerifierInput, TallyProverInput, VoteProverInput,
+    MAX_ACCOUNT_STATE_SIZE, MAX_BLOCK_HEADER_SIZE, MAX_CHAIN_LENGTH, MAX_DEPTH, MAX_NODE_LEN,
 };
 use crate::{utils::VoteChoice, BBJJ_Ec, BBJJ_Fr, BN254_Fr, BlockHeader, StateProof};
 
@@ -40,6 +40,34 @@ impl TomlSerializable for BlockHashVerifierInput {
     }
 }
 
+impl TomlSerializable for HeaderChainVerifierInput {
+    fn toml(self) -> Value {
+        let mut toml_map = toml::map::Map::new();
+
+        let num_headers = U64::from(self.block_headers.len());
+
+        // Headers need to be an appropriately padded flat array.
+        let mut block_headers = self
+            .block_headers
+            .into_iter()
+            .flat_map(|block_header| {
+                let mut block_header = block_header.0;
+                assert!(block_header.len() <= MAX_BLOCK_HEADER_SIZE);
+                block_header.resize(MAX_BLOCK_HEADER_SIZE, 0);
+                block_header
+            })
+            .collect::<Vec<u8>>();
+        block_headers.resize(MAX_BLOCK_HEADER_SIZE * MAX_CHAIN_LENGTH, 0);
+
+        toml_map.insert("block_hash".to_string(), self.block_hash.toml());
+        toml_map.insert("ancestor_hash".to_string(), self.ancestor_hash.toml());
+        toml_map.insert("num_headers".to_string(), num_headers.toml());
+        toml_map.insert("block_headers".to_string(), block_headers.toml());
+
+        Value::Table(toml_map)
+    }
+}
+
 impl TomlSerializable for TallyProverInput {
     fn toml(self) -> Value {
         let mut map = toml::map::Map::new();
@@ -116,8 +144,8 @@ impl TomlSerializable for VoteProverInput {
         map.insert("k".to_string(), self.k.toml());
         map.insert("registered_pbk".to_string(), self.registered_pbk.toml());
         map.insert(
-            "registry_key_proof".to_string(),
-            self.registry_key_sp.toml(),
+            "registry_commitment_proof".to_string(),
+            self.registry_commitment_sp.toml(),
         );
         map.insert(
             "nft_ownership_proof".to_string(),
@@ -0,0 +1,296 @@
+use ark_ff::{BigInteger, Field, PrimeField};
+use babyjubjub_ark::Signature;
+use ethers::core::k256::U256;
+use ethers::types::Address;
+use poseidon_ark::Poseidon;
+
+use crate::keys::SecretKey;
+use crate::{wrap, wrap_into, BBJJ_Ec, BBJJ_Fr, BN254_Fr, Wrapper};
+
+/// Length of an encoded proof of possession: the public key and the `R8` and `S` of the signature,
+/// as 32-byte big-endian words
+pub const POSSESSION_PROOF_LEN: usize = 5 * 32;
+
+/// Proof that whoever registers a BabyJubJub public key in a ZKRegistry holds its private key:
+/// a signature over the registering address, the chain and the registry.
+/// The ZKRegistry does not check it: it is published with the registration, so that anyone can reject
+/// keys registered without it, by someone who does not hold them, or that are no valid public keys.
+pub struct PossessionProof {
+    pub r_b8: BBJJ_Ec,
+    pub s: BBJJ_Fr,
+}
+
+impl PossessionProof {
+    /// Signs the registration of the public key of `key` by `address`
+    pub fn new(
+        key: &SecretKey,
+        address: Address,
+        chain_id: U256,
+        registry_address: Address,
+    ) -> Result<Self, String> {
+        let signature = key.sign(possession_message(address, chain_id, registry_address)?)?;
+
+        Ok(PossessionProof {
+            r_b8: signature.r_b8,
+            s: signature.s,
+        })
+    }
+
+    /// Checks that `public_key` is a valid public key and that the proof was signed with its private key
+    /// for its registration by `address`
+    pub fn verify(
+        &self,
+        public_key: &BBJJ_Ec,
+        address: Address,
+        chain_id: U256,
+        registry_address: Address,
+    ) -> Result<(), String> {
+        if !is_public_key(public_key) {
+            return Err(
+                "The public key is not a point of the BabyJubJub prime order subgroup".to_string(),
+            );
+        }
+
+        let message = possession_message(address, chain_id, registry_address)?;
+        let signature = Signature {
+            r_b8: self.r_b8.clone(),
+            s: self.s,
+        };
+        if !babyjubjub_ark::verify(public_key.clone(), signature, message) {
+            return Err(format!(
+                "The proof of possession was not signed with the private key for the registration by {:?}",
+                address
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Encodes the proof along with `public_key`, which the registered commitment hides,
+    /// to be published with the registration
+    pub fn encode(&self, public_key: &BBJJ_Ec) -> Vec<u8> {
+        [
+            public_key.x.into_bigint().to_bytes_be(),
+            public_key.y.into_bigint().to_bytes_be(),
+            self.r_b8.x.into_bigint().to_bytes_be(),
+            self.r_b8.y.into_bigint().to_bytes_be(),
+            self.s.into_bigint().to_bytes_be(),
+        ]
+        .concat()
+    }
+
+    /// Decodes a published proof into the public key it was made for and the proof itself
+    pub fn decode(bytes: &[u8]) -> Result<(BBJJ_Ec, Self), String> {
+        if bytes.len() != POSSESSION_PROOF_LEN {
+            return Err(format!(
+                "A proof of possession is {} bytes long, got {} bytes",
+                POSSESSION_PROOF_LEN,
+                bytes.len()
+            ));
+        }
+        let words: Vec<&[u8]> = bytes.chunks(32).collect();
+
+        let public_key = BBJJ_Ec {
+            x: decode_field(words[0])?,
+            y: decode_field(words[1])?,
+        };
+        let proof = PossessionProof {
+            r_b8: BBJJ_Ec {
+                x: decode_field(words[2])?,
+                y: decode_field(words[3])?,
+            },
+            s: decode_field(words[4])?,
+        };
+
+        Ok((public_key, proof))
+    }
+}
+
+/// Checks the proof of possession published with the registration of `commitment` by `address`:
+/// that it opens the commitment to a valid public key and was signed with its private key for that registration
+pub fn verify_registration(
+    proof: &[u8],
+    commitment: U256,
+    address: Address,
+    chain_id: U256,
+    registry_address: Address,
+) -> Result<(), String> {
+    let (public_key, proof) = PossessionProof::decode(proof)?;
+    let key_commitment: U256 = wrap_into!(key_commitment(&public_key)?);
+    if key_commitment != commitment {
+        return Err(
+            "The proof of possession is for another key than the registered one".to_string(),
+        );
+    }
+
+    proof.verify(&public_key, address, chain_id, registry_address)
+}
+
+/// The commitment to a BabyJubJub public key registered in a ZKRegistry: `Poseidon(pk.x, pk.y)`
+pub fn key_commitment(public_key: &BBJJ_Ec) -> Result<BN254_Fr, String> {
+    Poseidon::new().hash(vec![public_key.x, public_key.y])
+}
+
+/// The message signed by a proof of possession: `Poseidon(address, chain_id, registry_address)`
+fn possession_message(
+    address: Address,
+    chain_id: U256,
+    registry_address: Address,
+) -> Result<BN254_Fr, String> {
+    let address: BN254_Fr = wrap_into!(address);
+    let chain_id: [BN254_Fr; 2] = wrap_into!(chain_id);
+    let registry_address: BN254_Fr = wrap_into!(registry_address);
+
+    Poseidon::new().hash(vec![address, chain_id[0], chain_id[1], registry_address])
+}
+
+/// Reads a field element from its 32 big-endian bytes, which must be below the modulus
+fn decode_field<F: PrimeField>(word: &[u8]) -> Result<F, String> {
+    let element = F::from_be_bytes_mod_order(word);
+    if element.into_bigint().to_bytes_be() != word {
+        return Err("The proof of possession holds a value beyond its field".to_string());
+    }
+
+    Ok(element)
+}
+
+/// Checks that `point` is on the BabyJubJub curve, in its prime order subgroup, and not the identity
+fn is_public_key(point: &BBJJ_Ec) -> bool {
+    // `a x^2 + y^2 = 1 + d x^2 y^2`
+    let a = BN254_Fr::from(168700u64);
+    let d = BN254_Fr::from(168696u64);
+    let (x2, y2) = (point.x.square(), point.y.square());
+    if a * x2 + y2 != BN254_Fr::from(1u64) + d * x2 * y2 {
+        return false;
+    }
+
+    // Multiplying by the cofactor and then by its inverse modulo the subgroup order
+    // only gives back the points of the subgroup
+    let cofactor = BBJJ_Fr::from(8u64);
+    let cofactor_inverse = match cofactor.inverse() {
+        Some(cofactor_inverse) => cofactor_inverse,
+        None => return false,
+    };
+    let identity = BBJJ_Ec {
+        x: BN254_Fr::from(0u64),
+        y: BN254_Fr::from(1u64),
+    };
+
+    !point.equals(identity)
+        && point
+            .mul_scalar(&cofactor)
+            .mul_scalar(&cofactor_inverse)
+            .equals(point.clone())
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::core::k256::U256;
+    use ethers::types::Address;
+
+    use crate::keys::random_private_key;
+    use crate::registry::{
+        is_public_key, key_commitment, verify_registration, PossessionProof, POSSESSION_PROOF_LEN,
+    };
+    use crate::utils::mock::Mock;
+    use crate::{wrap, wrap_into, BBJJ_Ec, BN254_Fr, Wrapper, BBJJ_G1};
+
+    #[test]
+    fn test_possession_proof() -> Result<(), String> {
+        let rng = &mut ark_std::test_rng();
+
+        let key = random_private_key(rng);
+        let public_key = key.public();
+        let (address, chain_id, registry_address) =
+            (Address::mock(rng), U256::from_u64(1), Address::mock(rng));
+
+        let proof = PossessionProof::new(&key, address, chain_id, registry_address)?;
+        proof.verify(&public_key, address, chain_id, registry_address)?;
+
+        // It only holds for its own key, address, chain and registry
+        let other_key = random_private_key(rng).public();
+        assert!(proof
+            .verify(&other_key, address, chain_id, registry_address)
+            .is_err());
+        assert!(proof
+            .verify(&public_key, Address::mock(rng), chain_id, registry_address)
+            .is_err());
+        assert!(proof
+            .verify(&public_key, address, U256::from_u64(2), registry_address)
+            .is_err());
+        assert!(proof
+            .verify(&public_key, address, chain_id, Address::mock(rng))
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_registration_proof() -> Result<(), String> {
+        let rng = &mut ark_std::test_rng();
+
+        let key = random_private_key(rng);
+        let public_key = key.public();
+        let commitment: U256 = wrap_into!(key_commitment(&public_key)?);
+        let (address, chain_id, registry_address) =
+            (Address::mock(rng), U256::from_u64(1), Address::mock(rng));
+
+        let proof =
+            PossessionProof::new(&key, address, chain_id, registry_address)?.encode(&public_key);
+        assert_eq!(proof.len(), POSSESSION_PROOF_LEN);
+        verify_registration(&proof, commitment, address, chain_id, registry_address)?;
+
+        // It does not hold for another commitment or registrant, nor once tampered with
+        let other_commitment: U256 = wrap_into!(key_commitment(&random_private_key(rng).public())?);
+        assert!(verify_registration(
+            &proof,
+            other_commitment,
+            address,
+            chain_id,
+            registry_address
+        )
+        .is_err());
+        assert!(verify_registration(
+            &proof,
+            commitment,
+            Address::mock(rng),
+            chain_id,
+            registry_address
+        )
+        .is_err());
+        let mut tampered = proof.clone();
+        tampered[POSSESSION_PROOF_LEN - 1] ^= 1;
+        assert!(
+            verify_registration(&tampered, commitment, address, chain_id, registry_address)
+                .is_err()
+        );
+
+        // Values beyond the field and truncated proofs are rejected
+        let mut beyond_field = proof.clone();
+        beyond_field[..32].fill(0xff);
+        assert!(PossessionProof::decode(&beyond_field).is_err());
+        assert!(PossessionProof::decode(&proof[1..]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_public_key_validity() {
+        assert!(is_public_key(&BBJJ_G1));
+        assert!(!is_public_key(&BBJJ_Ec {
+            x: BBJJ_G1.x,
+            y: BBJJ_G1.y + BN254_Fr::from(1u64),
+        }));
+
+        // The identity and the point of order 2 are on the curve but outside the subgroup
+        let identity = BBJJ_Ec {
+            x: BN254_Fr::from(0u64),
+            y: BN254_Fr::from(1u64),
+        };
+        assert!(!is_public_key(&identity));
+        assert!(!is_public_key(&BBJJ_Ec {
+            x: BN254_Fr::from(0u64),
+            y: -BN254_Fr::from(1u64),
+        }));
+    }
+}
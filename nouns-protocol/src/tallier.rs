@@ -4,9 +4,11 @@ use poseidon_ark::Poseidon;
 use strum::IntoEnumIterator;
 
 use crate::noir::TallyProverInput;
+use crate::voter::hash_vote;
 use crate::{noir, utils::VoteChoice, wrap, wrap_into, BBJJ_Ec, BBJJ_Fr, BN254_Fr, Wrapper};
 
 /// Results of the tally
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Tally {
     pub vote_count: [usize; 3],
 }
@@ -67,8 +69,43 @@ impl Tallier {
         Ok((tally, proof))
     }
 
+    /// Function that decrypts the votes and returns the [Tally] results without proving them,
+    /// e.g. to check a tally submitted by someone else
+    /// @param ballots: The truncated ballots of the voters who voted
+    /// @param tlcs_prk: The TLCS secret key for time T used to decrypt the votes
+    /// @param chain_id: The chain id of the blockchain
+    /// @param process_id: The process id of the process
+    /// @param contract_addr: The address of the contract
+    pub fn decrypt(
+        ballots: &[TruncatedBallot],
+        tlcs_prk: BBJJ_Fr,
+        chain_id: U256,
+        process_id: U256,
+        contract_addr: Address,
+    ) -> Result<Tally, String> {
+        let (_, tally) = Self::gen_tally_with_hints(
+            ballots,
+            &tlcs_prk,
+            wrap_into!(process_id),
+            wrap_into!(contract_addr),
+            wrap_into!(chain_id),
+        )?;
+
+        Ok(tally)
+    }
+
+    /// Function that computes the hash of the chain of ballots, as the NounsVoting contract does when they are cast
+    /// @param ballots: The truncated ballots in the order they were cast
+    pub fn ballots_hash(ballots: &[TruncatedBallot]) -> Result<BN254_Fr, String> {
+        let poseidon = Poseidon::new();
+
+        ballots.iter().try_fold(BN254_Fr::from(0), |acc, ballot| {
+            poseidon.hash(vec![acc, ballot.b])
+        })
+    }
+
     fn gen_tally_with_hints(
-        ballots: &Vec<TruncatedBallot>,
+        ballots: &[TruncatedBallot],
         tlcs_prk: &BBJJ_Fr,
         process_id: BN254_Fr,
         contract_addr: BN254_Fr,
@@ -83,15 +120,14 @@ impl Tallier {
             let k = ballot.a.mul_scalar(tlcs_prk);
 
             for vote_candidate in vote_options.clone() {
-                let candidate_b = poseidon.hash(vec![
-                    k.x,
-                    k.y,
+                let candidate_b = hash_vote(
+                    &poseidon,
+                    &k,
                     vote_candidate.into(),
-                    chain_id[0],
-                    chain_id[1],
                     process_id,
                     contract_addr,
-                ])?;
+                    chain_id,
+                )?;
 
                 if candidate_b == ballot.b {
                     return Ok(vote_candidate);
@@ -145,7 +181,6 @@ impl Tallier {
 mod test {
     use ethers::core::k256::U256;
     use ethers::prelude::Address;
-    use poseidon_ark::Poseidon;
     use rand::Rng;
 
     use crate::tallier::{Tallier, Tally, TruncatedBallot};
@@ -153,9 +188,17 @@ mod test {
     use crate::voter::Voter;
     use crate::{BBJJ_Ec, BN254_Fr, PrivateKey, BBJJ_G1};
 
-    fn gen_tally<R: Rng>(rng: &mut R, num_voters: usize) -> Result<(Tally, Vec<u8>), String> {
-        let poseidon = Poseidon::new();
+    /// Ballots cast by random voters along with the data needed to tally them
+    struct MockProcess {
+        ballots: Vec<TruncatedBallot>,
+        votes: Vec<VoteChoice>,
+        tlcs_prk: PrivateKey,
+        chain_id: U256,
+        process_id: U256,
+        contract_addr: Address,
+    }
 
+    fn gen_ballots<R: Rng>(rng: &mut R, num_voters: usize) -> MockProcess {
         let nft_id = (0..num_voters).map(|_| U256::mock(rng)).collect::<Vec<_>>();
         let process_id = U256::from(rng.gen_range(0..100u8));
         let contract_addr = Address::mock(rng);
@@ -171,7 +214,7 @@ mod test {
             .map(|_| VoteChoice::mock(rng))
             .collect::<Vec<_>>();
 
-        let ballot = std::iter::zip(voter, std::iter::zip(nft_id, v))
+        let ballot = std::iter::zip(voter, std::iter::zip(nft_id, v.clone()))
             .map(|(voter, (nft_id, v))| {
                 voter
                     .gen_ballot_with_hints(
@@ -196,23 +239,28 @@ mod test {
             })
             .collect::<Vec<_>>();
 
-        let b_k = truncated_ballot
-            .clone()
-            .into_iter()
-            .map(|tb| tb.b)
-            .fold(0.into(), |acc, x| {
-                poseidon
-                    .hash(vec![acc, x])
-                    .expect("Error computing Poseidon hash!")
-            });
-
-        Tallier::tally(
-            truncated_ballot,
-            tlcs_prk.scalar_key(),
-            b_k,
+        MockProcess {
+            ballots: truncated_ballot,
+            votes: v,
+            tlcs_prk,
             chain_id,
             process_id,
             contract_addr,
+        }
+    }
+
+    fn gen_tally<R: Rng>(rng: &mut R, num_voters: usize) -> Result<(Tally, Vec<u8>), String> {
+        let process = gen_ballots(rng, num_voters);
+
+        let b_k = Tallier::ballots_hash(&process.ballots)?;
+
+        Tallier::tally(
+            process.ballots,
+            process.tlcs_prk.scalar_key(),
+            b_k,
+            process.chain_id,
+            process.process_id,
+            process.contract_addr,
         )
     }
 
@@ -230,6 +278,52 @@ mod test {
         gen_tally(rng, 200).unwrap();
     }
 
+    #[test]
+    fn test_tally_decrypt() -> Result<(), String> {
+        let rng = &mut ark_std::test_rng();
+        let process = gen_ballots(rng, 10);
+
+        let tally = Tallier::decrypt(
+            &process.ballots,
+            process.tlcs_prk.scalar_key(),
+            process.chain_id,
+            process.process_id,
+            process.contract_addr,
+        )?;
+
+        let count = |choice: VoteChoice| process.votes.iter().filter(|&&v| v == choice).count();
+        assert_eq!(
+            tally,
+            Tally {
+                vote_count: [
+                    count(VoteChoice::No),
+                    count(VoteChoice::Yes),
+                    count(VoteChoice::Abstain)
+                ]
+            }
+        );
+
+        // Decrypting with another key fails
+        assert!(Tallier::decrypt(
+            &process.ballots,
+            PrivateKey::mock(rng).scalar_key(),
+            process.chain_id,
+            process.process_id,
+            process.contract_addr,
+        )
+        .is_err());
+
+        // The ballots hash depends on the order of the ballots
+        let mut reordered_ballots = process.ballots.clone();
+        reordered_ballots.swap(0, 1);
+        assert_ne!(
+            Tallier::ballots_hash(&process.ballots)?,
+            Tallier::ballots_hash(&reordered_ballots)?
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_tally_gen() -> Result<(), String> {
         let rng = &mut ark_std::test_rng();
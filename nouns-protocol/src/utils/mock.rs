@@ -3,9 +3,10 @@ use babyjubjub_ark::PrivateKey;
 use ethers::core::k256::elliptic_curve::bigint::Encoding;
 use ethers::core::k256::U256;
 use ethers::prelude::StorageProof;
-use ethers::types::{Address, H256};
+use ethers::types::Address;
 use rand::Rng;
 
+use crate::keys::SecretKey;
 use crate::voter::Voter;
 use crate::{BBJJ_Ec, BBJJ_Fr, BN254_Fr, VoteChoice, BBJJ_G1};
 
@@ -19,7 +20,7 @@ impl Mock for Voter {
         // Generate a random Vec of bytes length 32
         Voter::new(
             Address::mock(rng),
-            PrivateKey::import(Vec::from(U256::mock(rng).to_be_bytes())).unwrap(),
+            SecretKey::import(&U256::mock(rng).to_be_bytes()).unwrap(),
         )
     }
 }
@@ -38,7 +39,7 @@ impl Mock for VoteChoice {
 impl Mock for StorageProof {
     fn mock<R: Rng>(rng: &mut R) -> Self {
         StorageProof {
-            key: H256::from(rng.gen::<[u8; 32]>()),
+            key: ethers::types::U256::from(rng.gen::<[u8; 32]>()),
             value: ethers::types::U256::zero(),
             proof: vec![],
         }
@@ -5,7 +5,9 @@ use babyjubjub_ark::Signature;
 use ethers::core::k256::U256;
 use ethers::prelude::{Address, StorageProof};
 use poseidon_ark::Poseidon;
+use zeroize::{Zeroize, Zeroizing};
 
+use crate::keys::SecretKey;
 use crate::utils::VoteChoice;
 use crate::{noir, wrap, wrap_into, BBJJ_Ec, BBJJ_Fr, BN254_Fr, Wrapper, BBJJ_G1};
 
@@ -19,11 +21,11 @@ pub struct Voter {
     eth_addr: Address,
     /// Secret Key (`sk`) of the Public Key (`pbk`) that is registered under the `address` in zkRegistry
     /// As we are using BBJJ this key should be registered in the `BBJJ` interface.
-    registered_sk: babyjubjub_ark::PrivateKey,
+    registered_sk: SecretKey,
 }
 
 impl Voter {
-    pub fn new(address: Address, rck: babyjubjub_ark::PrivateKey) -> Self {
+    pub fn new(address: Address, rck: SecretKey) -> Self {
         Voter {
             eth_addr: address,
             registered_sk: rck,
@@ -42,8 +44,59 @@ pub struct Ballot {
     pub n: BN254_Fr,
 }
 
+/// Represents the receipt of a cast ballot, which lets the voter check later on that the ballot
+/// was included and counted as cast.
+/// Its blinding factor is wiped from memory when dropped.
+pub struct VoteReceipt {
+    pub process_id: U256,
+    /// The nullifier of the ballot
+    pub nullifier: BN254_Fr,
+    /// The first part of the encrypted vote
+    pub a: BBJJ_Ec,
+    /// The second part of the encrypted vote
+    pub b: BN254_Fr,
+    pub vote_choice: VoteChoice,
+    /// The blinding factor `r` of the ballot
+    /// Anyone who knows it can decrypt the vote, so the receipt has to be kept secret
+    pub blinding_factor: BBJJ_Fr,
+}
+
+/// Represents a ballot that is encrypted but not proven nor cast yet.
+/// The voter can either cast it or spoil it, which reveals its blinding factor,
+/// to check that it encrypts the chosen vote (Benaloh challenge)
+pub struct PreparedBallot {
+    ballot: Ballot,
+    hints: BallotHints,
+    nft_id: U256,
+    vote_choice: VoteChoice,
+    process_id: U256,
+    contract_addr: Address,
+    chain_id: U256,
+    tlcs_pk: BBJJ_Ec,
+}
+
+/// Represents a ballot that was spoiled instead of cast.
+/// It reveals the blinding factor, so anyone can check which vote choice it encrypts.
+#[derive(Clone, Debug)]
+pub struct SpoiledBallot {
+    pub process_id: U256,
+    pub contract_addr: Address,
+    pub chain_id: U256,
+    /// The TLCS public key the ballot was encrypted to
+    pub tlcs_pk: BBJJ_Ec,
+    /// The first part of the encrypted vote
+    pub a: BBJJ_Ec,
+    /// The second part of the encrypted vote
+    pub b: BN254_Fr,
+    /// The vote choice the ballot claims to encrypt
+    pub vote_choice: VoteChoice,
+    /// The blinding factor `r` of the ballot
+    pub blinding_factor: BBJJ_Fr,
+}
+
 /// Represents the hints that were generated while constructing the ballot
-/// that the prover needs to generate the proof for ballot correctness
+/// that the prover needs to generate the proof for ballot correctness.
+/// Its blinding factor is wiped from memory when dropped.
 pub(crate) struct BallotHints {
     /// `sigma` representing the signature over the id of the vote
     signed_id: Signature,
@@ -70,25 +123,80 @@ impl Voter {
         registry_account_state: U256,
         storage_proofs: (StorageProof, StorageProof, StorageProof),
         rng: &mut R,
-    ) -> Result<(Ballot, Vec<u8>), String> {
+    ) -> Result<(Ballot, VoteReceipt, Vec<u8>), String> {
+        let prepared_ballot =
+            self.prepare_ballot(nft_id, v, process_id, contract_addr, chain_id, tlcs_pk, rng)?;
+
+        self.prove_ballot(
+            prepared_ballot,
+            nft_account_state,
+            registry_account_state,
+            storage_proofs,
+        )
+    }
+
+    /// Encrypt a vote for given parameters without proving it yet,
+    /// so that the voter can spoil the ballot to check its encryption
+    pub fn prepare_ballot<R: Rng>(
+        &self,
+        nft_id: U256,
+        v: VoteChoice,
+        process_id: U256,
+        contract_addr: Address,
+        chain_id: U256,
+        tlcs_pk: BBJJ_Ec,
+        rng: &mut R,
+    ) -> Result<PreparedBallot, String> {
+        let (ballot, hints) = self.gen_ballot_with_hints(
+            wrap_into!(nft_id),
+            v.into(),
+            wrap_into!(process_id),
+            wrap_into!(contract_addr),
+            wrap_into!(chain_id),
+            tlcs_pk.clone(),
+            rng,
+        )?;
+
+        Ok(PreparedBallot {
+            ballot,
+            hints,
+            nft_id,
+            vote_choice: v,
+            process_id,
+            contract_addr,
+            chain_id,
+            tlcs_pk,
+        })
+    }
+
+    /// Generate the proof of correctness of a prepared ballot
+    pub fn prove_ballot(
+        &self,
+        prepared_ballot: PreparedBallot,
+        nft_account_state: U256,
+        registry_account_state: U256,
+        storage_proofs: (StorageProof, StorageProof, StorageProof),
+    ) -> Result<(Ballot, VoteReceipt, Vec<u8>), String> {
+        let receipt = prepared_ballot.receipt();
+        let PreparedBallot {
+            ballot,
+            hints: ballot_hints,
+            nft_id,
+            vote_choice,
+            process_id,
+            contract_addr,
+            chain_id,
+            tlcs_pk,
+        } = prepared_ballot;
+
         // Convert the parameters to the correct field
         let nft_id: [BN254_Fr; 2] = wrap_into!(nft_id);
         let process_id: BN254_Fr = wrap_into!(process_id);
         let contract_addr: BN254_Fr = wrap_into!(contract_addr);
         let chain_id: [BN254_Fr; 2] = wrap_into!(chain_id);
-        let v = v.into();
+        let v = vote_choice.into();
 
         // Prepare the inputs for the Noir circuit vote prover circuit
-        let (ballot, ballot_hints) = self.gen_ballot_with_hints(
-            nft_id,
-            v,
-            process_id,
-            contract_addr,
-            chain_id,
-            tlcs_pk.clone(),
-            rng,
-        )?;
-
         let noir_input = noir::VoteProverInput {
             // Public inputs
             a: ballot.a.clone(),
@@ -99,25 +207,25 @@ impl Voter {
             chain_id,
             registry_account_state: Wrapper(registry_account_state).into(),
             nft_account_state: Wrapper(nft_account_state).into(),
-            tlcs_pk: tlcs_pk.clone(),
+            tlcs_pk,
             // Private inputs
             v,
             blinding_factor: ballot_hints.blinding_factor,
-            signed_id: ballot_hints.signed_id,
+            signed_id: ballot_hints.signed_id.clone(),
             voter_address: Wrapper(self.eth_addr).into(),
-            signed_v: ballot_hints.signed_v,
+            signed_v: ballot_hints.signed_v.clone(),
             nft_id,
             h_id: ballot_hints.id_hash,
-            k: ballot_hints.k,
+            k: ballot_hints.k.clone(),
             registered_pbk: self.registered_sk.public(),
-            registry_key_sp: storage_proofs.1,
+            registry_commitment_sp: storage_proofs.1,
             nft_ownership_proof: storage_proofs.0,
             delegation_proof: storage_proofs.2,
         };
 
         let proof = noir::prove_vote(noir_input)?;
 
-        Ok((ballot, proof))
+        Ok((ballot, receipt, proof))
     }
 
     /// Generate a vote ballot with prover hints for given vote parameters
@@ -158,7 +266,7 @@ impl Voter {
 
         // Generate a random value r that will be used to generate A and B
         // It is important to keep this value secret as it is used to keep the vote choice secret until the reveal phase
-        let blinding_factor = BBJJ_Fr::rand(rng);
+        let blinding_factor = Zeroizing::new(BBJJ_Fr::rand(rng));
         // Generate A as a point on the curve corresponding to the random value r
         let a: BBJJ_Ec = BBJJ_G1.mul_scalar(&blinding_factor); // `A = g^r in multiplicative notation`
 
@@ -167,15 +275,7 @@ impl Voter {
 
         // Generate B as a hash of the point K, the vote choice and the id of the vote
         // Note that the id of the vote is public, so the moment `k` is revealed, the vote choice can be bruteforced
-        let b = poseidon.hash(vec![
-            k.x,
-            k.y,
-            v,
-            chain_id[0],
-            chain_id[1],
-            process_id,
-            contract_addr,
-        ])?; // `B = Poseidon(K_i, vote_choice, election_params.identifier)`
+        let b = hash_vote(&poseidon, &k, v, process_id, contract_addr, chain_id)?; // `B = Poseidon(K_i, vote_choice, election_params.identifier)`
 
         return Ok((
             Ballot {
@@ -188,12 +288,153 @@ impl Voter {
                 id_hash,
                 signed_v,
                 k: k.clone(),
-                blinding_factor,
+                blinding_factor: *blinding_factor,
             },
         ));
     }
 }
 
+impl VoteReceipt {
+    /// Checks that the ballot of the receipt encrypts its vote choice to the given TLCS public key,
+    /// i.e. that the vote was cast as intended
+    pub fn verify(
+        &self,
+        tlcs_pk: &BBJJ_Ec,
+        contract_addr: Address,
+        chain_id: U256,
+    ) -> Result<bool, String> {
+        let (a, b) = encrypt_vote(
+            tlcs_pk,
+            &self.blinding_factor,
+            self.vote_choice,
+            self.process_id,
+            contract_addr,
+            chain_id,
+        )?;
+
+        Ok(a == self.a && b == self.b)
+    }
+}
+
+impl Drop for VoteReceipt {
+    fn drop(&mut self) {
+        self.blinding_factor.zeroize();
+    }
+}
+
+impl Drop for BallotHints {
+    fn drop(&mut self) {
+        self.blinding_factor.zeroize();
+    }
+}
+
+impl PreparedBallot {
+    pub fn ballot(&self) -> &Ballot {
+        &self.ballot
+    }
+
+    /// The commitment to the encrypted vote shown to the voter before casting or spoiling the ballot
+    pub fn commitment(&self) -> Result<BN254_Fr, String> {
+        ballot_commitment(&self.ballot.a, self.ballot.b)
+    }
+
+    /// The receipt of the ballot, once cast
+    pub fn receipt(&self) -> VoteReceipt {
+        VoteReceipt {
+            process_id: self.process_id,
+            nullifier: self.ballot.n,
+            a: self.ballot.a.clone(),
+            b: self.ballot.b,
+            vote_choice: self.vote_choice,
+            blinding_factor: self.hints.blinding_factor,
+        }
+    }
+
+    /// Spoil the ballot, revealing its blinding factor. A spoiled ballot must never be cast.
+    pub fn spoil(self) -> SpoiledBallot {
+        SpoiledBallot {
+            process_id: self.process_id,
+            contract_addr: self.contract_addr,
+            chain_id: self.chain_id,
+            tlcs_pk: self.tlcs_pk,
+            a: self.ballot.a,
+            b: self.ballot.b,
+            vote_choice: self.vote_choice,
+            blinding_factor: self.hints.blinding_factor,
+        }
+    }
+}
+
+impl SpoiledBallot {
+    /// The commitment to the encrypted vote, to be compared with the one shown before spoiling
+    pub fn commitment(&self) -> Result<BN254_Fr, String> {
+        ballot_commitment(&self.a, self.b)
+    }
+
+    /// Checks that the ballot encrypts its claimed vote choice by computing `K = tlcs_pk^r` again
+    pub fn verify(&self) -> Result<bool, String> {
+        let (a, b) = encrypt_vote(
+            &self.tlcs_pk,
+            &self.blinding_factor,
+            self.vote_choice,
+            self.process_id,
+            self.contract_addr,
+            self.chain_id,
+        )?;
+
+        Ok(a == self.a && b == self.b)
+    }
+}
+
+/// Encrypts a vote choice with the given blinding factor, giving the parts `A` and `B` of a ballot
+fn encrypt_vote(
+    tlcs_pk: &BBJJ_Ec,
+    blinding_factor: &BBJJ_Fr,
+    v: VoteChoice,
+    process_id: U256,
+    contract_addr: Address,
+    chain_id: U256,
+) -> Result<(BBJJ_Ec, BN254_Fr), String> {
+    let a = BBJJ_G1.mul_scalar(blinding_factor);
+    let k = tlcs_pk.mul_scalar(blinding_factor);
+    let b = hash_vote(
+        &Poseidon::new(),
+        &k,
+        v.into(),
+        wrap_into!(process_id),
+        wrap_into!(contract_addr),
+        wrap_into!(chain_id),
+    )?;
+
+    Ok((a, b))
+}
+
+/// Hashes both parts of an encrypted vote into a single commitment
+fn ballot_commitment(a: &BBJJ_Ec, b: BN254_Fr) -> Result<BN254_Fr, String> {
+    Poseidon::new().hash(vec![a.x, a.y, b])
+}
+
+/// Hashes a vote choice with the shared key `K` of the ballot and the id of the voting process,
+/// giving the second part `B` of the encrypted vote
+pub(crate) fn hash_vote(
+    poseidon: &Poseidon,
+    k: &BBJJ_Ec,
+    v: BN254_Fr,
+    process_id: BN254_Fr,
+    contract_addr: BN254_Fr,
+    chain_id: [BN254_Fr; 2],
+) -> Result<BN254_Fr, String> {
+    poseidon.hash(vec![
+        k.x,
+        k.y,
+        v,
+        chain_id[0],
+        chain_id[1],
+        process_id,
+        contract_addr,
+    ])
+}
+
 #[cfg(test)]
 mod test {
     use ethers::core::k256::U256;
@@ -202,7 +443,7 @@ mod test {
 
     use crate::utils::mock::Mock;
     use crate::utils::VoteChoice;
-    use crate::voter::Voter;
+    use crate::voter::{SpoiledBallot, VoteReceipt, Voter};
     use crate::BBJJ_Ec;
 
     #[test]
@@ -211,13 +452,17 @@ mod test {
 
         let voter = Voter::mock(rng);
 
-        let (ballot, proof) = voter.gen_vote(
+        let contract_addr = Address::mock(rng);
+        let chain_id = U256::mock(rng);
+        let tlcs_pk = BBJJ_Ec::mock(rng);
+
+        let (ballot, receipt, proof) = voter.gen_vote(
             U256::from_u64(1),
             VoteChoice::mock(rng),
             U256::from(rng.gen_range(0..100u8)),
-            Address::mock(rng),
-            U256::mock(rng),
-            BBJJ_Ec::mock(rng),
+            contract_addr,
+            chain_id,
+            tlcs_pk.clone(),
             U256::mock(rng),
             U256::mock(rng),
             (
@@ -231,6 +476,62 @@ mod test {
         println!("Ballot: {:?}", ballot);
         println!("Proof: {:?}", proof);
 
+        // The receipt matches the ballot only for its own vote choice
+        assert_eq!(receipt.nullifier, ballot.n);
+        assert!(receipt.verify(&tlcs_pk, contract_addr, chain_id)?);
+        let other_choice = match receipt.vote_choice {
+            VoteChoice::Yes => VoteChoice::No,
+            _ => VoteChoice::Yes,
+        };
+        let forged_receipt = VoteReceipt {
+            process_id: receipt.process_id,
+            nullifier: receipt.nullifier,
+            a: receipt.a.clone(),
+            b: receipt.b,
+            vote_choice: other_choice,
+            blinding_factor: receipt.blinding_factor,
+        };
+        assert!(!forged_receipt.verify(&tlcs_pk, contract_addr, chain_id)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_spoiled_ballot() -> Result<(), String> {
+        let rng = &mut ark_std::test_rng();
+
+        let voter = Voter::mock(rng);
+        let tlcs_pk = BBJJ_Ec::mock(rng);
+
+        let prepared_ballot = voter.prepare_ballot(
+            U256::from_u64(1),
+            VoteChoice::Abstain,
+            U256::from_u64(3),
+            Address::mock(rng),
+            U256::mock(rng),
+            tlcs_pk,
+            rng,
+        )?;
+        let commitment = prepared_ballot.commitment()?;
+
+        let spoiled_ballot = prepared_ballot.spoil();
+        assert_eq!(spoiled_ballot.commitment()?, commitment);
+        assert!(spoiled_ballot.verify()?);
+
+        // A ballot does not verify for any other vote choice or under any other key
+        for (vote_choice, tlcs_pk) in [
+            (VoteChoice::Yes, spoiled_ballot.tlcs_pk.clone()),
+            (VoteChoice::No, spoiled_ballot.tlcs_pk.clone()),
+            (VoteChoice::Abstain, BBJJ_Ec::mock(rng)),
+        ] {
+            let forged_ballot = SpoiledBallot {
+                vote_choice,
+                tlcs_pk,
+                ..spoiled_ballot.clone()
+            };
+            assert!(!forged_ballot.verify()?);
+        }
+
         Ok(())
     }
 }
@@ -1,6 +1,6 @@
 #!/bin/bash
-circuit_dirs=("hash_proof" "client-proof" "tally")
-circuits=("Hash" "Vote" "Tally")
+circuit_dirs=("hash_proof" "header_chain" "client-proof" "tally")
+circuits=("Hash" "HeaderChain" "Vote" "Tally")
 HASH_CONTRACT_PATH="circuits/hash_proof/contract"
 
 generate_contract() {